@@ -6,8 +6,6 @@ pub struct Attributes(u8);
 
 impl Attributes {
     /// The filesystem will not allow a file to be opened for modification.
-    // TODO: Follow this behaviour.
-    // BODY: If a read only attribute is encounter, we should act accordingly.
     pub const READ_ONLY: u8 = 0x01;
 
     /// Hides files or directories from normal directory views.
@@ -31,6 +29,9 @@ impl Attributes {
     /// Indicates a long file name entry.
     pub const LFN: u8 = Self::READ_ONLY | Self::HIDDEN | Self::SYSTEM | Self::VOLUME;
 
+    /// The attributes that can be changed by the user.
+    pub const MODIFIABLE: u8 = Self::READ_ONLY | Self::HIDDEN | Self::SYSTEM | Self::ARCHIVE;
+
     /// Create a new Attributes from a raw u8 value.
     pub fn new(value: u8) -> Attributes {
         Attributes(value)
@@ -80,4 +81,14 @@ impl Attributes {
     pub fn get_value(self) -> u8 {
         self.0
     }
+
+    /// Return a copy of the attributes with the given flags set.
+    pub fn set(self, flags: u8) -> Attributes {
+        Attributes(self.0 | flags)
+    }
+
+    /// Return a copy of the attributes with the given flags cleared.
+    pub fn clear(self, flags: u8) -> Attributes {
+        Attributes(self.0 & !flags)
+    }
 }
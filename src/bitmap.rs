@@ -0,0 +1,84 @@
+//! In-memory bitmap of the used clusters.
+
+use super::Cluster;
+use core::convert::TryFrom;
+
+/// A bitmap keeping track of the used clusters to avoid reading the FAT when allocating clusters.
+///
+/// Each bit represents one cluster, starting at the cluster 0. A bit is set when the cluster isn't free.
+/// The bitmap may be too small to cover every cluster of the filesystem, the FAT must then be read for the clusters it doesn't cover.
+pub(crate) struct AllocationBitmap<'m> {
+    /// The bits of the bitmap.
+    bits: &'m mut [u8],
+}
+
+impl<'m> AllocationBitmap<'m> {
+    /// Create an AllocationBitmap not covering any cluster.
+    pub fn empty() -> Self {
+        AllocationBitmap { bits: &mut [] }
+    }
+
+    /// Create an AllocationBitmap using the given memory, with all clusters marked as used.
+    pub fn new(bits: &'m mut [u8]) -> Self {
+        for byte in bits.iter_mut() {
+            *byte = 0xFF;
+        }
+
+        AllocationBitmap { bits }
+    }
+
+    /// Give back the memory used by the bitmap.
+    pub fn into_inner(self) -> &'m mut [u8] {
+        self.bits
+    }
+
+    /// Get the count of clusters covered by the bitmap.
+    pub fn cluster_count(&self) -> u32 {
+        u32::try_from(self.bits.len() * 8).unwrap_or(u32::MAX)
+    }
+
+    /// Return true if the given cluster is used or None if the bitmap doesn't cover it.
+    pub fn is_used(&self, cluster: Cluster) -> Option<bool> {
+        let byte = self.bits.get(cluster.0 as usize / 8)?;
+
+        Some(byte & (1 << (cluster.0 % 8)) != 0)
+    }
+
+    /// Mark the given cluster as used or free. Does nothing if the bitmap doesn't cover it.
+    pub fn set_used(&mut self, cluster: Cluster, used: bool) {
+        if let Some(byte) = self.bits.get_mut(cluster.0 as usize / 8) {
+            let mask = 1 << (cluster.0 % 8);
+
+            if used {
+                *byte |= mask;
+            } else {
+                *byte &= !mask;
+            }
+        }
+    }
+
+    /// Find the first free cluster in the given range of clusters covered by the bitmap.
+    ///
+    /// Whole bytes of used clusters are skipped without looking at each bit.
+    pub fn find_free(&self, start: u32, end: u32) -> Option<Cluster> {
+        let end = core::cmp::min(end as usize, self.bits.len() * 8);
+        let mut current = start as usize;
+
+        while current < end {
+            let byte = self.bits[current / 8];
+
+            if byte == 0xFF {
+                current = (current / 8 + 1) * 8;
+                continue;
+            }
+
+            if byte & (1 << (current % 8)) == 0 {
+                return Some(Cluster(current as u32));
+            }
+
+            current += 1;
+        }
+
+        None
+    }
+}
@@ -0,0 +1,280 @@
+//! Write-back block cache between the filesystem and the storage device.
+
+use storage_device::{StorageDevice, StorageDeviceResult};
+
+/// The state of a block of the storage device kept in memory.
+///
+/// An array of cache blocks is given to `FatFileSystem::set_cache` with the memory holding their content.
+#[derive(Clone, Copy)]
+pub struct CacheBlock {
+    /// The index of the block on the storage device. None if the cache block is unused.
+    index: Option<u64>,
+
+    /// True if the content of the block wasn't written back to the storage device.
+    dirty: bool,
+
+    /// The value of the cache clock when the block was last accessed.
+    last_access: u64,
+}
+
+impl CacheBlock {
+    /// An unused cache block.
+    pub const EMPTY: CacheBlock = CacheBlock {
+        index: None,
+        dirty: false,
+        last_access: 0,
+    };
+}
+
+impl Default for CacheBlock {
+    fn default() -> Self {
+        CacheBlock::EMPTY
+    }
+}
+
+/// A storage device keeping the least recently used blocks of another storage device in memory.
+///
+/// Without cache blocks, every operation is forwarded to the storage device.
+///
+/// # Note:
+///
+/// Requests of more than one block are forwarded to the storage device for the blocks that aren't cached.
+/// This avoids evicting the FAT and directory blocks when reading or writing file contents.
+pub(crate) struct BlockCache<'m, S: StorageDevice> {
+    /// The cached storage device.
+    storage_device: S,
+
+    /// The blocks of the cache.
+    blocks: &'m mut [CacheBlock],
+
+    /// The content of the blocks of the cache, `block_size` bytes per block.
+    memory: &'m mut [u8],
+
+    /// The size of a cached block, the logical block size of the filesystem.
+    block_size: usize,
+
+    /// Incremented at every block access, used to find the least recently used block.
+    clock: u64,
+}
+
+impl<'m, S: StorageDevice> BlockCache<'m, S> {
+    /// Create a new BlockCache caching blocks of the given size, without any cache block.
+    pub fn new(storage_device: S, block_size: usize) -> Self {
+        BlockCache {
+            storage_device,
+            blocks: &mut [],
+            memory: &mut [],
+            block_size,
+            clock: 0,
+        }
+    }
+
+    /// Write back the dirty blocks and use the given cache blocks, holding their content in the given memory.
+    ///
+    /// Only as many cache blocks as the memory can hold are used.
+    pub fn set_blocks(
+        &mut self,
+        blocks: &'m mut [CacheBlock],
+        memory: &'m mut [u8],
+    ) -> StorageDeviceResult<()> {
+        self.flush()?;
+
+        let count = core::cmp::min(blocks.len(), memory.len() / self.block_size);
+        let blocks = &mut blocks[..count];
+        for block in blocks.iter_mut() {
+            *block = CacheBlock::EMPTY;
+        }
+
+        self.blocks = blocks;
+        self.memory = &mut memory[..count * self.block_size];
+        self.clock = 0;
+
+        Ok(())
+    }
+
+    /// Write back all the dirty blocks to the storage device.
+    pub fn flush(&mut self) -> StorageDeviceResult<()> {
+        let block_size = self.block_size;
+
+        for (block, data) in self
+            .blocks
+            .iter_mut()
+            .zip(self.memory.chunks_exact(block_size))
+        {
+            if let (true, Some(index)) = (block.dirty, block.index) {
+                self.storage_device.write(index * block_size as u64, data)?;
+                block.dirty = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the position in the cache of the block at the given index.
+    fn find(&self, index: u64) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| block.index == Some(index))
+    }
+
+    /// Get the content of the cache block at the given position.
+    fn data(&mut self, position: usize) -> &mut [u8] {
+        let start = position * self.block_size;
+        &mut self.memory[start..start + self.block_size]
+    }
+
+    /// Mark the cache block at the given position as the most recently used.
+    fn touch(&mut self, position: usize) {
+        self.clock += 1;
+        self.blocks[position].last_access = self.clock;
+    }
+
+    /// Load the block at the given index in the cache, evicting the least recently used block.
+    ///
+    /// If fill is false, the content of the block isn't read because it's about to be overwritten.
+    fn load(&mut self, index: u64, fill: bool) -> StorageDeviceResult<usize> {
+        let mut position = 0;
+        for (current_position, block) in self.blocks.iter().enumerate() {
+            if block.index.is_none() {
+                position = current_position;
+                break;
+            }
+
+            if block.last_access < self.blocks[position].last_access {
+                position = current_position;
+            }
+        }
+
+        let block_size = self.block_size;
+        let block = &mut self.blocks[position];
+        let data = &mut self.memory[position * block_size..(position + 1) * block_size];
+
+        if let (true, Some(evicted_index)) = (block.dirty, block.index) {
+            self.storage_device
+                .write(evicted_index * block_size as u64, data)?;
+        }
+
+        block.index = None;
+        block.dirty = false;
+
+        if fill {
+            self.storage_device.read(index * block_size as u64, data)?;
+        }
+
+        block.index = Some(index);
+
+        Ok(position)
+    }
+}
+
+impl<'m, S: StorageDevice> StorageDevice for BlockCache<'m, S> {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> StorageDeviceResult<()> {
+        if self.blocks.is_empty() {
+            return self.storage_device.read(offset, buf);
+        }
+
+        let block_size = self.block_size;
+        let bypass = buf.len() > block_size;
+
+        // Start of the uncached blocks not yet read from the storage device.
+        let mut uncached_start = None;
+        let mut buf_offset = 0;
+
+        while buf_offset < buf.len() {
+            let device_offset = offset + buf_offset as u64;
+            let index = device_offset / block_size as u64;
+            let block_offset = (device_offset % block_size as u64) as usize;
+            let size = core::cmp::min(block_size - block_offset, buf.len() - buf_offset);
+
+            let position = self.find(index);
+
+            if position.is_none() && bypass && size == block_size {
+                uncached_start.get_or_insert(buf_offset);
+            } else {
+                if let Some(start) = uncached_start.take() {
+                    self.storage_device
+                        .read(offset + start as u64, &mut buf[start..buf_offset])?;
+                }
+
+                let position = match position {
+                    Some(position) => position,
+                    None => self.load(index, true)?,
+                };
+
+                self.touch(position);
+                buf[buf_offset..buf_offset + size]
+                    .copy_from_slice(&self.data(position)[block_offset..block_offset + size]);
+            }
+
+            buf_offset += size;
+        }
+
+        if let Some(start) = uncached_start {
+            self.storage_device
+                .read(offset + start as u64, &mut buf[start..])?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> StorageDeviceResult<()> {
+        if self.blocks.is_empty() {
+            return self.storage_device.write(offset, buf);
+        }
+
+        let block_size = self.block_size;
+        let bypass = buf.len() > block_size;
+
+        // Start of the uncached blocks not yet written to the storage device.
+        let mut uncached_start = None;
+        let mut buf_offset = 0;
+
+        while buf_offset < buf.len() {
+            let device_offset = offset + buf_offset as u64;
+            let index = device_offset / block_size as u64;
+            let block_offset = (device_offset % block_size as u64) as usize;
+            let size = core::cmp::min(block_size - block_offset, buf.len() - buf_offset);
+
+            let position = self.find(index);
+
+            if position.is_none() && bypass && size == block_size {
+                uncached_start.get_or_insert(buf_offset);
+            } else {
+                if let Some(start) = uncached_start.take() {
+                    self.storage_device
+                        .write(offset + start as u64, &buf[start..buf_offset])?;
+                }
+
+                let position = match position {
+                    Some(position) => position,
+                    None => self.load(index, size != block_size)?,
+                };
+
+                self.touch(position);
+                self.data(position)[block_offset..block_offset + size]
+                    .copy_from_slice(&buf[buf_offset..buf_offset + size]);
+                self.blocks[position].dirty = true;
+            }
+
+            buf_offset += size;
+        }
+
+        if let Some(start) = uncached_start {
+            self.storage_device
+                .write(offset + start as u64, &buf[start..])?;
+        }
+
+        Ok(())
+    }
+
+    fn len(&mut self) -> StorageDeviceResult<u64> {
+        self.storage_device.len()
+    }
+}
+
+impl<'m, S: StorageDevice> Drop for BlockCache<'m, S> {
+    fn drop(&mut self) {
+        // Errors can't be reported here, FatFileSystem::flush should be used to handle them.
+        let _ = self.flush();
+    }
+}
@@ -0,0 +1,190 @@
+//! Cache of the cluster chain positions of a file.
+
+use super::Cluster;
+
+/// A run of physically contiguous clusters of a file.
+///
+/// An array of extents is given to `File::set_extent_map` to keep the layout of the file in memory.
+#[derive(Clone, Copy)]
+pub struct FileExtent {
+    /// The index in the file of the first cluster of the extent.
+    file_cluster: u32,
+
+    /// The first cluster of the extent.
+    cluster: Cluster,
+
+    /// The count of clusters of the extent.
+    count: u32,
+}
+
+impl FileExtent {
+    /// An unused extent.
+    pub const EMPTY: FileExtent = FileExtent {
+        file_cluster: 0,
+        cluster: Cluster(0),
+        count: 0,
+    };
+
+    /// Get the index in the file following the last cluster of the extent.
+    fn end(&self) -> u32 {
+        self.file_cluster + self.count
+    }
+
+    /// Get the last cluster of the extent with its index in the file.
+    fn last(&self) -> (u32, Cluster) {
+        (self.end() - 1, Cluster(self.cluster.0 + self.count - 1))
+    }
+}
+
+impl Default for FileExtent {
+    fn default() -> Self {
+        FileExtent::EMPTY
+    }
+}
+
+/// Remembers where clusters of a file are to avoid following its cluster chain from the start.
+///
+/// The last cluster accessed is always kept, the extents of the start of the chain are also kept if an extent map is given.
+/// The extent map is filled as the chain is followed, once full only the last cluster accessed is kept for the rest of the chain.
+pub(crate) struct ChainCursor<'m> {
+    /// The index in the file and the cluster of the last cluster accessed.
+    position: Option<(u32, Cluster)>,
+
+    /// The extents of the start of the cluster chain, in file order.
+    extents: &'m mut [FileExtent],
+
+    /// The count of extents in use.
+    extent_count: usize,
+
+    /// The generation of the cluster chains of the filesystem when the known clusters were recorded.
+    generation: Option<u32>,
+}
+
+impl<'m> ChainCursor<'m> {
+    /// Create a ChainCursor without any known cluster and without extent map.
+    pub fn new() -> Self {
+        ChainCursor {
+            position: None,
+            extents: &mut [],
+            extent_count: 0,
+            generation: None,
+        }
+    }
+
+    /// Use the given memory as extent map and give back the previous one if any.
+    pub fn set_extents(&mut self, extents: &'m mut [FileExtent]) -> Option<&'m mut [FileExtent]> {
+        let previous_extents = core::mem::replace(&mut self.extents, extents);
+        self.extent_count = 0;
+
+        if previous_extents.is_empty() {
+            None
+        } else {
+            Some(previous_extents)
+        }
+    }
+
+    /// Get the extents in use.
+    fn used_extents(&self) -> &[FileExtent] {
+        &self.extents[..self.extent_count]
+    }
+
+    /// Check if the known clusters were recorded at another generation of the cluster chains.
+    pub fn is_stale(&self, generation: u32) -> bool {
+        self.generation != Some(generation)
+    }
+
+    /// Mark the known clusters as valid for the given generation of the cluster chains.
+    pub fn set_generation(&mut self, generation: u32) {
+        self.generation = Some(generation);
+    }
+
+    /// Forget every known cluster, used when the start of the chain changes.
+    pub fn reset(&mut self) {
+        self.position = None;
+        self.extent_count = 0;
+    }
+
+    /// Forget the clusters after the given count of clusters, used when the end of the chain is freed.
+    pub fn truncate(&mut self, cluster_count: u32) {
+        if let Some((index, _)) = self.position {
+            if index >= cluster_count {
+                self.position = None;
+            }
+        }
+
+        while let Some(extent) = self.extents[..self.extent_count].last_mut() {
+            if extent.file_cluster >= cluster_count {
+                self.extent_count -= 1;
+            } else {
+                extent.count = core::cmp::min(extent.count, cluster_count - extent.file_cluster);
+                break;
+            }
+        }
+    }
+
+    /// Find the known cluster the closest to the given index in the file, without going after it.
+    ///
+    /// Return the cluster with its index in the file or None if no cluster before the index is known.
+    pub fn closest(&self, index: u32) -> Option<(u32, Cluster)> {
+        let mut closest = None;
+
+        if let Some(last_extent) = self.used_extents().last() {
+            if index < last_extent.end() {
+                // The extents cover the start of the chain, the first one starts at the index 0.
+                let position = match self
+                    .used_extents()
+                    .binary_search_by(|extent| extent.file_cluster.cmp(&index))
+                {
+                    Ok(position) => position,
+                    Err(position) => position - 1,
+                };
+                let extent = &self.extents[position];
+
+                return Some((
+                    index,
+                    Cluster(extent.cluster.0 + index - extent.file_cluster),
+                ));
+            }
+
+            closest = Some(last_extent.last());
+        }
+
+        match (self.position, closest) {
+            (Some((position_index, cluster)), Some((closest_index, _)))
+                if position_index <= index && position_index > closest_index =>
+            {
+                Some((position_index, cluster))
+            }
+            (Some((position_index, cluster)), None) if position_index <= index => {
+                Some((position_index, cluster))
+            }
+            _ => closest,
+        }
+    }
+
+    /// Remember that the cluster at the given index in the file is the given cluster.
+    pub fn record(&mut self, index: u32, cluster: Cluster) {
+        self.position = Some((index, cluster));
+
+        let covered_count = self.used_extents().last().map_or(0, FileExtent::end);
+        if index != covered_count {
+            return;
+        }
+
+        if let Some(last_extent) = self.extents[..self.extent_count].last_mut() {
+            if last_extent.cluster.0 + last_extent.count == cluster.0 {
+                last_extent.count += 1;
+                return;
+            }
+        }
+
+        if let Some(extent) = self.extents.get_mut(self.extent_count) {
+            *extent = FileExtent {
+                file_cluster: index,
+                cluster,
+                count: 1,
+            };
+            self.extent_count += 1;
+        }
+    }
+}
@@ -0,0 +1,1212 @@
+//! Filesystem consistency checks.
+//!
+//! The check walks every directory from the root directory, follows every cluster chain through the FAT
+//! and reports each problem found to a callback. It never writes to the storage device.
+//!
+//! Each problem can then be turned into a `Repair` to review before applying it.
+
+use crate::attribute::Attributes;
+use crate::cluster::Cluster;
+use crate::directory::dir_entry::DirectoryEntryRawInfo;
+use crate::directory::raw_dir_entry::FatDirEntry;
+use crate::directory::raw_dir_entry_iterator::FatDirEntryIterator;
+use crate::filesystem::FatFileSystem;
+use crate::name::ShortFileName;
+use crate::table::{self, FatValue};
+use crate::utils::FileSystemIterator;
+use crate::FatError;
+use crate::FatFileSystemResult;
+use crate::FatFsType;
+use crate::MINIMAL_BLOCK_SIZE;
+use arrayvec::ArrayString;
+use core::fmt::Write;
+use storage_device::StorageDevice;
+
+/// The raw name of the "." entry of a directory.
+const DOT_NAME: [u8; ShortFileName::MAX_LEN] = *b".          ";
+
+/// The raw name of the ".." entry of a directory.
+const DOT_DOT_NAME: [u8; ShortFileName::MAX_LEN] = *b"..         ";
+
+/// The directory holding the saved lost chains.
+const FOUND_DIRECTORY: &str = "/FOUND.000";
+
+/// Options used when checking a filesystem.
+#[derive(Clone, Copy, Debug)]
+pub struct CheckOptions {
+    /// If true, cluster chains longer than needed by the file size aren't reported.
+    pub(crate) allow_preallocated: bool,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CheckOptions {
+    /// Create the default check options.
+    pub fn new() -> Self {
+        CheckOptions {
+            allow_preallocated: false,
+        }
+    }
+
+    /// Accept the cluster chains longer than needed by the file size, like the ones of preallocated files.
+    ///
+    /// By default they are reported with `Problem::ChainTooLong`.
+    pub fn allow_preallocated(mut self, allow_preallocated: bool) -> Self {
+        self.allow_preallocated = allow_preallocated;
+        self
+    }
+}
+
+/// The location of a sequence of raw entries in a directory.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryLocation {
+    /// The first cluster of the directory containing the entries, 0 for the root directory.
+    pub directory_cluster: u32,
+
+    /// The index of the first raw entry in the directory.
+    pub index: u32,
+
+    /// The count of raw entries, including the long name entries.
+    pub entry_count: u32,
+
+    /// The location of the raw entries on the storage device, None for the root directory itself.
+    pub(crate) raw_info: Option<DirectoryEntryRawInfo>,
+}
+
+/// A file or a directory found during a check.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryInfo {
+    /// The location of the entry in its directory.
+    pub location: EntryLocation,
+
+    /// The raw 8.3 name of the entry.
+    pub short_name: [u8; ShortFileName::MAX_LEN],
+
+    /// The first cluster of the entry.
+    pub start_cluster: u32,
+
+    /// The file size of the entry.
+    pub file_size: u32,
+
+    /// True if the entry is a directory.
+    pub is_directory: bool,
+}
+
+impl EntryInfo {
+    /// Return true if the entry is the root directory, which doesn't have a directory entry.
+    pub fn is_root_directory(&self) -> bool {
+        self.location.raw_info.is_none()
+    }
+}
+
+/// A problem found during a check.
+#[derive(Clone, Copy, Debug)]
+pub enum Problem {
+    /// A FAT differs from the first FAT in the block of MINIMAL_BLOCK_SIZE bytes starting at the given byte offset.
+    FatMismatch {
+        /// The index of the FAT.
+        fat_index: u8,
+
+        /// The byte offset of the block in the FAT.
+        offset: u32,
+    },
+
+    /// The cluster chain of an entry links to a cluster out of the data region, a free cluster or a bad cluster.
+    InvalidChain {
+        /// The entry owning the chain.
+        entry: EntryInfo,
+
+        /// The count of valid clusters at the start of the chain.
+        cluster_count: u32,
+    },
+
+    /// The cluster chain of an entry links to a cluster already used by another chain or earlier in the same chain.
+    ///
+    /// The content of a cross-linked directory isn't checked.
+    CrossLinkedChain {
+        /// The entry owning the chain.
+        entry: EntryInfo,
+
+        /// The count of clusters owned by the entry at the start of the chain.
+        cluster_count: u32,
+
+        /// The cluster already used.
+        cluster: u32,
+    },
+
+    /// The cluster chain of a file doesn't have enough clusters to hold the file size.
+    ChainTooShort {
+        /// The file owning the chain.
+        entry: EntryInfo,
+
+        /// The count of clusters of the chain.
+        cluster_count: u32,
+    },
+
+    /// The cluster chain of a file has more clusters than needed to hold the file size.
+    ChainTooLong {
+        /// The file owning the chain.
+        entry: EntryInfo,
+
+        /// The count of clusters of the chain.
+        cluster_count: u32,
+    },
+
+    /// The ".." entry of a directory is missing or doesn't point to its parent directory.
+    InvalidParentEntry {
+        /// The directory.
+        entry: EntryInfo,
+
+        /// The first cluster of the parent directory, 0 for the root directory.
+        parent_cluster: u32,
+    },
+
+    /// Long name entries aren't followed by their 8.3 entry, are out of order or have a wrong checksum.
+    OrphanLongName {
+        /// The location of the long name entries.
+        location: EntryLocation,
+    },
+
+    /// A cluster chain marked as used in the FAT isn't reachable from the root directory.
+    LostChain {
+        /// The first cluster of the chain.
+        start_cluster: u32,
+
+        /// The count of clusters of the chain.
+        cluster_count: u32,
+    },
+
+    /// The free cluster count stored in the FS Info structure of a FAT32 filesystem is wrong.
+    WrongFreeCount {
+        /// The count stored in the FS Info structure.
+        stored: u32,
+
+        /// The count of free clusters in the FAT.
+        actual: u32,
+    },
+}
+
+/// The summary of a check.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CheckReport {
+    /// The count of directories found, without the root directory.
+    pub directory_count: u32,
+
+    /// The count of files found.
+    pub file_count: u32,
+
+    /// The count of clusters reachable from the root directory.
+    pub used_clusters: u32,
+
+    /// The count of free clusters in the FAT.
+    pub free_clusters: u32,
+
+    /// The count of bad clusters in the FAT.
+    pub bad_clusters: u32,
+
+    /// The count of used clusters not reachable from the root directory.
+    pub lost_clusters: u32,
+
+    /// The count of preallocated files accepted by the options.
+    pub preallocated_files: u32,
+
+    /// The count of problems reported.
+    pub problem_count: u32,
+}
+
+impl CheckReport {
+    /// Return true if no problem was found.
+    pub fn is_clean(&self) -> bool {
+        self.problem_count == 0
+    }
+}
+
+/// Options used when repairing a filesystem.
+#[derive(Clone, Copy, Debug)]
+pub struct RepairOptions {
+    /// If true, lost chains are saved as files instead of being freed.
+    pub(crate) save_lost_chains: bool,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RepairOptions {
+    /// Create the default repair options.
+    pub fn new() -> Self {
+        RepairOptions {
+            save_lost_chains: false,
+        }
+    }
+
+    /// Save the lost chains as `FOUND.000/FILEnnnn.CHK` files instead of freeing them.
+    pub fn save_lost_chains(mut self, save_lost_chains: bool) -> Self {
+        self.save_lost_chains = save_lost_chains;
+        self
+    }
+}
+
+/// A change of the filesystem fixing a problem.
+#[derive(Clone, Copy, Debug)]
+pub enum Repair {
+    /// Copy a block of MINIMAL_BLOCK_SIZE bytes of the first FAT to another FAT.
+    CopyFatBlock {
+        /// The index of the FAT to overwrite.
+        fat_index: u8,
+
+        /// The byte offset of the block in the FAT.
+        offset: u32,
+    },
+
+    /// End the cluster chain of an entry after the given count of clusters.
+    ///
+    /// The following clusters belong to another chain or are invalid so they are left untouched.
+    /// With a count of 0, the first cluster of the entry is cleared.
+    EndChain {
+        /// The entry owning the chain.
+        entry: EntryInfo,
+
+        /// The count of clusters to keep.
+        cluster_count: u32,
+    },
+
+    /// End the cluster chain of an entry after the given count of clusters and free the following clusters.
+    TruncateChain {
+        /// The entry owning the chain.
+        entry: EntryInfo,
+
+        /// The count of clusters to keep.
+        cluster_count: u32,
+    },
+
+    /// Change the file size of an entry.
+    SetFileSize {
+        /// The entry to change.
+        entry: EntryInfo,
+
+        /// The new file size.
+        file_size: u32,
+    },
+
+    /// Point the ".." entry of a directory to its parent directory.
+    SetParentEntry {
+        /// The directory.
+        entry: EntryInfo,
+
+        /// The first cluster of the parent directory, 0 for the root directory.
+        parent_cluster: u32,
+    },
+
+    /// Delete raw entries, like orphaned long name entries or the entry of a directory without any cluster.
+    DeleteEntries {
+        /// The location of the raw entries.
+        location: EntryLocation,
+    },
+
+    /// Free a lost chain.
+    FreeChain {
+        /// The first cluster of the chain.
+        start_cluster: u32,
+
+        /// The count of clusters of the chain.
+        cluster_count: u32,
+    },
+
+    /// Save a lost chain as a new file in the `FOUND.000` directory.
+    SaveChain {
+        /// The first cluster of the chain.
+        start_cluster: u32,
+
+        /// The count of clusters of the chain.
+        cluster_count: u32,
+    },
+
+    /// Count the free clusters in the FAT and store the count in the FS Info structure.
+    UpdateFreeCount,
+}
+
+/// How a cluster chain ends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChainEnd {
+    /// The chain ends with an end of chain marker.
+    EndOfChain,
+
+    /// The chain links to an invalid cluster.
+    Invalid,
+
+    /// The chain links to the given cluster, already used.
+    CrossLinked(Cluster),
+}
+
+/// Get the byte offset of a FAT on the storage device.
+fn fat_offset<S: StorageDevice>(fs: &FatFileSystem<S>, fat_index: u8) -> u64 {
+    let boot_record = &fs.boot_record;
+    let bytes_per_block = u64::from(boot_record.bytes_per_block());
+
+    fs.partition_start
+        + (u64::from(boot_record.reserved_block_count())
+            + u64::from(fat_index) * u64::from(boot_record.fat_size()))
+            * bytes_per_block
+}
+
+/// Get a bit of a bitmap.
+fn get_bit(bits: &[u8], cluster: Cluster) -> bool {
+    bits[cluster.0 as usize / 8] & (1 << (cluster.0 % 8)) != 0
+}
+
+/// Set or clear a bit of a bitmap.
+fn set_bit(bits: &mut [u8], cluster: Cluster, value: bool) {
+    let mask = 1 << (cluster.0 % 8);
+
+    if value {
+        bits[cluster.0 as usize / 8] |= mask;
+    } else {
+        bits[cluster.0 as usize / 8] &= !mask;
+    }
+}
+
+/// Find the first set bit of a bitmap starting at the given cluster.
+fn find_set_bit(bits: &[u8], start: u32) -> Option<Cluster> {
+    let end = bits.len() * 8;
+    let mut current = start as usize;
+
+    while current < end {
+        if bits[current / 8] == 0 {
+            current = (current / 8 + 1) * 8;
+            continue;
+        }
+
+        if bits[current / 8] & (1 << (current % 8)) != 0 {
+            return Some(Cluster(current as u32));
+        }
+
+        current += 1;
+    }
+
+    None
+}
+
+/// A sequence of long name entries being read.
+struct LongNameSequence {
+    /// The location of the first entry of the sequence.
+    location: EntryLocation,
+
+    /// The order of the next entry expected, 0 if the 8.3 entry is expected.
+    next_order: u8,
+
+    /// The checksum of the 8.3 name stored in the entries.
+    checksum: u8,
+
+    /// True if an entry was out of order or had a different checksum.
+    broken: bool,
+}
+
+/// The state of a check.
+struct Checker<'a, 'm, S: StorageDevice, F: FnMut(&Problem)> {
+    /// The filesystem checked.
+    fs: &'a FatFileSystem<'m, S>,
+
+    /// The options of the check.
+    options: &'a CheckOptions,
+
+    /// The bitmap of the clusters reachable from the root directory.
+    reachable: &'a mut [u8],
+
+    /// The bitmap of the first clusters of the directories to scan, then of the clusters linked from lost clusters.
+    pending: &'a mut [u8],
+
+    /// The summary of the check.
+    report: CheckReport,
+
+    /// The function called for each problem.
+    on_problem: F,
+}
+
+impl<'a, 'm, S: StorageDevice, F: FnMut(&Problem)> Checker<'a, 'm, S, F> {
+    /// Report a problem.
+    fn problem(&mut self, problem: Problem) {
+        self.report.problem_count += 1;
+        (self.on_problem)(&problem);
+    }
+
+    /// Return true if the cluster is in the data region.
+    fn is_data_cluster(&self, cluster: Cluster) -> bool {
+        cluster.0 >= 2 && cluster.0 < self.fs.boot_record.cluster_count
+    }
+
+    /// Compare every FAT with the first one.
+    fn check_fats(&mut self) -> FatFileSystemResult<()> {
+        let boot_record = &self.fs.boot_record;
+        let fat_len = u64::from(boot_record.fat_size()) * u64::from(boot_record.bytes_per_block());
+
+        let mut first_block = [0x0u8; MINIMAL_BLOCK_SIZE];
+        let mut block = [0x0u8; MINIMAL_BLOCK_SIZE];
+
+        for fat_index in 1..boot_record.fats_count() {
+            let mut offset = 0;
+
+            while offset < fat_len {
+                let mut storage_device = self.fs.storage_device.lock();
+                storage_device
+                    .read(fat_offset(self.fs, 0) + offset, &mut first_block)
+                    .or(Err(FatError::ReadFailed))?;
+                storage_device
+                    .read(fat_offset(self.fs, fat_index) + offset, &mut block)
+                    .or(Err(FatError::ReadFailed))?;
+                drop(storage_device);
+
+                if block != first_block {
+                    self.problem(Problem::FatMismatch {
+                        fat_index,
+                        offset: offset as u32,
+                    });
+                }
+
+                offset += MINIMAL_BLOCK_SIZE as u64;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Follow a cluster chain, marking its clusters as reachable.
+    ///
+    /// Returns the count of clusters owned by the chain and how it ends.
+    fn walk_chain(&mut self, start_cluster: Cluster) -> FatFileSystemResult<(u32, ChainEnd)> {
+        let mut cluster = start_cluster;
+        let mut cluster_count = 0;
+
+        loop {
+            if !self.is_data_cluster(cluster) {
+                return Ok((cluster_count, ChainEnd::Invalid));
+            }
+
+            if get_bit(self.reachable, cluster) {
+                return Ok((cluster_count, ChainEnd::CrossLinked(cluster)));
+            }
+
+            set_bit(self.reachable, cluster, true);
+            self.report.used_clusters += 1;
+            cluster_count += 1;
+
+            match FatValue::get(self.fs, cluster)? {
+                FatValue::Data(next_cluster) => cluster = Cluster(next_cluster),
+                FatValue::EndOfChain => return Ok((cluster_count, ChainEnd::EndOfChain)),
+                FatValue::Free | FatValue::Bad => {
+                    return Ok((cluster_count, ChainEnd::Invalid));
+                }
+            }
+        }
+    }
+
+    /// Follow the cluster chain of an entry and report if it's invalid or cross-linked.
+    ///
+    /// Returns the count of clusters owned by the entry and how its chain ends.
+    fn check_chain(&mut self, entry: &EntryInfo) -> FatFileSystemResult<(u32, ChainEnd)> {
+        let (cluster_count, chain_end) = self.walk_chain(Cluster(entry.start_cluster))?;
+
+        match chain_end {
+            ChainEnd::EndOfChain => {}
+            ChainEnd::Invalid => self.problem(Problem::InvalidChain {
+                entry: *entry,
+                cluster_count,
+            }),
+            ChainEnd::CrossLinked(cluster) => self.problem(Problem::CrossLinkedChain {
+                entry: *entry,
+                cluster_count,
+                cluster: cluster.0,
+            }),
+        }
+
+        Ok((cluster_count, chain_end))
+    }
+
+    /// Check the cluster chain of a file against its size.
+    fn check_file(&mut self, entry: &EntryInfo) -> FatFileSystemResult<()> {
+        self.report.file_count += 1;
+
+        let cluster_count = if entry.start_cluster == 0 {
+            0
+        } else {
+            self.check_chain(entry)?.0
+        };
+
+        let cluster_size = self.fs.cluster_size();
+        let needed_cluster_count = (entry.file_size + cluster_size - 1) / cluster_size;
+
+        if cluster_count < needed_cluster_count {
+            self.problem(Problem::ChainTooShort {
+                entry: *entry,
+                cluster_count,
+            });
+        } else if cluster_count > needed_cluster_count {
+            if self.options.allow_preallocated {
+                self.report.preallocated_files += 1;
+            } else {
+                self.problem(Problem::ChainTooLong {
+                    entry: *entry,
+                    cluster_count,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the cluster chain and the ".." entry of a directory and schedule the scan of its entries.
+    fn check_directory(
+        &mut self,
+        entry: &EntryInfo,
+        parent_cluster: u32,
+    ) -> FatFileSystemResult<()> {
+        self.report.directory_count += 1;
+
+        if entry.start_cluster == 0 {
+            self.problem(Problem::InvalidChain {
+                entry: *entry,
+                cluster_count: 0,
+            });
+            return Ok(());
+        }
+
+        let (cluster_count, chain_end) = self.check_chain(entry)?;
+        if cluster_count == 0 {
+            return Ok(());
+        }
+
+        let start_cluster = Cluster(entry.start_cluster);
+        let mut raw_entries = FatDirEntryIterator::new(self.fs, start_cluster, 0, 0, false);
+        let is_valid_parent = match (raw_entries.next(self.fs), raw_entries.next(self.fs)) {
+            (Some(Err(error)), _) | (_, Some(Err(error))) => return Err(error),
+            (_, Some(Ok(dot_dot_entry))) => {
+                let cluster = dot_dot_entry.get_cluster();
+
+                // Some implementations point to the root directory of FAT32 filesystems with its cluster.
+                let is_root_cluster = self.fs.boot_record.fat_type == FatFsType::Fat32
+                    && parent_cluster == 0
+                    && cluster == self.fs.boot_record.root_dir_childs_cluster();
+
+                dot_dot_entry.data[..ShortFileName::MAX_LEN] == DOT_DOT_NAME
+                    && dot_dot_entry.attribute().is_directory()
+                    && (cluster.0 == parent_cluster || is_root_cluster)
+            }
+            _ => false,
+        };
+
+        if !is_valid_parent {
+            self.problem(Problem::InvalidParentEntry {
+                entry: *entry,
+                parent_cluster,
+            });
+        }
+
+        // The content of a cross-linked directory may belong to another entry.
+        if let ChainEnd::CrossLinked(_) = chain_end {
+            return Ok(());
+        }
+
+        set_bit(self.pending, start_cluster, true);
+
+        Ok(())
+    }
+
+    /// Get the count of clusters of a directory already checked, stopping at the first invalid link.
+    fn directory_cluster_count(&self, start_cluster: Cluster) -> FatFileSystemResult<u32> {
+        let mut cluster = start_cluster;
+        let mut cluster_count = 0;
+
+        while self.is_data_cluster(cluster) && cluster_count < self.fs.boot_record.cluster_count {
+            cluster_count += 1;
+
+            match FatValue::get(self.fs, cluster)? {
+                FatValue::Data(next_cluster) => cluster = Cluster(next_cluster),
+                _ => break,
+            }
+        }
+
+        Ok(cluster_count)
+    }
+
+    /// Report a long name sequence not followed by its 8.3 entry.
+    fn orphan_long_name(&mut self, sequence: Option<LongNameSequence>) {
+        if let Some(sequence) = sequence {
+            self.problem(Problem::OrphanLongName {
+                location: sequence.location,
+            });
+        }
+    }
+
+    /// Check every entry of a directory.
+    ///
+    /// The root directory of FAT12/FAT16 filesystems is given as the cluster 0.
+    fn scan_directory(&mut self, start_cluster: Cluster, is_root: bool) -> FatFileSystemResult<()> {
+        let in_old_fat_root_directory = is_root && self.fs.boot_record.fat_type != FatFsType::Fat32;
+        let directory_cluster = if is_root { 0 } else { start_cluster.0 };
+
+        // Only read the clusters owned by the directory.
+        let max_entry_count = if in_old_fat_root_directory {
+            u32::MAX
+        } else {
+            let entries_per_cluster = self.fs.cluster_size() / FatDirEntry::LEN as u32;
+            self.directory_cluster_count(start_cluster)?
+                .saturating_mul(entries_per_cluster)
+        };
+
+        let mut raw_entries = FatDirEntryIterator::new(self.fs, start_cluster, 0, 0, is_root);
+        let mut sequence: Option<LongNameSequence> = None;
+        let mut index = 0;
+
+        while index < max_entry_count {
+            let raw_entry = match raw_entries.next(self.fs) {
+                Some(raw_entry) => raw_entry?,
+                None => break,
+            };
+            let entry_index = index;
+            index += 1;
+
+            // End of directory
+            if raw_entry.is_free() {
+                break;
+            }
+
+            if raw_entry.is_deleted() {
+                let sequence = sequence.take();
+                self.orphan_long_name(sequence);
+                continue;
+            }
+
+            let location = EntryLocation {
+                directory_cluster,
+                index: entry_index,
+                entry_count: 1,
+                raw_info: Some(DirectoryEntryRawInfo::new(
+                    raw_entry.entry_cluster,
+                    raw_entry.entry_cluster_offset,
+                    raw_entry.entry_offset,
+                    1,
+                    in_old_fat_root_directory,
+                )),
+            };
+
+            if raw_entry.is_long_file_name() {
+                let lfn_entry = raw_entry.as_lfn_entry();
+                let order = lfn_entry.order_entry & 0x1F;
+
+                if (lfn_entry.order_entry & 0x40) != 0 {
+                    let previous_sequence = sequence.take();
+                    self.orphan_long_name(previous_sequence);
+
+                    sequence = Some(LongNameSequence {
+                        location,
+                        next_order: order.wrapping_sub(1),
+                        checksum: lfn_entry.lfn_checksum,
+                        broken: order == 0,
+                    });
+                } else if let Some(sequence) = &mut sequence {
+                    sequence.broken = sequence.broken
+                        || order != sequence.next_order
+                        || order == 0
+                        || lfn_entry.lfn_checksum != sequence.checksum;
+                    sequence.next_order = order.wrapping_sub(1);
+                    sequence.location.entry_count += 1;
+                    if let Some(raw_info) = &mut sequence.location.raw_info {
+                        raw_info.entry_count += 1;
+                    }
+                } else {
+                    sequence = Some(LongNameSequence {
+                        location,
+                        next_order: 0,
+                        checksum: lfn_entry.lfn_checksum,
+                        broken: true,
+                    });
+                }
+
+                continue;
+            }
+
+            let short_name = raw_entry.short_name().unwrap().as_bytes();
+            let attribute = raw_entry.attribute();
+
+            // Check that the long name belongs to this entry.
+            let mut location = location;
+            if let Some(sequence) = sequence.take() {
+                if sequence.broken
+                    || sequence.next_order != 0
+                    || sequence.checksum != ShortFileName::checksum_lfn(&short_name)
+                    || attribute.is_volume()
+                {
+                    self.orphan_long_name(Some(sequence));
+                } else {
+                    location.index = sequence.location.index;
+                    location.entry_count = sequence.location.entry_count + 1;
+                    location.raw_info = sequence.location.raw_info.map(|mut raw_info| {
+                        raw_info.entry_count += 1;
+                        raw_info
+                    });
+                }
+            }
+
+            if attribute.is_volume() || short_name == DOT_NAME || short_name == DOT_DOT_NAME {
+                continue;
+            }
+
+            let entry = EntryInfo {
+                location,
+                short_name,
+                start_cluster: raw_entry.get_cluster().0,
+                file_size: raw_entry.get_file_size(),
+                is_directory: attribute.is_directory(),
+            };
+
+            if entry.is_directory {
+                self.check_directory(&entry, directory_cluster)?;
+            } else {
+                self.check_file(&entry)?;
+            }
+        }
+
+        self.orphan_long_name(sequence);
+
+        Ok(())
+    }
+
+    /// Check the directories starting from the root directory.
+    fn check_tree(&mut self) -> FatFileSystemResult<()> {
+        let root_cluster = match self.fs.boot_record.fat_type {
+            FatFsType::Fat12 | FatFsType::Fat16 => Cluster(0),
+            FatFsType::Fat32 => {
+                let root_cluster = self.fs.boot_record.root_dir_childs_cluster();
+                let root_entry = EntryInfo {
+                    location: EntryLocation {
+                        directory_cluster: 0,
+                        index: 0,
+                        entry_count: 0,
+                        raw_info: None,
+                    },
+                    short_name: DOT_NAME,
+                    start_cluster: root_cluster.0,
+                    file_size: 0,
+                    is_directory: true,
+                };
+
+                self.check_chain(&root_entry)?;
+                root_cluster
+            }
+        };
+
+        self.scan_directory(root_cluster, true)?;
+
+        // Scan the pending directories, wrapping around as directories before the last one scanned can be found.
+        let mut start = 0;
+        loop {
+            let cluster = match find_set_bit(self.pending, start) {
+                Some(cluster) => cluster,
+                None if start != 0 => {
+                    start = 0;
+                    continue;
+                }
+                None => break,
+            };
+
+            set_bit(self.pending, cluster, false);
+            start = cluster.0;
+            self.scan_directory(cluster, false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Find the used clusters not reachable from the root directory and group them in chains.
+    fn check_lost_clusters(&mut self) -> FatFileSystemResult<()> {
+        let cluster_count = self.fs.boot_record.cluster_count;
+        let mut report = self.report;
+        let reachable = &mut *self.reachable;
+        let pending = &mut *self.pending;
+
+        // Mark the free and bad clusters as reachable and the clusters linked from lost clusters as pending.
+        table::for_each_cluster_value(self.fs, |cluster, value| match value {
+            FatValue::Free => {
+                report.free_clusters += 1;
+                set_bit(reachable, cluster, true);
+            }
+            FatValue::Bad => {
+                report.bad_clusters += 1;
+                set_bit(reachable, cluster, true);
+            }
+            FatValue::Data(next_cluster) if !get_bit(reachable, cluster) => {
+                report.lost_clusters += 1;
+                if next_cluster >= 2 && next_cluster < cluster_count {
+                    set_bit(pending, Cluster(next_cluster), true);
+                }
+            }
+            FatValue::EndOfChain if !get_bit(reachable, cluster) => report.lost_clusters += 1,
+            _ => {}
+        })?;
+        self.report = report;
+
+        // Lost clusters not linked from other lost clusters start a chain, the others are in lost loops.
+        for linked in &[false, true] {
+            for index in 2..cluster_count {
+                let cluster = Cluster(index);
+                if get_bit(self.reachable, cluster) || get_bit(self.pending, cluster) != *linked {
+                    continue;
+                }
+
+                let used_clusters = self.report.used_clusters;
+                let (chain_cluster_count, _) = self.walk_chain(cluster)?;
+                self.report.used_clusters = used_clusters;
+
+                self.problem(Problem::LostChain {
+                    start_cluster: index,
+                    cluster_count: chain_cluster_count,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compare the free cluster count stored in the FS Info structure with the FAT.
+    fn check_free_count(&mut self) -> FatFileSystemResult<()> {
+        if self.fs.boot_record.fat_type != FatFsType::Fat32 {
+            return Ok(());
+        }
+
+        if let Some(stored) = self.fs.stored_free_cluster_count()? {
+            if stored != self.report.free_clusters {
+                self.problem(Problem::WrongFreeCount {
+                    stored,
+                    actual: self.report.free_clusters,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check the consistency of a filesystem, calling the given function for each problem found.
+pub(crate) fn check<S: StorageDevice, F: FnMut(&Problem)>(
+    fs: &FatFileSystem<S>,
+    options: &CheckOptions,
+    memory: &mut [u8],
+    on_problem: F,
+) -> FatFileSystemResult<CheckReport> {
+    let bitmap_len = fs.allocation_bitmap_len();
+    if memory.len() < 2 * bitmap_len {
+        return Err(FatError::Custom {
+            name: "Not enough memory to check the filesystem",
+        });
+    }
+
+    let (reachable, pending) = memory[..2 * bitmap_len].split_at_mut(bitmap_len);
+    for byte in reachable.iter_mut().chain(pending.iter_mut()) {
+        *byte = 0;
+    }
+
+    let mut checker = Checker {
+        fs,
+        options,
+        reachable,
+        pending,
+        report: CheckReport::default(),
+        on_problem,
+    };
+
+    checker.check_fats()?;
+    checker.check_tree()?;
+    checker.check_lost_clusters()?;
+    checker.check_free_count()?;
+
+    Ok(checker.report)
+}
+
+/// Get the repair fixing a problem, None if it can't be fixed.
+pub(crate) fn plan_repair<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    problem: &Problem,
+    options: &RepairOptions,
+) -> Option<Repair> {
+    let cluster_size = fs.cluster_size();
+
+    match *problem {
+        Problem::FatMismatch { fat_index, offset } => {
+            Some(Repair::CopyFatBlock { fat_index, offset })
+        }
+        Problem::InvalidChain {
+            entry,
+            cluster_count,
+        }
+        | Problem::CrossLinkedChain {
+            entry,
+            cluster_count,
+            ..
+        } => {
+            if cluster_count != 0 || (!entry.is_directory && !entry.is_root_directory()) {
+                Some(Repair::EndChain {
+                    entry,
+                    cluster_count,
+                })
+            } else if entry.is_root_directory() {
+                None
+            } else {
+                // A directory needs at least one cluster for its "." and ".." entries.
+                Some(Repair::DeleteEntries {
+                    location: entry.location,
+                })
+            }
+        }
+        Problem::ChainTooShort {
+            entry,
+            cluster_count,
+        } => Some(Repair::SetFileSize {
+            entry,
+            file_size: cluster_count.saturating_mul(cluster_size),
+        }),
+        Problem::ChainTooLong { entry, .. } => Some(Repair::TruncateChain {
+            entry,
+            cluster_count: (entry.file_size + cluster_size - 1) / cluster_size,
+        }),
+        Problem::InvalidParentEntry {
+            entry,
+            parent_cluster,
+        } => Some(Repair::SetParentEntry {
+            entry,
+            parent_cluster,
+        }),
+        Problem::OrphanLongName { location } => Some(Repair::DeleteEntries { location }),
+        Problem::LostChain {
+            start_cluster,
+            cluster_count,
+        } => {
+            if options.save_lost_chains {
+                Some(Repair::SaveChain {
+                    start_cluster,
+                    cluster_count,
+                })
+            } else {
+                Some(Repair::FreeChain {
+                    start_cluster,
+                    cluster_count,
+                })
+            }
+        }
+        Problem::WrongFreeCount { .. } => Some(Repair::UpdateFreeCount),
+    }
+}
+
+/// Get the 8.3 entry of an entry found during a check.
+fn short_name_entry<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    entry: &EntryInfo,
+) -> FatFileSystemResult<FatDirEntry> {
+    match entry.location.raw_info {
+        Some(raw_info) => raw_info.get_dir_entry(fs),
+        None => Err(FatError::Custom {
+            name: "The root directory doesn't have an entry",
+        }),
+    }
+}
+
+/// Get the last cluster of the given count of clusters at the start of a chain.
+fn last_kept_cluster<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    start_cluster: Cluster,
+    cluster_count: u32,
+) -> FatFileSystemResult<Cluster> {
+    let mut last_cluster = start_cluster;
+
+    for _ in 1..cluster_count {
+        last_cluster = match FatValue::get(fs, last_cluster)? {
+            FatValue::Data(next_cluster) => Cluster(next_cluster),
+            _ => {
+                return Err(FatError::Custom {
+                    name: "The cluster chain changed since the check",
+                })
+            }
+        };
+    }
+
+    Ok(last_cluster)
+}
+
+/// End a cluster chain after the given count of clusters, which must not be 0.
+///
+/// Returns the cluster following the new end of the chain, if any.
+fn end_chain<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    start_cluster: Cluster,
+    cluster_count: u32,
+) -> FatFileSystemResult<Option<Cluster>> {
+    let last_cluster = last_kept_cluster(fs, start_cluster, cluster_count)?;
+    let next_cluster = match FatValue::get(fs, last_cluster)? {
+        FatValue::Data(next_cluster)
+            if next_cluster >= 2 && next_cluster < fs.boot_record.cluster_count =>
+        {
+            Some(Cluster(next_cluster))
+        }
+        _ => None,
+    };
+
+    FatValue::put(fs, last_cluster, FatValue::EndOfChain)?;
+    Ok(next_cluster)
+}
+
+/// Create a new file in the `FOUND.000` directory holding the given cluster chain.
+fn save_chain<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    start_cluster: Cluster,
+    cluster_count: u32,
+) -> FatFileSystemResult<()> {
+    match fs.create_directory(FOUND_DIRECTORY) {
+        Ok(()) | Err(FatError::FileExists) => {}
+        Err(error) => return Err(error),
+    }
+
+    for index in 0..10000 {
+        let mut path: ArrayString<[u8; 32]> = ArrayString::new();
+        write!(path, "{}/FILE{:04}.CHK", FOUND_DIRECTORY, index).unwrap();
+
+        match fs.create_file(&path) {
+            Ok(()) => {}
+            Err(FatError::FileExists) => continue,
+            Err(error) => return Err(error),
+        }
+
+        let raw_info = fs.search_entry(&path)?.raw_info.unwrap();
+        let mut raw_entry = raw_info.get_dir_entry(fs)?;
+        raw_entry.set_file_size(cluster_count.saturating_mul(fs.cluster_size()));
+        raw_entry.set_cluster(start_cluster);
+        return raw_entry.flush(fs);
+    }
+
+    Err(FatError::Custom {
+        name: "Too many saved chains",
+    })
+}
+
+/// Apply a repair.
+pub(crate) fn apply_repair<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    repair: &Repair,
+) -> FatFileSystemResult<()> {
+    fs.check_read_write()?;
+
+    match *repair {
+        Repair::CopyFatBlock { fat_index, offset } => {
+            let mut block = [0x0u8; MINIMAL_BLOCK_SIZE];
+            let mut storage_device = fs.storage_device.lock();
+
+            storage_device
+                .read(fat_offset(fs, 0) + u64::from(offset), &mut block)
+                .or(Err(FatError::ReadFailed))?;
+            storage_device
+                .write(fat_offset(fs, fat_index) + u64::from(offset), &block)
+                .or(Err(FatError::WriteFailed))
+        }
+        Repair::EndChain {
+            entry,
+            cluster_count: 0,
+        } => {
+            let mut raw_entry = short_name_entry(fs, &entry)?;
+            raw_entry.set_cluster(Cluster(0));
+            raw_entry.flush(fs)
+        }
+        Repair::EndChain {
+            entry,
+            cluster_count,
+        } => end_chain(fs, Cluster(entry.start_cluster), cluster_count).map(|_| ()),
+        Repair::TruncateChain {
+            entry,
+            cluster_count: 0,
+        } => {
+            let mut raw_entry = short_name_entry(fs, &entry)?;
+            raw_entry.set_cluster(Cluster(0));
+            raw_entry.flush(fs)?;
+
+            fs.free_cluster(Cluster(entry.start_cluster), None)
+        }
+        Repair::TruncateChain {
+            entry,
+            cluster_count,
+        } => {
+            let last_cluster = last_kept_cluster(fs, Cluster(entry.start_cluster), cluster_count)?;
+
+            match FatValue::get(fs, last_cluster)? {
+                FatValue::Data(next_cluster) => {
+                    fs.free_cluster(Cluster(next_cluster), Some(last_cluster))
+                }
+                _ => Ok(()),
+            }
+        }
+        Repair::SetFileSize { entry, file_size } => {
+            let mut raw_entry = short_name_entry(fs, &entry)?;
+            raw_entry.set_file_size(file_size);
+            raw_entry.flush(fs)
+        }
+        Repair::SetParentEntry {
+            entry,
+            parent_cluster,
+        } => {
+            let mut raw_entries =
+                FatDirEntryIterator::new(fs, Cluster(entry.start_cluster), 0, 0, false);
+
+            match raw_entries.nth(fs, 1) {
+                Some(Ok(mut dot_dot_entry))
+                    if dot_dot_entry.data[..ShortFileName::MAX_LEN] == DOT_DOT_NAME =>
+                {
+                    dot_dot_entry.set_attribute(Attributes::new(Attributes::DIRECTORY));
+                    dot_dot_entry.set_cluster(Cluster(parent_cluster));
+                    dot_dot_entry.flush(fs)
+                }
+                Some(Err(error)) => Err(error),
+                _ => Err(FatError::Custom {
+                    name: "Cannot find the \"..\" entry",
+                }),
+            }
+        }
+        Repair::DeleteEntries { location } => {
+            let raw_info = match location.raw_info {
+                Some(raw_info) => raw_info,
+                None => return Ok(()),
+            };
+            let mut raw_entries = FatDirEntryIterator::new(
+                fs,
+                raw_info.parent_cluster,
+                raw_info.first_entry_cluster_offset,
+                raw_info.first_entry_offset,
+                raw_info.in_old_fat_root_directory,
+            );
+
+            for _ in 0..raw_info.entry_count {
+                let mut raw_entry = raw_entries.next(fs).ok_or(FatError::ReadFailed)??;
+                raw_entry.set_deleted();
+                raw_entry.flush(fs)?;
+            }
+
+            Ok(())
+        }
+        Repair::FreeChain {
+            start_cluster,
+            cluster_count,
+        } => {
+            end_chain(fs, Cluster(start_cluster), cluster_count)?;
+            fs.free_cluster(Cluster(start_cluster), None)
+        }
+        Repair::SaveChain {
+            start_cluster,
+            cluster_count,
+        } => {
+            end_chain(fs, Cluster(start_cluster), cluster_count)?;
+            save_chain(fs, Cluster(start_cluster), cluster_count)
+        }
+        Repair::UpdateFreeCount => fs.update_free_cluster_count(),
+    }
+}
@@ -1,7 +1,15 @@
 //! FAT datetime.
 
+/// A source of the current date and time used to stamp directory entries.
+pub trait TimeProvider: Sync {
+    /// Return the current local date and time.
+    fn now(&self) -> FatDateTime;
+}
+
 /// Represent a FAT date time
-#[derive(Debug)]
+///
+/// A FAT date time is always valid and in the 1980-2107 range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FatDateTime {
     /// The year of the datetime.
     year: u16,
@@ -21,24 +29,32 @@ pub struct FatDateTime {
     /// The seconds of the datetime.
     seconds: u8,
 
-    /// The tenths of second of the datetime.
-    tenths: u8,
+    /// The hundredths of second of the datetime.
+    hundredths: u8,
+
+    /// The offset from UTC in 15 minutes increments if known.
+    utc_offset: Option<i8>,
 }
 
 impl FatDateTime {
-    /// The amount of days in every leap year indexed per months.
-    const DAYS: [[u16; 12]; 4] = [
-        [0, 31, 60, 91, 121, 152, 182, 213, 244, 274, 305, 335],
-        [366, 397, 425, 456, 486, 517, 547, 578, 609, 639, 670, 700],
-        [
-            731, 762, 790, 821, 851, 882, 912, 943, 974, 1004, 1035, 1065,
-        ],
-        [
-            1096, 1127, 1155, 1186, 1216, 1247, 1277, 1308, 1339, 1369, 1400, 1430,
-        ],
-    ];
-
-    /// Create a new datetime
+    /// The first year representable.
+    const MIN_YEAR: u16 = 1980;
+
+    /// The last year representable.
+    const MAX_YEAR: u16 = 2107;
+
+    /// The UNIX timestamp of the first datetime representable (1980-01-01 00:00:00).
+    const MIN_UNIX_TIME: u64 = 315_532_800;
+
+    /// The UNIX timestamp of the last datetime representable (2107-12-31 23:59:59).
+    const MAX_UNIX_TIME: u64 = 4_354_819_199;
+
+    /// The bit set in the raw timezone byte when the offset is valid.
+    const UTC_OFFSET_VALID: u8 = 0x80;
+
+    /// Create a new datetime or return None if one of the fields is out of range.
+    ///
+    /// The year must be in the 1980-2107 range and hundredths is the sub-second part in units of 10 milliseconds.
     pub fn new(
         year: u16,
         month: u8,
@@ -46,46 +62,310 @@ impl FatDateTime {
         hour: u8,
         minutes: u8,
         seconds: u8,
-        tenths: u8,
-    ) -> Self {
-        FatDateTime {
+        hundredths: u8,
+    ) -> Option<Self> {
+        if !(Self::MIN_YEAR..=Self::MAX_YEAR).contains(&year)
+            || !(1..=12).contains(&month)
+            || !(1..=Self::days_in_month(year, month)).contains(&day)
+            || hour > 23
+            || minutes > 59
+            || seconds > 59
+            || hundredths > 99
+        {
+            return None;
+        }
+
+        Some(FatDateTime {
             year,
             month,
             day,
             hour,
             minutes,
             seconds,
-            tenths,
+            hundredths,
+            utc_offset: None,
+        })
+    }
+
+    /// Set the offset from UTC of the datetime in 15 minutes increments or return None if the offset is outside of the -64..=63 range.
+    pub fn with_utc_offset(mut self, utc_offset: i8) -> Option<Self> {
+        if !(-64..=63).contains(&utc_offset) {
+            return None;
+        }
+
+        self.utc_offset = Some(utc_offset);
+        Some(self)
+    }
+
+    /// Check if the given year is a leap year.
+    // `is_multiple_of` needs a more recent compiler than the one supported by the crate.
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+    fn is_leap_year(year: u16) -> bool {
+        (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+    }
+
+    /// Get the amount of days in the given month.
+    fn days_in_month(year: u16, month: u8) -> u8 {
+        match month {
+            2 if Self::is_leap_year(year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
         }
     }
 
+    /// The year of the datetime.
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    /// The month of the datetime (1-12).
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    /// The day of the datetime (1-31).
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    /// The hour of the datetime (0-23).
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    /// The minutes of the datetime (0-59).
+    pub fn minutes(&self) -> u8 {
+        self.minutes
+    }
+
+    /// The seconds of the datetime (0-59).
+    pub fn seconds(&self) -> u8 {
+        self.seconds
+    }
+
+    /// The hundredths of second of the datetime (0-99).
+    pub fn hundredths(&self) -> u8 {
+        self.hundredths
+    }
+
+    /// The offset from UTC in 15 minutes increments if known.
+    pub fn utc_offset(&self) -> Option<i8> {
+        self.utc_offset
+    }
+
+    /// Create a datetime from a UNIX timestamp or return None if it cannot be represented in FAT (before 1980 or after 2107).
+    pub fn from_unix_time(timestamp: u64) -> Option<Self> {
+        if !(Self::MIN_UNIX_TIME..=Self::MAX_UNIX_TIME).contains(&timestamp) {
+            return None;
+        }
+
+        let days = timestamp / 86400;
+        let seconds_in_day = timestamp % 86400;
+
+        // Convert the days since the UNIX epoch to a civil date using a calendar starting in March.
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let day_of_era = z % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        FatDateTime::new(
+            year as u16,
+            month as u8,
+            day as u8,
+            (seconds_in_day / 3600) as u8,
+            (seconds_in_day / 60 % 60) as u8,
+            (seconds_in_day % 60) as u8,
+            0,
+        )
+    }
+
     /// Convert the FAT datetime to a UNIX timestamp.
-    /// NOTE: This only support the 2000-2099 range. If something outside this range is provided, it will return an UNIX epoch.
+    ///
+    /// The sub-second part is ignored. If the UTC offset is known, it's used to convert the local datetime to UTC.
     pub fn to_unix_time(&self) -> u64 {
-        // TODO: support other ranges than 2000-2099
-        // BODY: If a date that is outside the 2000-2099 range is provided, we should support it.
-        // BODY: In practice, this only apply to the range 1980-1999.
-        if self.year > 2099 || self.year < 2000 {
-            return 0;
+        // Convert the civil date to days since the UNIX epoch using a calendar starting in March.
+        let (year, month) = if self.month <= 2 {
+            (u64::from(self.year) - 1, u64::from(self.month) + 9)
+        } else {
+            (u64::from(self.year), u64::from(self.month) - 3)
+        };
+
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let day_of_year = (153 * month + 2) / 5 + u64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        let local_time = ((days * 24 + u64::from(self.hour)) * 60 + u64::from(self.minutes)) * 60
+            + u64::from(self.seconds);
+
+        match self.utc_offset {
+            Some(utc_offset) => {
+                let offset_seconds = u64::from(utc_offset.unsigned_abs()) * 900;
+                if utc_offset >= 0 {
+                    local_time - offset_seconds
+                } else {
+                    local_time + offset_seconds
+                }
+            }
+            None => local_time,
         }
+    }
+
+    /// Decode a datetime from raw FAT date, time and 10 milliseconds fields or return None if they are invalid.
+    pub fn from_fat(date: u16, time: u16, time_fine: u8) -> Option<Self> {
+        if time_fine > 199 {
+            return None;
+        }
+
+        let seconds = ((time & 0x1f) << 1) as u8 + time_fine / 100;
+        let minutes = ((time >> 5) & 0x3f) as u8;
+        let hour = ((time >> 11) & 0x1f) as u8;
+
+        let day = (date & 0x1f) as u8;
+        let month = ((date >> 5) & 0xf) as u8;
+        let year = Self::MIN_YEAR + ((date >> 9) & 0x7f);
+
+        FatDateTime::new(year, month, day, hour, minutes, seconds, time_fine % 100)
+    }
+
+    /// Decode a datetime from raw FAT date, time, 10 milliseconds and timezone fields or return None if they are invalid.
+    pub fn from_fat_with_utc_offset(
+        date: u16,
+        time: u16,
+        time_fine: u8,
+        utc_offset: u8,
+    ) -> Option<Self> {
+        let datetime = Self::from_fat(date, time, time_fine)?;
+
+        if utc_offset & Self::UTC_OFFSET_VALID == 0 {
+            return Some(datetime);
+        }
+
+        // Sign extend the 7 bits offset.
+        datetime.with_utc_offset(i8::from_le_bytes([utc_offset << 1]) >> 1)
+    }
+
+    /// Encode the date part of the datetime as a raw FAT date.
+    pub fn to_fat_date(self) -> u16 {
+        ((self.year - Self::MIN_YEAR) << 9) | (u16::from(self.month) << 5) | u16::from(self.day)
+    }
+
+    /// Encode the time part of the datetime as a raw FAT time with a two seconds resolution.
+    pub fn to_fat_time(self) -> u16 {
+        (u16::from(self.hour) << 11) | (u16::from(self.minutes) << 5) | u16::from(self.seconds / 2)
+    }
+
+    /// Encode the part of the time lost by ``to_fat_time`` in units of 10 milliseconds (0-199).
+    pub fn to_fat_time_fine(self) -> u8 {
+        (self.seconds % 2) * 100 + self.hundredths
+    }
+
+    /// Encode the UTC offset as a raw timezone byte (zero if unknown).
+    pub fn to_fat_utc_offset(self) -> u8 {
+        match self.utc_offset {
+            Some(utc_offset) => Self::UTC_OFFSET_VALID | (utc_offset.to_le_bytes()[0] & 0x7f),
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FatDateTime;
+
+    #[test]
+    fn unix_time_roundtrip() {
+        for timestamp in &[
+            FatDateTime::MIN_UNIX_TIME,
+            951_782_400,   // 2000-02-29
+            1_600_000_001, // 2020-09-13 12:26:41
+            4_107_542_400, // 2100-03-01
+            FatDateTime::MAX_UNIX_TIME,
+        ] {
+            let datetime = FatDateTime::from_unix_time(*timestamp).unwrap();
+            assert_eq!(datetime.to_unix_time(), *timestamp);
+        }
+
+        assert_eq!(
+            FatDateTime::from_unix_time(951_782_400).unwrap(),
+            FatDateTime::new(2000, 2, 29, 0, 0, 0, 0).unwrap()
+        );
+        assert!(FatDateTime::from_unix_time(FatDateTime::MIN_UNIX_TIME - 1).is_none());
+        assert!(FatDateTime::from_unix_time(FatDateTime::MAX_UNIX_TIME + 1).is_none());
+    }
+
+    #[test]
+    fn fat_roundtrip() {
+        let datetime = FatDateTime::new(2107, 12, 31, 23, 59, 59, 99).unwrap();
+        let decoded = FatDateTime::from_fat(
+            datetime.to_fat_date(),
+            datetime.to_fat_time(),
+            datetime.to_fat_time_fine(),
+        )
+        .unwrap();
+        assert_eq!(decoded, datetime);
+        assert_eq!(datetime.to_fat_time_fine(), 199);
+
+        let datetime = FatDateTime::new(1980, 1, 1, 0, 0, 0, 0).unwrap();
+        assert_eq!(datetime.to_fat_date(), 0x21);
+        assert_eq!(datetime.to_fat_time(), 0);
+    }
+
+    #[test]
+    fn utc_offset() {
+        let datetime = FatDateTime::new(2020, 1, 1, 12, 0, 0, 0).unwrap();
+
+        // UTC+2 and UTC-5
+        let east = datetime.with_utc_offset(8).unwrap();
+        let west = datetime.with_utc_offset(-20).unwrap();
+        assert_eq!(east.to_unix_time(), datetime.to_unix_time() - 7200);
+        assert_eq!(west.to_unix_time(), datetime.to_unix_time() + 18000);
+
+        for value in &[east, west] {
+            let decoded = FatDateTime::from_fat_with_utc_offset(
+                value.to_fat_date(),
+                value.to_fat_time(),
+                value.to_fat_time_fine(),
+                value.to_fat_utc_offset(),
+            )
+            .unwrap();
+            assert_eq!(decoded, *value);
+        }
+
+        assert_eq!(datetime.to_fat_utc_offset(), 0);
+        assert!(datetime.with_utc_offset(64).is_none());
+    }
+
+    #[test]
+    fn invalid_fields() {
+        assert!(FatDateTime::new(1979, 12, 31, 0, 0, 0, 0).is_none());
+        assert!(FatDateTime::new(2108, 1, 1, 0, 0, 0, 0).is_none());
+        assert!(FatDateTime::new(2020, 0, 1, 0, 0, 0, 0).is_none());
+        assert!(FatDateTime::new(2020, 13, 1, 0, 0, 0, 0).is_none());
+        assert!(FatDateTime::new(2020, 1, 32, 0, 0, 0, 0).is_none());
+        assert!(FatDateTime::new(2021, 2, 29, 0, 0, 0, 0).is_none());
+        assert!(FatDateTime::new(2100, 2, 29, 0, 0, 0, 0).is_none());
+        assert!(FatDateTime::new(2000, 2, 29, 0, 0, 0, 0).is_some());
+        assert!(FatDateTime::new(2020, 1, 1, 24, 0, 0, 0).is_none());
+        assert!(FatDateTime::new(2020, 1, 1, 0, 60, 0, 0).is_none());
+        assert!(FatDateTime::new(2020, 1, 1, 0, 0, 60, 0).is_none());
+        assert!(FatDateTime::new(2020, 1, 1, 0, 0, 0, 100).is_none());
 
-        let year = u64::from(self.year) % 100;
-        let month = u64::from(self.month) - 1;
-        let day = u64::from(self.day) - 1;
-
-        let hour = u64::from(self.hour);
-        let minutes = u64::from(self.minutes);
-        let seconds = u64::from(self.seconds);
-
-        946_684_800
-            + (((year / 4 * (365 * 4 + 1)
-                + u64::from(Self::DAYS[year as usize % 4][month as usize])
-                + day)
-                * 24
-                + hour)
-                * 60
-                + minutes)
-                * 60
-            + seconds
+        // A zeroed entry has no valid date.
+        assert!(FatDateTime::from_fat(0, 0, 0).is_none());
+        assert!(FatDateTime::from_fat(0x21, 0, 200).is_none());
     }
 }
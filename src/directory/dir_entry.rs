@@ -93,9 +93,9 @@ impl DirectoryEntry {
         DirectoryEntry {
             start_cluster: sfn_entry.get_cluster(),
             raw_info,
-            creation_timestamp: sfn_entry.get_creation_datetime().to_unix_time(),
-            last_access_timestamp: sfn_entry.get_last_access_date().to_unix_time(),
-            last_modification_timestamp: sfn_entry.get_modification_datetime().to_unix_time(),
+            creation_timestamp: sfn_entry.get_creation_timestamp(),
+            last_access_timestamp: sfn_entry.get_last_access_timestamp(),
+            last_modification_timestamp: sfn_entry.get_modification_timestamp(),
             file_size: sfn_entry.get_file_size(),
             file_name,
             attribute: sfn_entry.attribute(),
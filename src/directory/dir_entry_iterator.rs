@@ -137,9 +137,9 @@ impl<S: StorageDevice> FileSystemIterator<S> for DirectoryEntryIterator {
                         entry_count,
                         self.raw_iter.cluster_iter.is_none(),
                     )),
-                    creation_timestamp: entry.get_creation_datetime().to_unix_time(),
-                    last_access_timestamp: entry.get_last_access_date().to_unix_time(),
-                    last_modification_timestamp: entry.get_modification_datetime().to_unix_time(),
+                    creation_timestamp: entry.get_creation_timestamp(),
+                    last_access_timestamp: entry.get_last_access_timestamp(),
+                    last_modification_timestamp: entry.get_modification_timestamp(),
                     file_size: entry.get_file_size(),
                     file_name,
                     attribute: entry.attribute(),
@@ -1,6 +1,7 @@
 //! FAT directory managment.
 
 use super::attribute::Attributes;
+use super::chain_cursor::ChainCursor;
 use super::cluster::Cluster;
 use super::name::ShortFileName;
 use super::name::ShortFileNameContext;
@@ -12,9 +13,12 @@ use super::FatFsType;
 use crate::utils::FileSystemIterator;
 use crate::FatError;
 use crate::FatFileSystemResult;
+use crate::FileExtent;
 use arrayvec::ArrayString;
+use core::ops::Range;
 use dir_entry::{DirectoryEntry, DirectoryEntryRawInfo};
 use dir_entry_iterator::DirectoryEntryIterator;
+use raw_dir_entry::FatDirEntry;
 use raw_dir_entry_iterator::FatDirEntryIterator;
 use storage_device::StorageDevice;
 
@@ -24,28 +28,31 @@ pub(crate) mod raw_dir_entry;
 pub(crate) mod raw_dir_entry_iterator;
 
 /// Represents a Directory.
-pub struct Directory<'a, S: StorageDevice> {
+pub struct Directory<'a, 'm, S: StorageDevice> {
     /// The information about this directory.
     pub(crate) dir_info: DirectoryEntry,
 
     /// A reference to the filesystem containing this directory.
-    fs: &'a FatFileSystem<S>,
+    fs: &'a FatFileSystem<'m, S>,
 }
 
 /// Represents a File.
-pub struct File {
+pub struct File<'m> {
     /// The information about this file.
     pub file_info: DirectoryEntry,
+
+    /// The known positions in the cluster chain of this file.
+    cursor: ChainCursor<'m>,
 }
 
-impl<'a, S: StorageDevice> Directory<'a, S> {
+impl<'a, 'm, S: StorageDevice> Directory<'a, 'm, S> {
     /// Helper to determine if the directory is the root directory.
     pub fn is_root_directory(&self) -> bool {
         self.dir_info.raw_info.is_none()
     }
 
     /// Create a directory from a filesystem reference and a directory entry.
-    pub fn from_entry(fs: &'a FatFileSystem<S>, dir_info: DirectoryEntry) -> Self {
+    pub fn from_entry(fs: &'a FatFileSystem<'m, S>, dir_info: DirectoryEntry) -> Self {
         Directory { dir_info, fs }
     }
 
@@ -161,17 +168,22 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
     }
 
     /// Open a file at the given path.
-    pub fn open_file(&self, path: &str) -> FatFileSystemResult<File> {
+    pub fn open_file(&self, path: &str) -> FatFileSystemResult<File<'static>> {
+        let generation = self.fs.chain_generation();
         let entry = self.search_entry(path)?;
         if entry.attribute.is_directory() {
             return Err(FatError::NotAFile);
         }
 
-        Ok(File::from_entry(entry))
+        // The entry was just read, no need to read it again on the first access.
+        let mut file = File::from_entry(entry);
+        file.cursor.set_generation(generation);
+
+        Ok(file)
     }
 
     /// Open a directory at the given path.
-    pub fn open_directory(&self, path: &str) -> FatFileSystemResult<Directory<'a, S>> {
+    pub fn open_directory(&self, path: &str) -> FatFileSystemResult<Directory<'a, 'm, S>> {
         let entry = self.search_entry(path)?;
         if !entry.attribute.is_directory() {
             return Err(FatError::NotADirectory);
@@ -180,13 +192,16 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         Ok(Directory::from_entry(self.fs, entry))
     }
 
-    /// Look for unused space to allocate a directory entry and return a raw entry iterator to it.
-    fn allocate_entries(
+    /// Look for unused space to allocate ``count`` contiguous directory entries and return a raw entry iterator to the first one.
+    ///
+    /// If there isn't enough space, the directory is extended with new clusters.
+    pub(crate) fn allocate_entries(
         entry: &DirectoryEntry,
-        fs: &'a FatFileSystem<S>,
+        fs: &'a FatFileSystem<'m, S>,
         count: u32,
     ) -> FatFileSystemResult<FatDirEntryIterator> {
-        let mut i = 0;
+        let mut free_count = 0;
+        let mut first_free_entry = None;
         let directory = Directory::from_entry(fs, *entry);
         let is_root_directory = directory.is_root_directory();
 
@@ -194,50 +209,75 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         while let Some(raw_dir_entry) = fat_dir_entry_iter.next(fs) {
             let raw_dir_entry = raw_dir_entry?;
             if raw_dir_entry.is_free() || raw_dir_entry.is_deleted() {
-                i += 1;
-                if i == count {
-                    return Ok(FatDirEntryIterator::new(
-                        fs,
-                        raw_dir_entry.entry_cluster,
-                        raw_dir_entry.entry_cluster_offset,
-                        raw_dir_entry.entry_offset,
-                        is_root_directory,
-                    ));
+                if free_count == 0 {
+                    first_free_entry = Some(raw_dir_entry);
                 }
+
+                free_count += 1;
+                if free_count == count {
+                    break;
+                }
+            } else {
+                free_count = 0;
+                first_free_entry = None;
             }
         }
 
-        if is_root_directory {
-            match fs.boot_record.fat_type {
-                FatFsType::Fat12 | FatFsType::Fat16 => return Err(FatError::NoSpaceLeft),
-                _ => {}
+        if free_count != count {
+            if is_root_directory {
+                match fs.boot_record.fat_type {
+                    FatFsType::Fat12 | FatFsType::Fat16 => return Err(FatError::NoSpaceLeft),
+                    _ => {}
+                }
             }
-        }
 
-        // if the directory is full, try to allocate a cluster and use it
-        let last_cluster = table::get_last_cluster(fs, entry.start_cluster)?;
-        let new_cluster = fs.alloc_cluster(Some(last_cluster))?;
+            // if the directory is full, try to allocate clusters and use them
+            let entries_per_cluster = u32::from(fs.boot_record.blocks_per_cluster())
+                * u32::from(fs.boot_record.bytes_per_block())
+                / FatDirEntry::LEN as u32;
 
-        let clear_res = fs.clean_cluster_data(new_cluster);
+            let mut last_cluster = table::get_last_cluster(fs, entry.start_cluster)?;
 
-        if let Err(error) = clear_res {
-            // If it fail here, this can be catastrophic but at least we tried our best.
-            fs.free_cluster(new_cluster, Some(last_cluster))?;
-            return Err(error);
+            while free_count < count {
+                let new_cluster = fs.alloc_cluster(Some(last_cluster))?;
+
+                let clear_res = fs.clean_cluster_data(new_cluster);
+
+                if let Err(error) = clear_res {
+                    // If it fail here, this can be catastrophic but at least we tried our best.
+                    fs.free_cluster(new_cluster, Some(last_cluster))?;
+                    return Err(error);
+                }
+
+                if free_count == 0 {
+                    first_free_entry = Some(FatDirEntry::from_raw(
+                        &[0x0; FatDirEntry::LEN],
+                        new_cluster,
+                        0,
+                        0,
+                    ));
+                }
+
+                free_count += entries_per_cluster;
+                last_cluster = new_cluster;
+            }
         }
 
+        // unwrap will never fail here
+        let first_free_entry = first_free_entry.unwrap();
+
         Ok(FatDirEntryIterator::new(
             fs,
-            new_cluster,
-            0,
-            0,
+            first_free_entry.entry_cluster,
+            first_free_entry.entry_cluster_offset,
+            first_free_entry.entry_offset,
             is_root_directory,
         ))
     }
 
     /// Create a directory entry in a given parent directory.
     fn create_dir_entry(
-        fs: &'a FatFileSystem<S>,
+        fs: &'a FatFileSystem<'m, S>,
         parent_entry: &DirectoryEntry,
         attribute: Attributes,
         name: &str,
@@ -245,7 +285,12 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         file_size: u32,
     ) -> FatFileSystemResult<DirectoryEntry> {
         let is_special_entry = name == "." || name == "..";
-        let mut count: u32 = 1;
+        let lfn_count = if is_special_entry {
+            0
+        } else {
+            (name.len() as u32 + 12) / 13
+        };
+        let count: u32 = lfn_count + 1;
 
         let mut free_entries_iter = Self::allocate_entries(parent_entry, fs, count)?;
 
@@ -256,7 +301,6 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
             let mut context: ShortFileNameContext = ShortFileNameContext::default();
             short_file_name = ShortFileName::from_unformated_str(&mut context, name);
 
-            let lfn_count = (name.len() as u32 + 12) / 13;
             let sfn_checksum = ShortFileName::checksum_lfn(&short_file_name.as_bytes());
 
             for index in 0..lfn_count {
@@ -279,8 +323,6 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
                 lfn_entry.set_lfn_checksum(sfn_checksum as u8);
                 lfn_entry.flush(fs)?;
             }
-
-            count += lfn_count;
         } else {
             short_file_name = ShortFileName::from_slice(name.as_bytes());
         }
@@ -291,6 +333,10 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         sfn_entry.set_cluster(cluster);
         sfn_entry.set_attribute(attribute);
 
+        if let Some(now) = fs.now() {
+            sfn_entry.set_all_datetimes(&now);
+        }
+
         sfn_entry.set_short_name(&short_file_name);
         sfn_entry.flush(fs)?;
 
@@ -303,7 +349,7 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         let first_raw_dir_entry = first_raw_dir_entry.unwrap();
 
         let is_in_old_root_directory = match fs.boot_record.fat_type {
-            FatFsType::Fat12 | FatFsType::Fat16 => cluster.0 == 0,
+            FatFsType::Fat12 | FatFsType::Fat16 => parent_entry.raw_info.is_none(),
             _ => false,
         };
 
@@ -327,7 +373,7 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
     /// - `ReadFailed`
     ///    - When the directory is not empty.
     fn delete_dir_entry(
-        fs: &'a FatFileSystem<S>,
+        fs: &'a FatFileSystem<'m, S>,
         dir_entry: &DirectoryEntry,
     ) -> FatFileSystemResult<()> {
         if let Some(raw_info) = dir_entry.raw_info {
@@ -358,6 +404,8 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
 
     /// Create a directory with the given name.
     pub(crate) fn create_directory(&mut self, name: &str) -> FatFileSystemResult<()> {
+        self.fs.check_read_write()?;
+
         if name.len() > DirectoryEntry::MAX_FILE_NAME_LEN {
             return Err(FatError::PathTooLong);
         }
@@ -405,7 +453,8 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
 
         let entry = new_entry_res?;
 
-        let res = Self::create_special_directory_entries(self.fs, &entry);
+        let res =
+            Self::create_special_directory_entries(self.fs, &entry, self.child_parent_cluster());
 
         if let Err(err) = res {
             // If it fail here, this can be catastrophic but at least we tried our best.
@@ -418,53 +467,47 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
     }
 
     /// Create directory special entries (".", "..")
+    ///
+    /// `parent_cluster` is the first cluster of the parent directory, 0 for the root directory.
     pub fn create_special_directory_entries(
-        fs: &'a FatFileSystem<S>,
-        parent_entry: &DirectoryEntry,
+        fs: &'a FatFileSystem<'m, S>,
+        entry: &DirectoryEntry,
+        parent_cluster: Cluster,
     ) -> FatFileSystemResult<()> {
-        let res = Self::create_dir_entry(
+        Self::create_dir_entry(
             fs,
-            parent_entry,
+            entry,
             Attributes::new(Attributes::DIRECTORY),
             ".",
-            parent_entry.start_cluster,
+            entry.start_cluster,
             0,
-        );
-
-        if let Err(err) = res {
-            return Err(err);
-        }
-
-        let raw_info_opt = parent_entry.raw_info;
-
-        let parent_cluster = if let Some(raw_info) = raw_info_opt {
-            if raw_info.parent_cluster == fs.get_root_directory().dir_info.start_cluster {
-                Cluster(0)
-            } else {
-                raw_info.parent_cluster
-            }
-        } else {
-            fs.get_root_directory().dir_info.start_cluster
-        };
+        )?;
 
-        let res = Self::create_dir_entry(
+        Self::create_dir_entry(
             fs,
-            parent_entry,
+            entry,
             Attributes::new(Attributes::DIRECTORY),
             "..",
             parent_cluster,
             0,
-        );
-
-        if let Err(err) = res {
-            return Err(err);
-        }
+        )?;
 
         Ok(())
     }
 
+    /// Get the cluster the ".." entries of the child directories point to.
+    fn child_parent_cluster(&self) -> Cluster {
+        if self.is_root_directory() {
+            Cluster(0)
+        } else {
+            self.dir_info.start_cluster
+        }
+    }
+
     /// Create a file with the given name.
     pub fn create_file(&mut self, name: &str) -> FatFileSystemResult<()> {
+        self.fs.check_read_write()?;
+
         if name.len() > DirectoryEntry::MAX_FILE_NAME_LEN {
             return Err(FatError::PathTooLong);
         }
@@ -487,7 +530,7 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         Self::create_dir_entry(
             self.fs,
             &self.dir_info,
-            Attributes::new(0),
+            Attributes::new(Attributes::ARCHIVE),
             lowercase_name.as_str(),
             Cluster(0),
             0,
@@ -509,6 +552,7 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
     /// Delete a directory or a file with the given name.
     fn unlink(self, name: &str, is_dir: bool) -> FatFileSystemResult<()> {
         let fs = self.fs;
+        fs.check_read_write()?;
 
         let dir_entry = self.find_entry(name)?;
 
@@ -516,6 +560,8 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
             return Err(FatError::AccessDenied);
         }
 
+        fs.check_writable(dir_entry.attribute)?;
+
         if dir_entry.attribute.is_directory() != is_dir {
             if is_dir {
                 return Err(FatError::NotADirectory);
@@ -550,6 +596,8 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         new_name: &str,
         is_dir: bool,
     ) -> FatFileSystemResult<()> {
+        self.fs.check_writable(dir_entry.attribute)?;
+
         if new_name.len() > DirectoryEntry::MAX_FILE_NAME_LEN {
             return Err(FatError::PathTooLong);
         }
@@ -608,10 +656,15 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
 
             let mut sfn_entry = entries_iter.next(self.fs).unwrap()?;
             sfn_entry.set_short_name(&short_file_name);
+            if let Some(now) = self.fs.now() {
+                sfn_entry.set_modified(&now);
+            }
             sfn_entry.flush(self.fs)?;
             return Ok(());
         }
 
+        let old_sfn_entry = old_raw_info.get_dir_entry(self.fs)?;
+
         let new_entry = Self::create_dir_entry(
             self.fs,
             &self.dir_info,
@@ -621,40 +674,20 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
             dir_entry.file_size,
         )?;
 
-        if is_dir {
-            let new_raw_info = new_entry.raw_info.unwrap();
-
-            let old_parent_cluster = if old_raw_info.parent_cluster
-                == self.fs.get_root_directory().dir_info.start_cluster
-            {
-                Cluster(0)
-            } else {
-                old_raw_info.parent_cluster
-            };
+        // Moving an entry doesn't change its creation date.
+        if self.fs.now().is_some() {
+            let mut new_sfn_entry = new_entry.raw_info.unwrap().get_dir_entry(self.fs)?;
+            new_sfn_entry.copy_creation_datetime(&old_sfn_entry);
+            new_sfn_entry.flush(self.fs)?;
+        }
 
-            let new_parent_cluster = if new_raw_info.parent_cluster
-                == self.fs.get_root_directory().dir_info.start_cluster
-            {
-                Cluster(0)
-            } else {
-                new_raw_info.parent_cluster
-            };
+        // The ".." entry of a moved directory points to its new parent.
+        if is_dir {
+            let mut iter = FatDirEntryIterator::new(self.fs, new_entry.start_cluster, 0, 0, false);
 
-            // We aren't in the same dir?
-            if new_parent_cluster != old_parent_cluster {
-                let mut iter = FatDirEntryIterator::new(
-                    self.fs,
-                    new_entry.start_cluster,
-                    0,
-                    0,
-                    new_raw_info.in_old_fat_root_directory,
-                );
-
-                // TODO: is that always the second entry?
-                let mut second_entry = iter.nth(self.fs, 2).unwrap()?;
-                second_entry.set_cluster(new_parent_cluster);
-                second_entry.flush(self.fs)?;
-            }
+            let mut dot_dot_entry = iter.nth(self.fs, 1).unwrap()?;
+            dot_dot_entry.set_cluster(self.child_parent_cluster());
+            dot_dot_entry.flush(self.fs)?;
         }
 
         Self::delete_dir_entry(self.fs, &dir_entry)?;
@@ -662,7 +695,7 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
     }
 }
 
-impl<'a, S: StorageDevice> Directory<'a, S> {
+impl<'a, 'm, S: StorageDevice> Directory<'a, 'm, S> {
     /// Create a raw directory entry iterator from the directory.
     pub(crate) fn fat_dir_entry_iter(&self) -> FatDirEntryIterator {
         FatDirEntryIterator::from_directory(self)
@@ -675,7 +708,7 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
 
 impl FatDirEntryIterator {
     /// Create a raw directory entry iterator from a directory.
-    pub fn from_directory<S: StorageDevice>(root: &Directory<'_, S>) -> Self {
+    pub fn from_directory<S: StorageDevice>(root: &Directory<'_, '_, S>) -> Self {
         let cluster = root.dir_info.start_cluster;
         let fs = &root.fs;
 
@@ -700,17 +733,99 @@ impl FatDirEntryIterator {
 
 impl DirectoryEntryIterator {
     /// Create a directory entry iterator from a directory.
-    pub fn new<S: StorageDevice>(root: &Directory<'_, S>) -> Self {
+    pub fn new<S: StorageDevice>(root: &Directory<'_, '_, S>) -> Self {
         DirectoryEntryIterator {
             raw_iter: FatDirEntryIterator::from_directory(root),
         }
     }
 }
 
-impl File {
+impl<'m> File<'m> {
     /// Create a file from a filesystem reference and a directory entry.
     pub fn from_entry(file_info: DirectoryEntry) -> Self {
-        File { file_info }
+        File {
+            file_info,
+            cursor: ChainCursor::new(),
+        }
+    }
+
+    /// Use the given memory to keep the extents of the file in memory and give back the previous memory if any.
+    ///
+    /// The extent map is filled as the file is accessed and allows to find any cluster it covers without reading the FAT.
+    /// Without extent map, only the last cluster accessed is kept.
+    pub fn set_extent_map(
+        &mut self,
+        extents: &'m mut [FileExtent],
+    ) -> Option<&'m mut [FileExtent]> {
+        self.cursor.set_extents(extents)
+    }
+
+    /// Check that the file can be modified.
+    ///
+    /// The attributes are read back from the directory entry, as they might have changed since the file was opened.
+    fn check_writable<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+    ) -> FatFileSystemResult<()> {
+        if let Some(raw_file_info) = self.file_info.raw_info {
+            self.file_info.attribute = raw_file_info.get_dir_entry(fs)?.attribute();
+        }
+
+        fs.check_writable(self.file_info.attribute)
+    }
+
+    /// Forget the known clusters of the file if a cluster chain of the filesystem was shortened since they were recorded.
+    ///
+    /// The start cluster and the size of the file are read back from its directory entry, as another instance of the file or a repair might have changed them.
+    fn sync_chain<S: StorageDevice>(&mut self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        let generation = fs.chain_generation();
+
+        if !self.cursor.is_stale(generation) {
+            return Ok(());
+        }
+
+        if let Some(raw_file_info) = self.file_info.raw_info {
+            let raw_dir_entry = raw_file_info.get_dir_entry(fs)?;
+
+            self.file_info.start_cluster = raw_dir_entry.get_cluster();
+            self.file_info.file_size = raw_dir_entry.get_file_size();
+        }
+
+        self.cursor.reset();
+        self.cursor.set_generation(generation);
+
+        Ok(())
+    }
+
+    /// Get the cluster at the given index in the cluster chain of the file.
+    ///
+    /// The chain is followed from the closest known cluster. Return None if the chain is shorter.
+    fn cluster_at<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        index: u32,
+    ) -> FatFileSystemResult<Option<Cluster>> {
+        let (mut current_index, mut cluster) = match self.cursor.closest(index) {
+            Some(position) => position,
+            None if self.file_info.start_cluster.0 == 0 => return Ok(None),
+            None => (0, self.file_info.start_cluster),
+        };
+
+        self.cursor.record(current_index, cluster);
+
+        while current_index < index {
+            match table::FatValue::get(fs, cluster)? {
+                table::FatValue::Data(next_cluster) => {
+                    cluster = Cluster(next_cluster);
+                    current_index += 1;
+                }
+                _ => return Ok(None),
+            }
+
+            self.cursor.record(current_index, cluster);
+        }
+
+        Ok(Some(cluster))
     }
 
     /// Check offset range for a given fat_type.
@@ -728,85 +843,114 @@ impl File {
         Ok(())
     }
 
-    /// Read at a given offset of the file into a given buffer.
-    pub fn read<S: StorageDevice>(
+    /// Call the given function for each run of physically contiguous clusters holding the given range of the file.
+    ///
+    /// The function receives the offset of the run on the storage device and the matching range relative to the start of the file range.
+    /// Return the size of the range covered by the cluster chain.
+    fn for_each_contiguous_run<S, F>(
         &mut self,
         fs: &FatFileSystem<S>,
         offset: u64,
-        buf: &mut [u8],
-    ) -> FatFileSystemResult<u64> {
-        if Self::check_range(offset, fs.boot_record.fat_type).is_err() {
-            return Ok(0);
-        }
-
-        if offset >= u64::from(self.file_info.file_size) {
-            return Ok(0);
-        }
+        len: usize,
+        mut function: F,
+    ) -> FatFileSystemResult<usize>
+    where
+        S: StorageDevice,
+        F: FnMut(u64, Range<usize>) -> FatFileSystemResult<()>,
+    {
+        let cluster_size = u64::from(fs.cluster_size());
+        let mut cluster_index = (offset / cluster_size) as u32;
+        let mut cluster_offset = offset % cluster_size;
+        let mut pending_cluster = self.cluster_at(fs, cluster_index)?;
+        let mut done = 0;
+
+        while done < len {
+            let first_cluster = match pending_cluster.take() {
+                Some(cluster) => cluster,
+                None => break,
+            };
 
-        let blocks_per_cluster = u64::from(fs.boot_record.blocks_per_cluster());
-        let block_size = u64::from(fs.boot_record.bytes_per_block());
-        let mut cluster_offset_iterator =
-            ClusterOffsetIter::new(fs, self.file_info.start_cluster, Some(offset / block_size));
+            let remaining = (len - done) as u64;
+            let mut run_size = cluster_size - cluster_offset;
+            let mut last_cluster = first_cluster;
 
-        let mut read_size = 0u64;
+            // Extend the run while the following clusters are needed and physically contiguous.
+            while run_size < remaining {
+                cluster_index += 1;
 
-        while read_size < buf.len() as u64 {
-            let cluster_opt = cluster_offset_iterator.next(fs);
-            if cluster_opt.is_none() {
-                break;
+                match self.cluster_at(fs, cluster_index)? {
+                    Some(cluster) if cluster.0 == last_cluster.0 + 1 => {
+                        last_cluster = cluster;
+                        run_size += cluster_size;
+                    }
+                    cluster_opt => {
+                        pending_cluster = cluster_opt;
+                        break;
+                    }
+                }
             }
 
-            let cluster = cluster_opt.unwrap();
-
-            let mut buf_limit = block_size;
+            let size = core::cmp::min(run_size, remaining) as usize;
+            let device_offset =
+                fs.partition_start + first_cluster.to_data_bytes_offset(fs)? + cluster_offset;
 
-            let bytes_left = u64::from(self.file_info.file_size) - read_size - offset;
+            function(device_offset, done..done + size)?;
 
-            if bytes_left == 0 {
-                break;
-            } else if bytes_left < buf_limit {
-                buf_limit = bytes_left;
-            }
+            done += size;
+            cluster_offset = 0;
+        }
 
-            let buf_slice = &mut buf[read_size as usize..];
+        Ok(done)
+    }
 
-            if buf_limit > buf_slice.len() as u64 {
-                buf_limit = buf_slice.len() as u64;
-            }
+    /// Read at a given offset of the file into a given buffer.
+    pub fn read<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> FatFileSystemResult<u64> {
+        if Self::check_range(offset, fs.boot_record.fat_type).is_err() {
+            return Ok(0);
+        }
 
-            let mut buf_slice = &mut buf_slice[..buf_limit as usize];
+        self.sync_chain(fs)?;
 
-            let cluster_offset = cluster.to_data_bytes_offset(fs)?;
+        if offset >= u64::from(self.file_info.file_size) {
+            return Ok(0);
+        }
 
-            fs.storage_device
-                .lock()
-                .read(
-                    fs.partition_start
-                        + cluster_offset
-                        + (offset % block_size)
-                        + (read_size % (block_size * blocks_per_cluster)),
-                    &mut buf_slice,
-                )
-                .or(Err(FatError::ReadFailed))?;
+        let bytes_left = u64::from(self.file_info.file_size) - offset;
+        let read_len = core::cmp::min(bytes_left, buf.len() as u64) as usize;
 
-            read_size += buf_slice.len() as u64;
-        }
+        let read_size =
+            self.for_each_contiguous_run(fs, offset, read_len, |device_offset, range| {
+                fs.storage_device
+                    .lock()
+                    .read(device_offset, &mut buf[range])
+                    .or(Err(FatError::ReadFailed))
+            })?;
 
-        Ok(read_size)
+        Ok(read_size as u64)
     }
 
-    /// Write the given buffer at a given offset of the file.
+    /// Write the given buffer at a given offset of the file and return the count of bytes written.
+    ///
+    /// Fails with `InvalidPartition` if the cluster chain of the file is too short to hold the whole buffer.
     pub fn write<S: StorageDevice>(
         &mut self,
         fs: &FatFileSystem<S>,
         offset: u64,
         buf: &[u8],
         appendable: bool,
-    ) -> FatFileSystemResult<()> {
+    ) -> FatFileSystemResult<u64> {
+        self.check_writable(fs)?;
         Self::check_range(offset, fs.boot_record.fat_type)?;
+        self.sync_chain(fs)?;
 
         let min_size = offset + buf.len() as u64;
-        if min_size > u64::from(self.file_info.file_size) {
+        let need_resize = min_size > u64::from(self.file_info.file_size);
+        if need_resize {
             if appendable {
                 self.set_len(fs, min_size)?;
             } else {
@@ -814,44 +958,135 @@ impl File {
             }
         }
 
-        let blocks_per_cluster = u64::from(fs.boot_record.blocks_per_cluster());
-        let block_size = u64::from(fs.boot_record.bytes_per_block());
-        let mut cluster_offset_iterator =
-            ClusterOffsetIter::new(fs, self.file_info.start_cluster, Some(offset / block_size));
+        let write_size =
+            self.for_each_contiguous_run(fs, offset, buf.len(), |device_offset, range| {
+                fs.storage_device
+                    .lock()
+                    .write(device_offset, &buf[range])
+                    .or(Err(FatError::WriteFailed))
+            })?;
+
+        // The cluster chain is shorter than the file size.
+        if write_size < buf.len() {
+            return Err(FatError::InvalidPartition);
+        }
 
-        let mut write_size = 0u64;
+        // set_len already updated the modification date when the file was extended.
+        if !need_resize {
+            self.touch(fs)?;
+        }
 
-        while write_size < buf.len() as u64 {
-            let cluster_opt = cluster_offset_iterator.next(fs);
-            if cluster_opt.is_none() {
-                break;
-            }
+        Ok(write_size as u64)
+    }
 
-            let cluster = cluster_opt.unwrap();
+    /// Mark the file as modified by setting the archive attribute and by updating the last access and last modification dates if the filesystem has a clock.
+    fn touch<S: StorageDevice>(&mut self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        let now = fs.now();
 
-            let mut buf_limit = block_size;
+        // Nothing to update
+        if now.is_none() && self.file_info.attribute.is_archive() {
+            return Ok(());
+        }
 
-            let buf_slice = &buf[write_size as usize..];
+        let raw_file_info = self.file_info.raw_info.ok_or(FatError::Custom {
+            name: "Raw Info is missing ON A FILE",
+        })?;
+        let mut raw_dir_entry = raw_file_info.get_dir_entry(fs)?;
+
+        raw_dir_entry.set_attribute(raw_dir_entry.attribute().set(Attributes::ARCHIVE));
+        if let Some(now) = now {
+            raw_dir_entry.set_modified(&now);
+        }
+        raw_dir_entry.flush(fs)?;
+
+        self.file_info.attribute = raw_dir_entry.attribute();
+        self.file_info.last_access_timestamp = raw_dir_entry.get_last_access_timestamp();
+        self.file_info.last_modification_timestamp = raw_dir_entry.get_modification_timestamp();
+
+        Ok(())
+    }
+
+    /// Get the count of clusters needed to hold the given length.
+    fn cluster_count_for_len<S: StorageDevice>(fs: &FatFileSystem<S>, len: u64) -> u32 {
+        let cluster_size = u64::from(fs.cluster_size());
+
+        (utils::align_up(len, cluster_size) / cluster_size) as u32
+    }
+
+    /// Get the count of clusters allocated to the file and the last one.
+    ///
+    /// More clusters than needed by the file size can be allocated if the file was preallocated.
+    fn allocated_clusters<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+    ) -> FatFileSystemResult<(u32, Option<Cluster>)> {
+        let (mut last_index, mut last_cluster) = match self.cursor.closest(u32::MAX) {
+            Some(position) => position,
+            None => {
+                let start_cluster = self.file_info.start_cluster;
+
+                // Empty files truncated by older versions can still point to their freed first cluster.
+                if start_cluster.0 == 0
+                    || table::FatValue::get(fs, start_cluster)? == table::FatValue::Free
+                {
+                    return Ok((0, None));
+                }
 
-            if buf_limit > buf_slice.len() as u64 {
-                buf_limit = buf_slice.len() as u64;
+                self.cursor.record(0, start_cluster);
+                (0, start_cluster)
             }
+        };
 
-            let buf_slice = &buf_slice[..buf_limit as usize];
+        while let table::FatValue::Data(next_cluster) = table::FatValue::get(fs, last_cluster)? {
+            last_cluster = Cluster(next_cluster);
+            last_index += 1;
+            self.cursor.record(last_index, last_cluster);
+        }
 
-            let cluster_offset = cluster.to_data_bytes_offset(fs)?;
+        Ok((last_index + 1, Some(last_cluster)))
+    }
 
-            fs.storage_device
-                .lock()
-                .write(
-                    fs.partition_start
-                        + cluster_offset
-                        + (offset % block_size)
-                        + (write_size % (block_size * blocks_per_cluster)),
-                    &buf_slice,
-                )
-                .or(Err(FatError::WriteFailed))?;
-            write_size += buf_slice.len() as u64;
+    /// Reserve the clusters needed to hold the given length without changing the file size.
+    ///
+    /// The clusters are allocated contiguously if possible so writing up to this length doesn't need to allocate any cluster.
+    /// Preallocated clusters after the end of the file are kept until the file is truncated or deleted.
+    ///
+    /// # Note:
+    ///
+    /// Some filesystem checkers consider a cluster chain longer than the file size as an error and truncate it.
+    pub fn preallocate<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        len: u64,
+    ) -> FatFileSystemResult<()> {
+        self.check_writable(fs)?;
+        self.sync_chain(fs)?;
+
+        if len > 0xFFFF_FFFF {
+            return Err(FatError::NoSpaceLeft);
+        }
+
+        let needed_cluster_count = Self::cluster_count_for_len(fs, len);
+        let (allocated_cluster_count, last_cluster) = self.allocated_clusters(fs)?;
+
+        if needed_cluster_count <= allocated_cluster_count {
+            return Ok(());
+        }
+
+        let first_new_cluster =
+            fs.alloc_clusters(last_cluster, needed_cluster_count - allocated_cluster_count)?;
+
+        if last_cluster.is_none() {
+            let raw_file_info = self.file_info.raw_info.ok_or(FatError::Custom {
+                name: "Raw Info is missing ON A FILE",
+            })?;
+            let mut raw_dir_entry = raw_file_info.get_dir_entry(fs)?;
+
+            raw_dir_entry.set_cluster(first_new_cluster);
+            raw_dir_entry.flush(fs)?;
+
+            self.file_info.start_cluster = first_new_cluster;
+            self.cursor.reset();
         }
 
         Ok(())
@@ -863,6 +1098,9 @@ impl File {
         fs: &FatFileSystem<S>,
         size: u64,
     ) -> FatFileSystemResult<()> {
+        self.check_writable(fs)?;
+        self.sync_chain(fs)?;
+
         let current_len = u64::from(self.file_info.file_size);
         if size == current_len {
             return Ok(());
@@ -875,56 +1113,50 @@ impl File {
         })?;
         let mut raw_dir_entry = raw_file_info.get_dir_entry(fs)?;
 
-        let cluster_size = u64::from(
-            u16::from(fs.boot_record.blocks_per_cluster()) * fs.boot_record.bytes_per_block(),
-        );
-        let aligned_size = utils::align_up(size, cluster_size);
-        let aligned_current_len = utils::align_up(current_len, cluster_size);
+        let needed_cluster_count = Self::cluster_count_for_len(fs, size);
+        let (allocated_cluster_count, last_cluster) = self.allocated_clusters(fs)?;
 
-        let new_size;
+        if needed_cluster_count > allocated_cluster_count {
+            let first_new_cluster =
+                fs.alloc_clusters(last_cluster, needed_cluster_count - allocated_cluster_count)?;
 
-        if size > current_len {
-            let diff_size = size - current_len;
-            let mut cluster_to_add_count = (aligned_size - aligned_current_len) / cluster_size;
-            let mut start_cluster =
-                if self.file_info.start_cluster.0 == 0 || self.file_info.file_size == 0 {
-                    None
-                } else {
-                    Some(table::get_last_cluster(fs, self.file_info.start_cluster)?)
-                };
-
-            let mut last_cluster = start_cluster;
-            let need_update_cluster = start_cluster.is_none();
-
-            while cluster_to_add_count != 0 {
-                last_cluster = Some(fs.alloc_cluster(last_cluster)?);
-                if start_cluster.is_none() {
-                    start_cluster = last_cluster;
-                }
-                cluster_to_add_count -= 1;
+            if last_cluster.is_none() {
+                self.file_info.start_cluster = first_new_cluster;
+                self.cursor.reset();
             }
+        } else if needed_cluster_count < allocated_cluster_count && size < current_len {
+            // Truncating also frees the clusters preallocated after the end of the file.
+            if needed_cluster_count == 0 {
+                fs.free_cluster(self.file_info.start_cluster, None)?;
+                self.file_info.start_cluster = Cluster(0);
+                self.cursor.reset();
+                self.cursor.set_generation(fs.chain_generation());
+            } else {
+                let new_last_cluster = self
+                    .cluster_at(fs, needed_cluster_count - 1)?
+                    .ok_or(FatError::InvalidPartition)?;
+
+                if let table::FatValue::Data(to_remove) =
+                    table::FatValue::get(fs, new_last_cluster)?
+                {
+                    fs.free_cluster(Cluster(to_remove), Some(new_last_cluster))?;
+                }
 
-            new_size = self.file_info.file_size + diff_size as u32;
-            if need_update_cluster {
-                self.file_info.start_cluster = start_cluster.unwrap();
-            }
-        } else {
-            let diff_size = current_len - size;
-            let mut cluster_to_remove_count = (aligned_current_len - aligned_size) / cluster_size;
-
-            while cluster_to_remove_count != 0 {
-                let (last_cluster, previous_cluster) =
-                    table::get_last_and_previous_cluster(fs, self.file_info.start_cluster)?;
-                fs.free_cluster(last_cluster, previous_cluster)?;
-                cluster_to_remove_count -= 1;
+                self.cursor.truncate(needed_cluster_count);
+                self.cursor.set_generation(fs.chain_generation());
             }
-
-            new_size = self.file_info.file_size - diff_size as u32;
         }
-        // TODO: update modified date?
-        // BODY: We should update the modification date here at some point.
+
+        let new_size = size as u32;
         raw_dir_entry.set_cluster(self.file_info.start_cluster);
         raw_dir_entry.set_file_size(new_size);
+        raw_dir_entry.set_attribute(raw_dir_entry.attribute().set(Attributes::ARCHIVE));
+        self.file_info.attribute = raw_dir_entry.attribute();
+        if let Some(now) = fs.now() {
+            raw_dir_entry.set_modified(&now);
+            self.file_info.last_access_timestamp = raw_dir_entry.get_last_access_timestamp();
+            self.file_info.last_modification_timestamp = raw_dir_entry.get_modification_timestamp();
+        }
         raw_dir_entry.flush(fs)?;
 
         self.file_info.file_size = new_size;
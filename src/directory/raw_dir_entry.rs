@@ -145,6 +145,8 @@ impl FatDirEntry {
 
     /// Write the raw data buffer to disk.
     pub fn flush<S: StorageDevice>(&self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        fs.check_read_write()?;
+
         let is_in_old_root_directory = match fs.boot_record.fat_type {
             FatFsType::Fat12 | FatFsType::Fat16 => self.entry_cluster.0 == 0,
             _ => false,
@@ -156,7 +158,7 @@ impl FatDirEntry {
                 / u32::from(fs.boot_record.bytes_per_block());
             let root_dir_offset = root_dir_blocks * u32::from(fs.boot_record.bytes_per_block());
 
-            if self.entry_cluster_offset > u64::from(root_dir_offset) {
+            if self.entry_cluster_offset >= u64::from(root_dir_offset) {
                 Err(FatError::NoSpaceLeft)
             } else {
                 Ok(fs.first_data_offset - u64::from(root_dir_offset)
@@ -287,49 +289,81 @@ impl FatDirEntry {
         }
     }
 
-    /// Retrieve the creation datetime of this 8.3 entry.
-    pub fn get_creation_datetime(&self) -> FatDateTime {
+    /// Retrieve the creation datetime of this 8.3 entry or None if it isn't valid.
+    pub fn get_creation_datetime(&self) -> Option<FatDateTime> {
         let raw_time = u16::from_le_bytes(self.data[14..16].try_into().unwrap());
-        let seconds = ((raw_time & 0x1f) << 1) as u8;
-        let minutes = ((raw_time >> 5) & 0x3f) as u8;
-        let hour = ((raw_time >> 11) & 0x1f) as u8;
-
         let raw_date = u16::from_le_bytes(self.data[16..18].try_into().unwrap());
-        let day = (raw_date & 0x1f) as u8;
-        let month = ((raw_date >> 5) & 0xf) as u8;
-        let year = (raw_date >> 9) & 0x7f;
-        FatDateTime::new(
-            1980 + year,
-            month,
-            day,
-            hour,
-            minutes,
-            seconds,
-            self.data[13],
-        )
-    }
-
-    /// Retrieve the last access datetime of this 8.3 entry.
-    pub fn get_last_access_date(&self) -> FatDateTime {
+
+        FatDateTime::from_fat(raw_date, raw_time, self.data[13])
+    }
+
+    /// Set the creation datetime of this 8.3 entry.
+    pub fn set_creation_datetime(&mut self, datetime: &FatDateTime) {
+        self.data[13] = datetime.to_fat_time_fine();
+        self.data[14..16].copy_from_slice(&datetime.to_fat_time().to_le_bytes());
+        self.data[16..18].copy_from_slice(&datetime.to_fat_date().to_le_bytes());
+    }
+
+    /// Retrieve the last access date of this 8.3 entry or None if it isn't valid.
+    pub fn get_last_access_date(&self) -> Option<FatDateTime> {
         let raw_date = u16::from_le_bytes(self.data[18..20].try_into().unwrap());
-        let day = (raw_date & 0x1f) as u8;
-        let month = ((raw_date >> 5) & 0xf) as u8;
-        let year = (raw_date >> 9) & 0x7f;
-        FatDateTime::new(1980 + year, month, day, 0, 0, 0, 0)
+
+        FatDateTime::from_fat(raw_date, 0, 0)
     }
 
-    /// Retrieve the last modification datetime of this 8.3 entry.
-    pub fn get_modification_datetime(&self) -> FatDateTime {
-        let raw_time = u16::from_le_bytes(self.data[22..24].try_into().unwrap());
-        let seconds = ((raw_time & 0x1f) << 1) as u8;
-        let minutes = ((raw_time >> 5) & 0x3f) as u8;
-        let hour = ((raw_time >> 11) & 0x1f) as u8;
+    /// Set the last access date of this 8.3 entry.
+    pub fn set_last_access_date(&mut self, datetime: &FatDateTime) {
+        self.data[18..20].copy_from_slice(&datetime.to_fat_date().to_le_bytes());
+    }
 
+    /// Retrieve the last modification datetime of this 8.3 entry or None if it isn't valid.
+    pub fn get_modification_datetime(&self) -> Option<FatDateTime> {
+        let raw_time = u16::from_le_bytes(self.data[22..24].try_into().unwrap());
         let raw_date = u16::from_le_bytes(self.data[24..26].try_into().unwrap());
-        let day = (raw_date & 0x1f) as u8;
-        let month = ((raw_date >> 5) & 0xf) as u8;
-        let year = (raw_date >> 9) & 0x7f;
-        FatDateTime::new(1980 + year, month, day, hour, minutes, seconds, 0)
+
+        FatDateTime::from_fat(raw_date, raw_time, 0)
+    }
+
+    /// Set the last modification datetime of this 8.3 entry.
+    pub fn set_modification_datetime(&mut self, datetime: &FatDateTime) {
+        self.data[22..24].copy_from_slice(&datetime.to_fat_time().to_le_bytes());
+        self.data[24..26].copy_from_slice(&datetime.to_fat_date().to_le_bytes());
+    }
+
+    /// Return the creation UNIX timestamp of this 8.3 entry or zero if it isn't valid.
+    pub fn get_creation_timestamp(&self) -> u64 {
+        self.get_creation_datetime()
+            .map_or(0, |datetime| datetime.to_unix_time())
+    }
+
+    /// Return the last access UNIX timestamp of this 8.3 entry or zero if it isn't valid.
+    pub fn get_last_access_timestamp(&self) -> u64 {
+        self.get_last_access_date()
+            .map_or(0, |datetime| datetime.to_unix_time())
+    }
+
+    /// Return the last modification UNIX timestamp of this 8.3 entry or zero if it isn't valid.
+    pub fn get_modification_timestamp(&self) -> u64 {
+        self.get_modification_datetime()
+            .map_or(0, |datetime| datetime.to_unix_time())
+    }
+
+    /// Copy the raw creation datetime of another 8.3 entry.
+    pub fn copy_creation_datetime(&mut self, other: &FatDirEntry) {
+        self.data[13..18].copy_from_slice(&other.data[13..18]);
+    }
+
+    /// Set the creation, last access and last modification datetimes of this 8.3 entry.
+    pub fn set_all_datetimes(&mut self, datetime: &FatDateTime) {
+        self.set_creation_datetime(datetime);
+        self.set_last_access_date(datetime);
+        self.set_modification_datetime(datetime);
+    }
+
+    /// Set the last access and last modification datetimes of this 8.3 entry.
+    pub fn set_modified(&mut self, datetime: &FatDateTime) {
+        self.set_last_access_date(datetime);
+        self.set_modification_datetime(datetime);
     }
 }
 
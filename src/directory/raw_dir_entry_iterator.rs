@@ -36,21 +36,18 @@ impl FatDirEntryIterator {
         offset: u64,
         in_root_directory: bool,
     ) -> Self {
+        // The cluster offset iterator works with block indexes.
+        let block_index = cluster_offset / u64::from(fs.boot_record.bytes_per_block());
+
         let cluster_iter = if in_root_directory {
             match fs.boot_record.fat_type {
                 FatFsType::Fat12 | FatFsType::Fat16 => None,
-                FatFsType::Fat32 => Some(ClusterOffsetIter::new(
-                    fs,
-                    start_cluster,
-                    Some(cluster_offset),
-                )),
+                FatFsType::Fat32 => {
+                    Some(ClusterOffsetIter::new(fs, start_cluster, Some(block_index)))
+                }
             }
         } else {
-            Some(ClusterOffsetIter::new(
-                fs,
-                start_cluster,
-                Some(cluster_offset),
-            ))
+            Some(ClusterOffsetIter::new(fs, start_cluster, Some(block_index)))
         };
 
         FatDirEntryIterator {
@@ -103,7 +100,7 @@ impl<S: StorageDevice> FileSystemIterator<S> for FatDirEntryIterator {
             let root_dir_offset =
                 root_dir_blocks * u32::from(filesystem.boot_record.bytes_per_block());
 
-            if self.cluster_offset > u64::from(root_dir_offset) {
+            if self.cluster_offset >= u64::from(root_dir_offset) {
                 None
             } else {
                 Some(
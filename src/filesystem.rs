@@ -1,8 +1,14 @@
 //! FAT Filesystem.
 
 use super::attribute::Attributes;
+use super::bitmap::AllocationBitmap;
+use super::cache::{BlockCache, CacheBlock};
+use super::check::{self, CheckOptions, CheckReport, Problem, Repair, RepairOptions};
 use super::cluster::Cluster;
-use super::directory::{dir_entry::DirectoryEntry, Directory, File};
+use super::datetime::{FatDateTime, TimeProvider};
+use super::directory::{dir_entry::DirectoryEntry, raw_dir_entry::FatDirEntry, Directory, File};
+use super::handle::{FileHandle, OpenOptions};
+use super::name::{ShortFileName, VolumeLabel};
 use super::offset_iter::ClusterOffsetIter;
 use super::table;
 use super::table::FatValue;
@@ -33,17 +39,14 @@ struct FatFileSystemInfo {
 }
 
 impl FatFileSystemInfo {
-    /// Import FS Info from a FAT32 filesystem.
-    ///
-    /// Note:
+    /// Read the FS Info block of a FAT32 filesystem.
     ///
-    /// This function guarantee does sanity checks on the values it reads from the filesystem.
-    fn from_fs<S: StorageDevice>(fs: &FatFileSystem<S>) -> FatFileSystemResult<Self> {
+    /// Returns None if the block doesn't have valid signatures.
+    fn read_block<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+    ) -> FatFileSystemResult<Option<[u8; crate::MINIMAL_BLOCK_SIZE]>> {
         let mut block = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
 
-        let mut last_cluster = 0xFFFF_FFFF;
-        let mut free_cluster = 0xFFFF_FFFF;
-
         fs.storage_device
             .lock()
             .read(
@@ -59,6 +62,22 @@ impl FatFileSystemInfo {
             && &block[0x1e4..0x1e8] == b"rrAa"
             && u16::from_le_bytes(block[0x1fe..0x200].try_into().unwrap()) == 0xAA55
         {
+            Ok(Some(block))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Import FS Info from a FAT32 filesystem.
+    ///
+    /// Note:
+    ///
+    /// This function guarantee does sanity checks on the values it reads from the filesystem.
+    fn from_fs<S: StorageDevice>(fs: &FatFileSystem<S>) -> FatFileSystemResult<Self> {
+        let mut last_cluster = 0xFFFF_FFFF;
+        let mut free_cluster = 0xFFFF_FFFF;
+
+        if let Some(block) = Self::read_block(fs)? {
             // check cluster sanity
             let fs_last_cluster = u32::from_le_bytes(block[0x1ec..0x1f0].try_into().unwrap());
             if fs_last_cluster >= 2 && fs_last_cluster < fs.boot_record.cluster_count {
@@ -80,6 +99,8 @@ impl FatFileSystemInfo {
 
     /// Flush the FS Info to the disk on FAT32 filesystems.
     fn flush<S: StorageDevice>(&self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        fs.check_read_write()?;
+
         if fs.boot_record.fat_type != FatFsType::Fat32 {
             return Ok(());
         }
@@ -109,11 +130,80 @@ impl FatFileSystemInfo {
     }
 }
 
+/// Represent the geometry and usage information of a FAT filesystem.
+#[derive(Clone, Copy, Debug)]
+pub struct VolumeInfo {
+    /// The type of FAT filesystem.
+    pub fat_type: FatFsType,
+
+    /// The volume serial number or None if the boot record doesn't have one.
+    pub volume_id: Option<u32>,
+
+    /// The OEM name stored in the boot record.
+    pub oem_name: [u8; 8],
+
+    /// The media type of the filesystem.
+    pub media_type: u8,
+
+    /// The amount of bytes per block.
+    pub bytes_per_block: u16,
+
+    /// The amount of blocks per cluster.
+    pub blocks_per_cluster: u8,
+
+    /// The total count of blocks of the filesystem.
+    pub total_blocks: u32,
+
+    /// The count of reserved blocks at the start of the filesystem.
+    pub reserved_block_count: u16,
+
+    /// The count of FATs.
+    pub fats_count: u8,
+
+    /// The size in blocks of one FAT.
+    pub fat_size: u32,
+
+    /// The number of childs in the root directory (zero on FAT32).
+    pub root_dir_childs_count: u16,
+
+    /// The count of clusters in the data region.
+    pub total_clusters: u32,
+
+    /// The count of free clusters in the data region.
+    pub free_clusters: u32,
+
+    /// The cluster after which the search of a free cluster starts (FS Info hint) or None if unknown.
+    pub next_free_cluster_hint: Option<u32>,
+
+    /// The block index of the FS Info structure (FAT32 only).
+    pub fs_info_block: Option<u16>,
+
+    /// The block index of the backup boot record (FAT32 only).
+    pub backup_boot_record_block: Option<u16>,
+}
+
+impl VolumeInfo {
+    /// The size of a cluster in bytes.
+    pub fn cluster_size(&self) -> u32 {
+        u32::from(self.bytes_per_block) * u32::from(self.blocks_per_cluster)
+    }
+
+    /// The size of the data region in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        u64::from(self.total_clusters) * u64::from(self.cluster_size())
+    }
+
+    /// The amount of free bytes in the data region.
+    pub fn free_bytes(&self) -> u64 {
+        u64::from(self.free_clusters) * u64::from(self.cluster_size())
+    }
+}
+
 /// Represent a FAT filesystem.
 #[allow(dead_code)]
-pub struct FatFileSystem<S: StorageDevice> {
-    /// The device of the filesystem.
-    pub(crate) storage_device: Mutex<S>,
+pub struct FatFileSystem<'m, S: StorageDevice> {
+    /// The device of the filesystem, accessed through the block cache.
+    pub(crate) storage_device: Mutex<BlockCache<'m, S>>,
 
     /// The block index of the start of the partition of this filesystem.
     pub(crate) partition_start: u64,
@@ -127,11 +217,29 @@ pub struct FatFileSystem<S: StorageDevice> {
     /// The volume information of the filesystem.
     pub(crate) boot_record: FatVolumeBootRecord,
 
+    /// The volume label of the boot record, kept up to date when the label is changed.
+    boot_record_label: Mutex<Option<[u8; 11]>>,
+
     /// The extra infos of the filesystem.
     fat_info: FatFileSystemInfo,
+
+    /// The clock used to stamp directory entries. Entries aren't stamped if None.
+    time_provider: Option<&'m dyn TimeProvider>,
+
+    /// If true, entries with the read only attribute can be modified.
+    ignore_read_only: bool,
+
+    /// If true, the storage device is never written.
+    read_only: bool,
+
+    /// The bitmap of the used clusters, kept in sync with the FAT.
+    pub(crate) allocation_bitmap: Mutex<AllocationBitmap<'m>>,
+
+    /// Incremented each time clusters are removed from a cluster chain, files drop the cluster positions they know when it changes.
+    chain_generation: AtomicU32,
 }
 
-impl<S: StorageDevice> FatFileSystem<S> {
+impl<'m, S: StorageDevice> FatFileSystem<'m, S> {
     /// Create a new instance of FatFileSystem.
     pub(crate) fn new(
         storage_device: S,
@@ -139,17 +247,26 @@ impl<S: StorageDevice> FatFileSystem<S> {
         first_data_offset: u64,
         partition_size: u64,
         boot_record: FatVolumeBootRecord,
-    ) -> FatFileSystemResult<FatFileSystem<S>> {
+    ) -> FatFileSystemResult<FatFileSystem<'m, S>> {
         let fs = FatFileSystem {
-            storage_device: Mutex::new(storage_device),
+            storage_device: Mutex::new(BlockCache::new(
+                storage_device,
+                usize::from(boot_record.bytes_per_block()),
+            )),
             partition_start,
             first_data_offset,
             partition_size,
+            boot_record_label: Mutex::new(boot_record.volume_label()),
             boot_record,
             fat_info: FatFileSystemInfo {
                 last_cluster: AtomicU32::new(0xFFFF_FFFF),
                 free_cluster: AtomicU32::new(0xFFFF_FFFF),
             },
+            time_provider: None,
+            ignore_read_only: false,
+            read_only: false,
+            allocation_bitmap: Mutex::new(AllocationBitmap::empty()),
+            chain_generation: AtomicU32::new(0),
         };
         Ok(fs)
     }
@@ -170,8 +287,204 @@ impl<S: StorageDevice> FatFileSystem<S> {
         Ok(())
     }
 
+    /// Set the clock used to stamp the creation, modification and access times of directory entries.
+    ///
+    /// Without a clock, the times of the entries are left untouched.
+    pub fn set_time_provider(&mut self, time_provider: &'m dyn TimeProvider) {
+        self.time_provider = Some(time_provider);
+    }
+
+    /// Get the size in bytes of the memory needed by `set_cache` to cache the given count of blocks.
+    pub fn cache_memory_len(&self, block_count: usize) -> usize {
+        block_count * usize::from(self.boot_record.bytes_per_block())
+    }
+
+    /// Cache the blocks of the storage device in the given cache blocks, holding their content in the given memory.
+    ///
+    /// Each cache block uses `bytes_per_block` bytes of the memory, the count of cached blocks is limited by both the
+    /// count of cache blocks and the size of the memory.
+    /// The cache is write-back: modifications are only guaranteed to reach the storage device after a call to `flush`.
+    /// The blocks of the previous cache are written back before switching to the new one.
+    /// Giving an empty slice disables the cache.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut cache = [CacheBlock::EMPTY; 32];
+    /// let mut memory = [0x0u8; 32 * 512];
+    /// fs.set_cache(&mut cache, &mut memory)?;
+    /// ```
+    pub fn set_cache(
+        &mut self,
+        blocks: &'m mut [CacheBlock],
+        memory: &'m mut [u8],
+    ) -> FatFileSystemResult<()> {
+        self.storage_device
+            .lock()
+            .set_blocks(blocks, memory)
+            .or(Err(FatError::WriteFailed))
+    }
+
+    /// Write back all the modified blocks of the cache to the storage device.
+    pub fn flush(&self) -> FatFileSystemResult<()> {
+        self.storage_device
+            .lock()
+            .flush()
+            .or(Err(FatError::WriteFailed))
+    }
+
+    /// Get the size in bytes of an allocation bitmap covering every cluster of the filesystem.
+    pub fn allocation_bitmap_len(&self) -> usize {
+        (self.boot_record.cluster_count as usize + 7) / 8
+    }
+
+    /// Keep track of the used clusters in the given memory to avoid reading the FAT when allocating clusters.
+    ///
+    /// The bitmap is built from the FAT and the free cluster count is updated from it.
+    /// If the memory is smaller than `allocation_bitmap_len`, only the first clusters are covered by the bitmap
+    /// and the FAT is read for the others.
+    ///
+    /// Returns the memory of the previous allocation bitmap if any.
+    pub fn set_allocation_bitmap(
+        &mut self,
+        bits: &'m mut [u8],
+    ) -> FatFileSystemResult<Option<&'m mut [u8]>> {
+        let mut bitmap = AllocationBitmap::new(bits);
+        let mut free_clusters = 0;
+
+        table::for_each_cluster_value(self, |cluster, value| {
+            if value == FatValue::Free {
+                bitmap.set_used(cluster, false);
+                free_clusters += 1;
+            }
+        })?;
+
+        self.fat_info
+            .free_cluster
+            .store(free_clusters, Ordering::SeqCst);
+
+        let previous_bitmap = core::mem::replace(&mut *self.allocation_bitmap.lock(), bitmap);
+        let previous_bits = previous_bitmap.into_inner();
+
+        if previous_bits.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(previous_bits))
+        }
+    }
+
+    /// Get the size in bytes of the memory needed by `check`.
+    pub fn check_memory_len(&self) -> usize {
+        2 * self.allocation_bitmap_len()
+    }
+
+    /// Check the consistency of the filesystem without modifying it.
+    ///
+    /// Every directory is walked from the root directory and every cluster chain is followed through the FAT.
+    /// The given function is called for each problem found and a summary is returned.
+    /// The given memory must be at least `check_memory_len` bytes long.
+    pub fn check<F: FnMut(&Problem)>(
+        &self,
+        options: &CheckOptions,
+        memory: &mut [u8],
+        on_problem: F,
+    ) -> FatFileSystemResult<CheckReport> {
+        check::check(self, options, memory, on_problem)
+    }
+
+    /// Get the repair fixing a problem found by `check`, None if it can't be fixed.
+    ///
+    /// Planning a repair doesn't modify the filesystem so the repairs can be reviewed before being applied.
+    pub fn plan_repair(&self, problem: &Problem, options: &RepairOptions) -> Option<Repair> {
+        check::plan_repair(self, problem, options)
+    }
+
+    /// Apply a repair given by `plan_repair`.
+    ///
+    /// The repairs must be applied once the check is done, in the order of the problems they fix.
+    /// Running the check again afterward confirms that every problem is fixed.
+    pub fn apply_repair(&self, repair: &Repair) -> FatFileSystemResult<()> {
+        // Repairs can change any cluster chain or directory entry.
+        self.chain_generation.fetch_add(1, Ordering::SeqCst);
+
+        check::apply_repair(self, repair)
+    }
+
+    /// Get the generation of the cluster chains, changed each time clusters are removed from a chain.
+    pub(crate) fn chain_generation(&self) -> u32 {
+        self.chain_generation.load(Ordering::SeqCst)
+    }
+
+    /// Count the free clusters in the FAT and store the count in the FS Info structure.
+    pub(crate) fn update_free_cluster_count(&self) -> FatFileSystemResult<()> {
+        self.fat_info
+            .free_cluster
+            .store(table::get_free_cluster_count(self)?, Ordering::SeqCst);
+        self.fat_info.flush(self)
+    }
+
+    /// Get the free cluster count stored in the FS Info structure of a FAT32 filesystem.
+    ///
+    /// Returns None if the FS Info structure is invalid or if the count is unknown.
+    pub(crate) fn stored_free_cluster_count(&self) -> FatFileSystemResult<Option<u32>> {
+        Ok(FatFileSystemInfo::read_block(self)?
+            .map(|block| u32::from_le_bytes(block[0x1e8..0x1ec].try_into().unwrap()))
+            .filter(|free_cluster| *free_cluster != 0xFFFF_FFFF))
+    }
+
+    /// Allow or deny the modification of entries with the read only attribute.
+    ///
+    /// By default, writing, resizing, deleting or renaming a read only entry fails with `AccessDenied`.
+    pub fn set_ignore_read_only(&mut self, ignore_read_only: bool) {
+        self.ignore_read_only = ignore_read_only;
+    }
+
+    /// Mount the filesystem read only or read write.
+    ///
+    /// Opening a filesystem never writes to the storage device.
+    /// Once read only, every operation modifying the filesystem fails with `ReadOnlyFileSystem` before touching the storage device.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Return true if the filesystem is mounted read only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Check if the filesystem can be modified.
+    pub(crate) fn check_read_write(&self) -> FatFileSystemResult<()> {
+        if self.read_only {
+            return Err(FatError::ReadOnlyFileSystem);
+        }
+
+        Ok(())
+    }
+
+    /// Check if an entry with the given attributes can be modified.
+    pub(crate) fn check_writable(&self, attribute: Attributes) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
+        if attribute.is_read_only() && !self.ignore_read_only {
+            return Err(FatError::AccessDenied);
+        }
+
+        Ok(())
+    }
+
+    /// Get the size of a cluster in bytes.
+    pub(crate) fn cluster_size(&self) -> u32 {
+        u32::from(self.boot_record.blocks_per_cluster())
+            * u32::from(self.boot_record.bytes_per_block())
+    }
+
+    /// Get the current datetime from the clock of the filesystem if any.
+    pub(crate) fn now(&self) -> Option<FatDateTime> {
+        self.time_provider.map(|time_provider| time_provider.now())
+    }
+
     /// Get the root directory of the filesystem.
-    pub(crate) fn get_root_directory(&self) -> Directory<'_, S> {
+    pub(crate) fn get_root_directory(&self) -> Directory<'_, 'm, S> {
         let dir_info = DirectoryEntry {
             start_cluster: self.boot_record.root_dir_childs_cluster(),
             raw_info: None,
@@ -216,7 +529,7 @@ impl<S: StorageDevice> FatFileSystem<S> {
     /// Note:
     ///
     /// - an empty path is treated as a "/".
-    fn open_parent_directory(&self, path: &str) -> FatFileSystemResult<Directory<'_, S>> {
+    fn open_parent_directory(&self, path: &str) -> FatFileSystemResult<Directory<'_, 'm, S>> {
         let (parent_name, _) = utils::get_parent(path);
         self.open_directory(parent_name)
     }
@@ -228,7 +541,7 @@ impl<S: StorageDevice> FatFileSystem<S> {
     }
 
     /// Open a directory at the given path.
-    pub fn open_directory(&self, path: &str) -> FatFileSystemResult<Directory<'_, S>> {
+    pub fn open_directory(&self, path: &str) -> FatFileSystemResult<Directory<'_, 'm, S>> {
         if path == "/" || path == "" {
             Ok(self.get_root_directory())
         } else {
@@ -237,12 +550,56 @@ impl<S: StorageDevice> FatFileSystem<S> {
     }
 
     /// Open a file at the given path.
-    pub fn open_file(&self, path: &str) -> FatFileSystemResult<File> {
+    pub fn open_file(&self, path: &str) -> FatFileSystemResult<File<'static>> {
         self.get_root_directory().open_file(path)
     }
 
+    /// Open a file at the given path with the given options, returning a handle keeping track of the position in the file.
+    ///
+    /// # Errors:
+    ///
+    /// - `AccessDenied` if the file can't be written while the options require it, or if the options require the write access without giving it.
+    /// - `NotFound` if the file doesn't exist and the options don't allow to create it.
+    /// - `NotAFile` if the path refers to a directory.
+    /// - `ReadOnlyFileSystem` if the filesystem is mounted read only and the options give the write access.
+    pub fn open_file_with_options(
+        &self,
+        path: &str,
+        options: &OpenOptions,
+    ) -> FatFileSystemResult<FileHandle<'_, 'm, S>> {
+        let writable = options.is_writable();
+
+        if (options.truncate || options.create) && !writable {
+            return Err(FatError::AccessDenied);
+        }
+
+        if writable {
+            self.check_read_write()?;
+        }
+
+        let mut file = match self.open_file(path) {
+            Err(FatError::NotFound) if options.create => {
+                self.create_file(path)?;
+                self.open_file(path)?
+            }
+            res => res?,
+        };
+
+        if writable {
+            self.check_writable(file.file_info.attribute)?;
+        }
+
+        if options.truncate {
+            file.set_len(self, 0)?;
+        }
+
+        Ok(FileHandle::new(self, file, *options))
+    }
+
     /// Create a new directory at the given path.
     pub fn create_directory(&self, path: &str) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
         let (_, file_name) = utils::get_parent(path);
         let mut parent_dir = self.open_parent_directory(path)?;
 
@@ -256,6 +613,8 @@ impl<S: StorageDevice> FatFileSystem<S> {
 
     /// Create a new file at the given path.
     pub fn create_file(&self, path: &str) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
         let (_, file_name) = utils::get_parent(path);
         let mut parent_dir = self.open_parent_directory(path)?;
 
@@ -269,12 +628,16 @@ impl<S: StorageDevice> FatFileSystem<S> {
 
     /// Delete a file at the given path.
     pub fn delete_file(&self, path: &str) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
         let (_, file_name) = utils::get_parent(path);
         self.open_parent_directory(path)?.delete_file(file_name)
     }
 
     /// Delete a directory at the given path.
     pub fn delete_directory(&self, path: &str) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
         let (_, file_name) = utils::get_parent(path);
         self.open_parent_directory(path)?
             .delete_directory(file_name)
@@ -290,13 +653,240 @@ impl<S: StorageDevice> FatFileSystem<S> {
         self.rename(old_path, new_path, true)
     }
 
+    /// Set the creation, last access and last modification times of the entry at the given path.
+    ///
+    /// The times are UNIX timestamps, None leaves the current value untouched.
+    /// As FAT stores the modification time with a two seconds resolution, odd seconds are rounded down. The last access time only keeps the date.
+    ///
+    /// # Error
+    ///
+    /// - `Custom("Invalid timestamp")`
+    ///    - When a timestamp is before 1980 or after 2107.
+    /// - `AccessDenied`
+    ///    - When the path is the root directory.
+    /// - `ReadOnlyFileSystem`
+    ///    - When the filesystem is mounted read only.
+    pub fn set_times(
+        &self,
+        path: &str,
+        created: Option<u64>,
+        accessed: Option<u64>,
+        modified: Option<u64>,
+    ) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
+        let to_datetime = |timestamp: u64| {
+            FatDateTime::from_unix_time(timestamp).ok_or(FatError::Custom {
+                name: "Invalid timestamp",
+            })
+        };
+
+        let created = created.map(to_datetime).transpose()?;
+        let accessed = accessed.map(to_datetime).transpose()?;
+        let modified = modified.map(to_datetime).transpose()?;
+
+        let entry = self.search_entry(path)?;
+        let raw_info = entry.raw_info.ok_or(FatError::AccessDenied)?;
+        let mut raw_dir_entry = raw_info.get_dir_entry(self)?;
+
+        if let Some(created) = created {
+            raw_dir_entry.set_creation_datetime(&created);
+        }
+
+        if let Some(accessed) = accessed {
+            raw_dir_entry.set_last_access_date(&accessed);
+        }
+
+        if let Some(modified) = modified {
+            raw_dir_entry.set_modification_datetime(&modified);
+        }
+
+        raw_dir_entry.flush(self)
+    }
+
+    /// Set the attributes of the entry at the given path.
+    ///
+    /// Only the read only, hidden, system and archive attributes can be changed.
+    ///
+    /// # Error
+    ///
+    /// - `Custom("Invalid attributes")`
+    ///    - When the directory, volume or device attributes would be changed.
+    /// - `AccessDenied`
+    ///    - When the path is the root directory.
+    /// - `ReadOnlyFileSystem`
+    ///    - When the filesystem is mounted read only.
+    pub fn set_attributes(&self, path: &str, attributes: Attributes) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
+        let entry = self.search_entry(path)?;
+        let raw_info = entry.raw_info.ok_or(FatError::AccessDenied)?;
+        let mut raw_dir_entry = raw_info.get_dir_entry(self)?;
+
+        let current_value = raw_dir_entry.attribute().get_value();
+        if (current_value ^ attributes.get_value()) & !Attributes::MODIFIABLE != 0 {
+            return Err(FatError::Custom {
+                name: "Invalid attributes",
+            });
+        }
+
+        raw_dir_entry.set_attribute(attributes);
+        raw_dir_entry.flush(self)
+    }
+
     /// Get the FAT filesystem type.
     pub fn get_type(&self) -> FatFsType {
         self.boot_record.fat_type
     }
 
+    /// Get the geometry and usage information of the filesystem.
+    ///
+    /// The free cluster count comes from the cached FS Info and doesn't require to scan the FATs.
+    pub fn volume_info(&self) -> VolumeInfo {
+        let boot_record = &self.boot_record;
+
+        let next_free_cluster = self.fat_info.last_cluster.load(Ordering::SeqCst);
+        let next_free_cluster_hint =
+            if next_free_cluster >= 2 && next_free_cluster < boot_record.cluster_count {
+                Some(next_free_cluster)
+            } else {
+                None
+            };
+
+        let (fs_info_block, backup_boot_record_block) = match boot_record.fat_type {
+            FatFsType::Fat32 => (
+                Some(boot_record.fs_info_block()),
+                Some(boot_record.backup_boot_record_block()),
+            ),
+            FatFsType::Fat12 | FatFsType::Fat16 => (None, None),
+        };
+
+        VolumeInfo {
+            fat_type: boot_record.fat_type,
+            volume_id: boot_record.volume_id(),
+            oem_name: boot_record.oem_name(),
+            media_type: boot_record.media_type(),
+            bytes_per_block: boot_record.bytes_per_block(),
+            blocks_per_cluster: boot_record.blocks_per_cluster(),
+            total_blocks: boot_record.total_blocks(),
+            reserved_block_count: boot_record.reserved_block_count(),
+            fats_count: boot_record.fats_count(),
+            fat_size: boot_record.fat_size(),
+            root_dir_childs_count: boot_record.root_dir_childs_count(),
+            total_clusters: boot_record.cluster_count - 2,
+            free_clusters: self.fat_info.free_cluster.load(Ordering::SeqCst),
+            next_free_cluster_hint,
+            fs_info_block,
+            backup_boot_record_block,
+        }
+    }
+
+    /// Search the volume label entry in the root directory.
+    fn find_volume_label_entry(&self) -> FatFileSystemResult<Option<FatDirEntry>> {
+        let mut fat_dir_entry_iter = self.get_root_directory().fat_dir_entry_iter();
+        while let Some(raw_dir_entry) = fat_dir_entry_iter.next(self) {
+            let raw_dir_entry = raw_dir_entry?;
+
+            // End of directory
+            if raw_dir_entry.is_free() {
+                break;
+            }
+
+            if raw_dir_entry.is_deleted() || raw_dir_entry.is_long_file_name() {
+                continue;
+            }
+
+            let attribute = raw_dir_entry.attribute();
+            if attribute.is_volume() && !attribute.is_directory() {
+                return Ok(Some(raw_dir_entry));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get the volume label of the filesystem.
+    ///
+    /// The label is read from the root directory and fallback to the one of the boot record.
+    /// An empty string is returned if the volume doesn't have a label.
+    pub fn volume_label(
+        &self,
+    ) -> FatFileSystemResult<ArrayString<[u8; VolumeLabel::MAX_LEN_UNICODE]>> {
+        let label = match self.find_volume_label_entry()? {
+            // unwrap will never fail here as LFN entries are skipped
+            Some(raw_dir_entry) => {
+                VolumeLabel::from_data(raw_dir_entry.short_name().unwrap().as_bytes())
+            }
+            None => match *self.boot_record_label.lock() {
+                Some(label) if label != VolumeLabel::NO_NAME => VolumeLabel::from_data(label),
+                _ => VolumeLabel::from_data([b' '; VolumeLabel::MAX_LEN]),
+            },
+        };
+
+        Ok(label.as_str())
+    }
+
+    /// Set the volume label of the filesystem.
+    ///
+    /// Both the root directory entry and the boot record are updated. An empty label removes the label of the volume.
+    ///
+    /// # Error
+    ///
+    /// - `Custom("Invalid volume label")`
+    ///    - When the label is longer than 11 characters or contains a character not allowed in a volume label.
+    /// - `NoSpaceLeft`
+    ///    - When the root directory is full.
+    /// - `ReadOnlyFileSystem`
+    ///    - When the filesystem is mounted read only.
+    pub fn set_volume_label(&self, label: &str) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
+        let label = VolumeLabel::from_utf8(label)?;
+        self.write_volume_label(&label)
+    }
+
+    /// Write a volume label to the root directory and the boot record.
+    pub(crate) fn write_volume_label(&self, label: &VolumeLabel) -> FatFileSystemResult<()> {
+        let raw_dir_entry = match self.find_volume_label_entry()? {
+            Some(raw_dir_entry) => Some(raw_dir_entry),
+            None if !label.is_empty() => {
+                let root_directory = self.get_root_directory();
+                let mut free_entries_iter =
+                    Directory::allocate_entries(&root_directory.dir_info, self, 1)?;
+
+                // unwrap will never fail here as the entry was just allocated
+                let mut raw_dir_entry = free_entries_iter.next(self).unwrap()?;
+                raw_dir_entry.clear();
+                raw_dir_entry.set_attribute(Attributes::new(Attributes::VOLUME));
+                Some(raw_dir_entry)
+            }
+            None => None,
+        };
+
+        if let Some(mut raw_dir_entry) = raw_dir_entry {
+            if label.is_empty() {
+                raw_dir_entry.set_deleted();
+            } else {
+                raw_dir_entry.set_short_name(&ShortFileName::from_data(label.as_bytes()));
+            }
+            raw_dir_entry.flush(self)?;
+        }
+
+        // The boot record is shared, write an updated copy of it.
+        let mut boot_record = self.boot_record.clone();
+        boot_record.set_volume_label(label.as_boot_record_bytes());
+
+        let mut storage_device = self.storage_device.lock();
+        boot_record.flush(&mut *storage_device, self.partition_start)?;
+        *self.boot_record_label.lock() = boot_record.volume_label();
+
+        Ok(())
+    }
+
     /// Rename a directory or a file at the given path to a new path.
     fn rename(&self, old_path: &str, new_path: &str, is_dir: bool) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
         let (_, file_name) = utils::get_parent(old_path);
         let parent_old_dir = self.open_parent_directory(old_path)?;
 
@@ -362,11 +952,166 @@ impl<S: StorageDevice> FatFileSystem<S> {
         Ok(())
     }
 
+    /// Check if a cluster is free, using the allocation bitmap if it covers the cluster.
+    fn is_cluster_free(&self, cluster: Cluster) -> FatFileSystemResult<bool> {
+        if let Some(used) = self.allocation_bitmap.lock().is_used(cluster) {
+            return Ok(!used);
+        }
+
+        Ok(FatValue::get(self, cluster)? == FatValue::Free)
+    }
+
+    /// Find the first free cluster between the start cluster and the end cluster (excluded).
+    ///
+    /// The FAT is only read for the clusters not covered by the allocation bitmap.
+    fn find_free_cluster(&self, start: u32, end: u32) -> FatFileSystemResult<Option<Cluster>> {
+        let bitmap_end = {
+            let bitmap = self.allocation_bitmap.lock();
+            if let Some(cluster) = bitmap.find_free(start, end) {
+                return Ok(Some(cluster));
+            }
+
+            bitmap.cluster_count()
+        };
+
+        for index in core::cmp::max(start, bitmap_end)..end {
+            if FatValue::get(self, Cluster(index))? == FatValue::Free {
+                return Ok(Some(Cluster(index)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check if the given count of clusters starting at the given cluster are all free.
+    fn is_free_cluster_run(&self, start: u32, count: u32) -> FatFileSystemResult<bool> {
+        match start.checked_add(count) {
+            Some(end) if start >= 2 && end <= self.boot_record.cluster_count => {}
+            _ => return Ok(false),
+        }
+
+        for index in start..start + count {
+            if !self.is_cluster_free(Cluster(index))? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Find the first run of the given count of free clusters between the start cluster and the end cluster (excluded).
+    fn find_free_cluster_run(
+        &self,
+        start: u32,
+        end: u32,
+        count: u32,
+    ) -> FatFileSystemResult<Option<Cluster>> {
+        let mut search_start = start;
+
+        while let Some(free_cluster) = self.find_free_cluster(search_start, end)? {
+            let mut run_len = 1;
+            while run_len < count
+                && free_cluster.0 + run_len < end
+                && self.is_cluster_free(Cluster(free_cluster.0 + run_len))?
+            {
+                run_len += 1;
+            }
+
+            if run_len == count {
+                return Ok(Some(free_cluster));
+            }
+
+            // The cluster after the run is used.
+            search_start = free_cluster.0 + run_len + 1;
+        }
+
+        Ok(None)
+    }
+
+    /// Allocate the given count of clusters as a chain and if specified append it to a cluster chain.
+    ///
+    /// The clusters following the cluster chain are used if they are free, otherwise the first run of free clusters long enough is used.
+    /// If there isn't any, the clusters are allocated one by one and are freed again if there isn't enough space.
+    ///
+    /// Returns the first allocated cluster.
+    pub(crate) fn alloc_clusters(
+        &self,
+        last_cluster_allocated_opt: Option<Cluster>,
+        count: u32,
+    ) -> FatFileSystemResult<Cluster> {
+        self.check_read_write()?;
+        debug_assert!(count != 0);
+
+        let cluster_count = self.boot_record.cluster_count;
+        let mut hint = self.fat_info.last_cluster.load(Ordering::SeqCst);
+        if hint < 2 || hint >= cluster_count {
+            hint = 2;
+        }
+
+        let run_start = match last_cluster_allocated_opt {
+            Some(last_cluster) if self.is_free_cluster_run(last_cluster.0 + 1, count)? => {
+                Some(Cluster(last_cluster.0 + 1))
+            }
+            _ => match self.find_free_cluster_run(hint, cluster_count, count)? {
+                Some(run_start) => Some(run_start),
+                None => self.find_free_cluster_run(2, hint, count)?,
+            },
+        };
+
+        let run_start = match run_start {
+            Some(run_start) => run_start,
+            None => {
+                // Too fragmented, allocate the clusters one by one.
+                let first_cluster = self.alloc_cluster(last_cluster_allocated_opt)?;
+                let mut last_cluster = first_cluster;
+                for _ in 1..count {
+                    last_cluster = match self.alloc_cluster(Some(last_cluster)) {
+                        Ok(cluster) => cluster,
+                        Err(error) => {
+                            // Give back the clusters already allocated and restore the end of the chain.
+                            self.free_cluster(first_cluster, last_cluster_allocated_opt)?;
+                            return Err(error);
+                        }
+                    };
+                }
+
+                return Ok(first_cluster);
+            }
+        };
+
+        for index in run_start.0..run_start.0 + count {
+            let value = if index + 1 == run_start.0 + count {
+                FatValue::EndOfChain
+            } else {
+                FatValue::Data(index + 1)
+            };
+
+            FatValue::put(self, Cluster(index), value)?;
+        }
+
+        // Link the existing cluster chain with the new clusters
+        if let Some(last_cluster_allocated) = last_cluster_allocated_opt {
+            FatValue::put(self, last_cluster_allocated, FatValue::Data(run_start.0))?;
+        }
+
+        self.fat_info
+            .last_cluster
+            .store(run_start.0 + count - 1, Ordering::SeqCst);
+        self.fat_info
+            .free_cluster
+            .fetch_sub(count, Ordering::SeqCst);
+        self.fat_info.flush(self)?;
+
+        Ok(run_start)
+    }
+
     /// Allocate a cluster and if specified add it to a cluster chain.
     pub(crate) fn alloc_cluster(
         &self,
         last_cluster_allocated_opt: Option<Cluster>,
     ) -> FatFileSystemResult<Cluster> {
+        self.check_read_write()?;
+
         let mut start_cluster = Cluster(self.fat_info.last_cluster.load(Ordering::SeqCst));
         let mut resize_existing_cluster = false;
 
@@ -402,9 +1147,7 @@ impl<S: StorageDevice> FatFileSystem<S> {
                 number_cluster = 2;
             }
 
-            let value = FatValue::get(self, Cluster(number_cluster))?;
-
-            if value != FatValue::Free {
+            if !self.is_cluster_free(Cluster(number_cluster))? {
                 let new_start = Cluster(self.fat_info.last_cluster.load(Ordering::SeqCst));
                 if new_start.0 >= 2 && new_start.0 < self.boot_record.cluster_count {
                     start_cluster = new_start;
@@ -415,26 +1158,17 @@ impl<S: StorageDevice> FatFileSystem<S> {
         }
 
         if number_cluster == 0 {
-            number_cluster = start_cluster.0;
-            loop {
-                number_cluster += 1;
-                if number_cluster >= self.boot_record.cluster_count {
-                    number_cluster = 2;
-                    if number_cluster > start_cluster.0 {
-                        return Err(FatError::NoSpaceLeft);
-                    }
-                }
-
-                let value = FatValue::get(self, Cluster(number_cluster))?;
-
-                if value == FatValue::Free {
-                    break;
-                }
-
-                if number_cluster == start_cluster.0 {
-                    return Err(FatError::NoSpaceLeft);
-                }
-            }
+            // Search after the start cluster first, then wrap around.
+            let free_cluster = match self
+                .find_free_cluster(start_cluster.0 + 1, self.boot_record.cluster_count)?
+            {
+                Some(free_cluster) => free_cluster,
+                None => self
+                    .find_free_cluster(2, start_cluster.0 + 1)?
+                    .ok_or(FatError::NoSpaceLeft)?,
+            };
+
+            number_cluster = free_cluster.0;
         }
 
         let allocated_cluster = Cluster(number_cluster);
@@ -469,6 +1203,10 @@ impl<S: StorageDevice> FatFileSystem<S> {
         to_remove: Cluster,
         previous_cluster: Option<Cluster>,
     ) -> FatFileSystemResult<()> {
+        self.check_read_write()?;
+
+        self.chain_generation.fetch_add(1, Ordering::SeqCst);
+
         if let Some(previous_cluster) = previous_cluster {
             FatValue::put(self, previous_cluster, FatValue::EndOfChain)?;
         }
@@ -0,0 +1,436 @@
+//! Options used to format a partition.
+
+use crate::name::VolumeLabel;
+use crate::FatError;
+use crate::FatFileSystemResult;
+use crate::FatFsType;
+use crate::MAXIMAL_BLOCK_SIZE;
+use crate::MINIMAL_BLOCK_SIZE;
+
+/// The maximal cluster size in bytes.
+const MAXIMAL_CLUSTER_SIZE: u32 = 64 * 1024;
+
+/// The maximal cluster size in bytes picked automatically.
+///
+/// Clusters of 64KiB aren't supported by every implementation.
+const MAXIMAL_AUTO_CLUSTER_SIZE: u32 = 32 * 1024;
+
+/// The minimal partition size that is formatted as FAT32 when no FAT type is requested.
+const FAT32_AUTO_MINIMAL_SIZE: u64 = 512 * 1024 * 1024;
+
+/// The geometry of a filesystem computed from the format options.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FormatGeometry {
+    /// The type of FAT filesystem.
+    pub fat_type: FatFsType,
+
+    /// The total count of blocks of the filesystem.
+    pub total_blocks: u32,
+
+    /// The amount of blocks per cluster.
+    pub blocks_per_cluster: u8,
+
+    /// The count of reserved blocks.
+    pub reserved_block_count: u16,
+
+    /// The number of childs in the root directory (zero on FAT32).
+    pub root_dir_childs_count: u16,
+
+    /// The size in blocks of one FAT.
+    pub fat_size: u32,
+
+    /// The count of clusters in the data region.
+    pub cluster_count: u32,
+}
+
+/// Options used when formatting a partition.
+///
+/// Every option not explicitly set is computed from the partition size and the FAT type.
+///
+/// # Example
+///
+/// ```ignore
+/// let options = FormatOptions::new()
+///     .fat_type(FatFsType::Fat32)
+///     .blocks_per_cluster(8)
+///     .volume_label(*b"MY VOLUME  ")
+///     .volume_id(0x1234_5678);
+/// libfat::format_partition_with_options(storage_device, &options, partition_start, partition_size)?;
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct FormatOptions {
+    /// The type of FAT filesystem. If None, the type is chosen from the partition size.
+    pub(crate) fat_type: Option<FatFsType>,
+
+    /// The amount of bytes per block.
+    pub(crate) bytes_per_block: u16,
+
+    /// The amount of blocks per cluster. If None, the cluster size is chosen from the partition size.
+    pub(crate) blocks_per_cluster: Option<u8>,
+
+    /// The number of FAT present in the filesystem.
+    pub(crate) fats_count: u8,
+
+    /// The count of reserved blocks. If None, uses 1 on FAT12/FAT16 and 32 on FAT32.
+    pub(crate) reserved_block_count: Option<u16>,
+
+    /// The number of childs in the root directory for FAT12/FAT16 filesystems. If None, uses 512.
+    pub(crate) root_dir_childs_count: Option<u16>,
+
+    /// The media type of the filesystem.
+    pub(crate) media_type: u8,
+
+    /// The volume label.
+    pub(crate) volume_label: [u8; 11],
+
+    /// The volume serial number.
+    pub(crate) volume_id: u32,
+
+    /// The OEM name.
+    pub(crate) oem_name: [u8; 8],
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FormatOptions {
+    /// Create the default format options.
+    pub fn new() -> Self {
+        FormatOptions {
+            fat_type: None,
+            bytes_per_block: MINIMAL_BLOCK_SIZE as u16,
+            blocks_per_cluster: None,
+            fats_count: 2,
+            reserved_block_count: None,
+            root_dir_childs_count: None,
+            media_type: 0xF8,
+            volume_label: [b' '; 11],
+            volume_id: 0,
+            oem_name: *b"MSWIN4.1",
+        }
+    }
+
+    /// Set the type of FAT filesystem.
+    pub fn fat_type(mut self, fat_type: FatFsType) -> Self {
+        self.fat_type = Some(fat_type);
+        self
+    }
+
+    /// Set the amount of bytes per block (512, 1024, 2048 or 4096).
+    pub fn bytes_per_block(mut self, bytes_per_block: u16) -> Self {
+        self.bytes_per_block = bytes_per_block;
+        self
+    }
+
+    /// Set the amount of blocks per cluster (a power of two up to 128).
+    pub fn blocks_per_cluster(mut self, blocks_per_cluster: u8) -> Self {
+        self.blocks_per_cluster = Some(blocks_per_cluster);
+        self
+    }
+
+    /// Set the number of FAT present in the filesystem.
+    pub fn fats_count(mut self, fats_count: u8) -> Self {
+        self.fats_count = fats_count;
+        self
+    }
+
+    /// Set the count of reserved blocks.
+    pub fn reserved_block_count(mut self, reserved_block_count: u16) -> Self {
+        self.reserved_block_count = Some(reserved_block_count);
+        self
+    }
+
+    /// Set the number of childs in the root directory for FAT12/FAT16 filesystems.
+    ///
+    /// The count must fill whole blocks: it must be a multiple of the block size divided by 32.
+    pub fn root_dir_childs_count(mut self, root_dir_childs_count: u16) -> Self {
+        self.root_dir_childs_count = Some(root_dir_childs_count);
+        self
+    }
+
+    /// Set the media type of the filesystem (0xF0 or 0xF8 to 0xFF).
+    pub fn media_type(mut self, media_type: u8) -> Self {
+        self.media_type = media_type;
+        self
+    }
+
+    /// Set the volume label.
+    ///
+    /// The label must be padded with spaces and only use uppercase 8.3 characters. A label only made of spaces means no label.
+    pub fn volume_label(mut self, volume_label: [u8; 11]) -> Self {
+        self.volume_label = volume_label;
+        self
+    }
+
+    /// Set the volume serial number.
+    pub fn volume_id(mut self, volume_id: u32) -> Self {
+        self.volume_id = volume_id;
+        self
+    }
+
+    /// Set the OEM name.
+    pub fn oem_name(mut self, oem_name: [u8; 8]) -> Self {
+        self.oem_name = oem_name;
+        self
+    }
+
+    /// Validate the options and compute the geometry of a filesystem of the given size.
+    pub(crate) fn compute_geometry(
+        &self,
+        partition_size: u64,
+    ) -> FatFileSystemResult<FormatGeometry> {
+        if self.bytes_per_block < MINIMAL_BLOCK_SIZE as u16
+            || self.bytes_per_block > MAXIMAL_BLOCK_SIZE as u16
+            || !self.bytes_per_block.is_power_of_two()
+        {
+            return Err(FatError::Custom {
+                name: "Invalid block size",
+            });
+        }
+
+        if self.fats_count == 0 {
+            return Err(FatError::Custom {
+                name: "Invalid FAT count",
+            });
+        }
+
+        if self.media_type != 0xF0 && self.media_type < 0xF8 {
+            return Err(FatError::Custom {
+                name: "Invalid media type",
+            });
+        }
+
+        if !VolumeLabel::is_valid(&self.volume_label) {
+            return Err(FatError::Custom {
+                name: "Invalid volume label",
+            });
+        }
+
+        if let Some(fat_type) = self.fat_type {
+            return self.compute_geometry_for_type(fat_type, partition_size, true);
+        }
+
+        if partition_size >= FAT32_AUTO_MINIMAL_SIZE {
+            return self.compute_geometry_for_type(FatFsType::Fat32, partition_size, true);
+        }
+
+        // Pick the smallest FAT type that doesn't need clusters bigger than the preferred ones.
+        self.compute_geometry_for_type(FatFsType::Fat12, partition_size, false)
+            .or_else(|_| self.compute_geometry_for_type(FatFsType::Fat16, partition_size, false))
+            .or_else(|_| self.compute_geometry_for_type(FatFsType::Fat32, partition_size, true))
+    }
+
+    /// Validate the options and compute the geometry of a filesystem of the given size and type.
+    ///
+    /// If allow_bigger_clusters is false and no cluster size was requested, only the preferred cluster size and smaller ones are considered.
+    fn compute_geometry_for_type(
+        &self,
+        fat_type: FatFsType,
+        partition_size: u64,
+        allow_bigger_clusters: bool,
+    ) -> FatFileSystemResult<FormatGeometry> {
+        let block_count = partition_size / u64::from(self.bytes_per_block);
+        if block_count > u64::from(u32::MAX) {
+            return Err(FatError::InvalidPartition);
+        }
+
+        let total_blocks = block_count as u32;
+
+        let reserved_block_count = match (self.reserved_block_count, fat_type) {
+            (Some(reserved_block_count), _) => reserved_block_count,
+            (None, FatFsType::Fat32) => 32,
+            (None, _) => 1,
+        };
+
+        // On FAT32, the FS informations and the backup of the boot record must fit in the reserved blocks.
+        if reserved_block_count == 0 || (fat_type == FatFsType::Fat32 && reserved_block_count < 7) {
+            return Err(FatError::Custom {
+                name: "Invalid reserved block count",
+            });
+        }
+
+        let root_dir_childs_count = match (self.root_dir_childs_count, fat_type) {
+            (_, FatFsType::Fat32) => 0,
+            (Some(root_dir_childs_count), _) => root_dir_childs_count,
+            (None, _) => 512,
+        };
+
+        // The root directory must fill whole blocks.
+        let childs_per_block = self.bytes_per_block / 32;
+        if fat_type != FatFsType::Fat32
+            && (root_dir_childs_count == 0 || root_dir_childs_count % childs_per_block != 0)
+        {
+            return Err(FatError::Custom {
+                name: "Invalid root directory size",
+            });
+        }
+
+        if let Some(blocks_per_cluster) = self.blocks_per_cluster {
+            if !blocks_per_cluster.is_power_of_two()
+                || u32::from(blocks_per_cluster) * u32::from(self.bytes_per_block)
+                    > MAXIMAL_CLUSTER_SIZE
+            {
+                return Err(FatError::Custom {
+                    name: "Invalid cluster size",
+                });
+            }
+
+            return self.compute_geometry_with_cluster_size(
+                fat_type,
+                total_blocks,
+                reserved_block_count,
+                root_dir_childs_count,
+                blocks_per_cluster,
+            );
+        }
+
+        // Try the preferred cluster size first, then bigger and finally smaller ones.
+        let preferred_cluster_size = match fat_type {
+            FatFsType::Fat32 => {
+                let size_mb = partition_size / (1024 * 1024);
+                if size_mb > 32 * 1024 {
+                    32 * 1024
+                } else if size_mb > 16 * 1024 {
+                    16 * 1024
+                } else if size_mb > 8 * 1024 {
+                    8 * 1024
+                } else if size_mb > 1024 {
+                    4 * 1024
+                } else {
+                    512
+                }
+            }
+            _ => 2048,
+        };
+
+        let preferred_blocks_per_cluster =
+            core::cmp::max(preferred_cluster_size / u32::from(self.bytes_per_block), 1);
+        let maximal_blocks_per_cluster = core::cmp::min(
+            MAXIMAL_AUTO_CLUSTER_SIZE / u32::from(self.bytes_per_block),
+            128,
+        );
+
+        let bigger_sizes = (0..8)
+            .map(|shift| 1u32 << shift)
+            .filter(|size| *size >= preferred_blocks_per_cluster);
+        let smaller_sizes = (0..8)
+            .rev()
+            .map(|shift| 1u32 << shift)
+            .filter(|size| *size < preferred_blocks_per_cluster);
+
+        let mut res = Err(FatError::InvalidPartition);
+        for blocks_per_cluster in bigger_sizes.chain(smaller_sizes) {
+            if blocks_per_cluster > maximal_blocks_per_cluster
+                || (!allow_bigger_clusters && blocks_per_cluster > preferred_blocks_per_cluster)
+            {
+                continue;
+            }
+
+            res = self.compute_geometry_with_cluster_size(
+                fat_type,
+                total_blocks,
+                reserved_block_count,
+                root_dir_childs_count,
+                blocks_per_cluster as u8,
+            );
+
+            if res.is_ok() {
+                break;
+            }
+        }
+
+        res
+    }
+
+    /// Compute the geometry of a filesystem with a fixed cluster size and check that the cluster count is in range of the FAT type.
+    fn compute_geometry_with_cluster_size(
+        &self,
+        fat_type: FatFsType,
+        total_blocks: u32,
+        reserved_block_count: u16,
+        root_dir_childs_count: u16,
+        blocks_per_cluster: u8,
+    ) -> FatFileSystemResult<FormatGeometry> {
+        let bytes_per_block = u64::from(self.bytes_per_block);
+
+        let root_dir_blocks =
+            (u64::from(root_dir_childs_count) * 32 + bytes_per_block - 1) / bytes_per_block;
+        let metadata_blocks = u64::from(reserved_block_count) + root_dir_blocks;
+
+        if u64::from(total_blocks) <= metadata_blocks {
+            return Err(FatError::NoSpaceLeft);
+        }
+
+        // Start by overestimating the cluster count to get a FAT big enough.
+        let estimated_cluster_count =
+            (u64::from(total_blocks) - metadata_blocks) / u64::from(blocks_per_cluster);
+
+        let fat_byte_size = match fat_type {
+            FatFsType::Fat12 => ((estimated_cluster_count + 2) * 3 + 1) / 2,
+            FatFsType::Fat16 => (estimated_cluster_count + 2) * 2,
+            FatFsType::Fat32 => (estimated_cluster_count + 2) * 4,
+        };
+
+        let fat_size = (fat_byte_size + bytes_per_block - 1) / bytes_per_block;
+        let fats_size = fat_size * u64::from(self.fats_count);
+
+        if fat_type != FatFsType::Fat32 && fat_size > u64::from(u16::MAX) {
+            return Err(FatError::InvalidPartition);
+        }
+
+        if u64::from(total_blocks) <= metadata_blocks + fats_size {
+            return Err(FatError::NoSpaceLeft);
+        }
+
+        let cluster_count =
+            (u64::from(total_blocks) - metadata_blocks - fats_size) / u64::from(blocks_per_cluster);
+
+        let (minimal_cluster_count, maximal_cluster_count) = match fat_type {
+            FatFsType::Fat12 => (1, 0xFF4),
+            FatFsType::Fat16 => (0xFF5, 0xFFF4),
+            FatFsType::Fat32 => (0xFFF5, 0x0FFF_FFF4),
+        };
+
+        if cluster_count < minimal_cluster_count || cluster_count > maximal_cluster_count {
+            return Err(FatError::InvalidPartition);
+        }
+
+        Ok(FormatGeometry {
+            fat_type,
+            total_blocks,
+            blocks_per_cluster,
+            reserved_block_count,
+            root_dir_childs_count,
+            fat_size: fat_size as u32,
+            cluster_count: cluster_count as u32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FormatOptions;
+    use crate::FatFsType;
+
+    #[test]
+    fn fat32_maximal_cluster_count() {
+        let options = FormatOptions::new()
+            .fat_type(FatFsType::Fat32)
+            .blocks_per_cluster(1)
+            .fats_count(1);
+
+        // Around 0x0FFF_FFF5 clusters with 128 FAT entries per block.
+        let first_total_blocks = (0x0FFF_FFF5u64 + 32) * 128 / 127 - 256;
+
+        let maximal_cluster_count = (first_total_blocks..first_total_blocks + 512)
+            .filter_map(|total_blocks| options.compute_geometry(total_blocks * 512).ok())
+            .map(|geometry| geometry.cluster_count)
+            .max();
+
+        // The last cluster number must stay below 0x0FFF_FFF6.
+        assert_eq!(maximal_cluster_count, Some(0x0FFF_FFF4));
+    }
+}
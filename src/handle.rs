@@ -0,0 +1,235 @@
+//! Open file handles keeping track of their position.
+
+use crate::directory::File;
+use crate::filesystem::FatFileSystem;
+use crate::io::{Read, Seek, SeekFrom, Write};
+use crate::FatError;
+use crate::FatFileSystemResult;
+use core::convert::TryFrom;
+use storage_device::StorageDevice;
+
+/// Options used when opening a file.
+///
+/// # Example
+///
+/// ```ignore
+/// let options = OpenOptions::new().write(true).create(true).truncate(true);
+/// let mut file = fs.open_file_with_options("/log.txt", &options)?;
+/// file.write_all(b"Hello")?;
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OpenOptions {
+    /// Allow to read the file.
+    pub(crate) read: bool,
+
+    /// Allow to write the file.
+    pub(crate) write: bool,
+
+    /// Write at the end of the file whatever the position is.
+    pub(crate) append: bool,
+
+    /// Set the file length to zero when opening it.
+    pub(crate) truncate: bool,
+
+    /// Create the file if it doesn't exist.
+    pub(crate) create: bool,
+}
+
+impl OpenOptions {
+    /// Create open options with every option disabled.
+    pub fn new() -> Self {
+        OpenOptions {
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+        }
+    }
+
+    /// Allow to read the file.
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = read;
+        self
+    }
+
+    /// Allow to write the file.
+    pub fn write(mut self, write: bool) -> Self {
+        self.write = write;
+        self
+    }
+
+    /// Write at the end of the file whatever the position is. Implies the write access.
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Set the file length to zero when opening it. Requires the write access.
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Create the file if it doesn't exist. Requires the write access.
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Return true if the options give the write access.
+    pub(crate) fn is_writable(&self) -> bool {
+        self.write || self.append
+    }
+}
+
+/// An open file, borrowing the filesystem containing it and keeping track of the current position.
+///
+/// Reading and writing are done at the current position, which is then moved after the bytes read or written.
+pub struct FileHandle<'a, 'm, S: StorageDevice> {
+    /// The filesystem containing the file.
+    fs: &'a FatFileSystem<'m, S>,
+
+    /// The file.
+    file: File<'a>,
+
+    /// The options used to open the file.
+    options: OpenOptions,
+
+    /// The current position in the file.
+    position: u64,
+}
+
+impl<'a, 'm, S: StorageDevice> FileHandle<'a, 'm, S> {
+    /// Create a file handle with the given options and a position at the start of the file.
+    pub(crate) fn new(fs: &'a FatFileSystem<'m, S>, file: File<'a>, options: OpenOptions) -> Self {
+        FileHandle {
+            fs,
+            file,
+            options,
+            position: 0,
+        }
+    }
+
+    /// Get the underlying file.
+    pub fn file(&self) -> &File<'a> {
+        &self.file
+    }
+
+    /// Get the underlying file mutably.
+    pub fn file_mut(&mut self) -> &mut File<'a> {
+        &mut self.file
+    }
+
+    /// Close the handle and give back the underlying file.
+    pub fn into_file(self) -> File<'a> {
+        self.file
+    }
+
+    /// Get the length of the file.
+    pub fn len(&self) -> u64 {
+        u64::from(self.file.file_info.file_size)
+    }
+
+    /// Return true if the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Set the length of the file. The position isn't changed.
+    ///
+    /// # Errors:
+    ///
+    /// - `AccessDenied` if the file wasn't opened with the write access.
+    pub fn set_len(&mut self, size: u64) -> FatFileSystemResult<()> {
+        if !self.options.is_writable() {
+            return Err(FatError::AccessDenied);
+        }
+
+        self.file.set_len(self.fs, size)
+    }
+}
+
+impl<'a, 'm, S: StorageDevice> Read for FileHandle<'a, 'm, S> {
+    fn read(&mut self, buf: &mut [u8]) -> FatFileSystemResult<usize> {
+        if !self.options.read {
+            return Err(FatError::AccessDenied);
+        }
+
+        let read_size = self.file.read(self.fs, self.position, buf)?;
+        self.position += read_size;
+
+        Ok(read_size as usize)
+    }
+}
+
+impl<'a, 'm, S: StorageDevice> Write for FileHandle<'a, 'm, S> {
+    fn write(&mut self, buf: &[u8]) -> FatFileSystemResult<usize> {
+        if !self.options.is_writable() {
+            return Err(FatError::AccessDenied);
+        }
+
+        if self.options.append {
+            self.position = self.len();
+        }
+
+        let write_size = self.file.write(self.fs, self.position, buf, true)?;
+        self.position += write_size;
+
+        Ok(write_size as usize)
+    }
+
+    fn flush(&mut self) -> FatFileSystemResult<()> {
+        self.fs.flush()
+    }
+}
+
+impl<'a, 'm, S: StorageDevice> Seek for FileHandle<'a, 'm, S> {
+    fn seek(&mut self, pos: SeekFrom) -> FatFileSystemResult<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.len(), offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+
+        let position = i64::try_from(base)
+            .ok()
+            .and_then(|base| base.checked_add(offset))
+            .and_then(|position| u64::try_from(position).ok())
+            .ok_or(FatError::Custom {
+                name: "Invalid seek to a negative or overflowing position",
+            })?;
+
+        self.position = position;
+
+        Ok(position)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, 'm, S: StorageDevice> std::io::Read for FileHandle<'a, 'm, S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(Read::read(self, buf)?)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, 'm, S: StorageDevice> std::io::Write for FileHandle<'a, 'm, S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(Write::write(self, buf)?)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(Write::flush(self)?)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, 'm, S: StorageDevice> std::io::Seek for FileHandle<'a, 'm, S> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        Ok(Seek::seek(self, pos.into())?)
+    }
+}
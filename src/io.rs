@@ -0,0 +1,120 @@
+//! no_std equivalents of the I/O traits of the standard library.
+
+use crate::FatError;
+use crate::FatFileSystemResult;
+
+/// Possible positions to seek to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeekFrom {
+    /// Seek to the given count of bytes from the start.
+    Start(u64),
+
+    /// Seek to the given count of bytes from the end.
+    End(i64),
+
+    /// Seek to the given count of bytes from the current position.
+    Current(i64),
+}
+
+/// Read bytes from a source.
+pub trait Read {
+    /// Read bytes into the given buffer and return the count of bytes read.
+    ///
+    /// Reading less bytes than the buffer size is only done at the end of the source.
+    fn read(&mut self, buf: &mut [u8]) -> FatFileSystemResult<usize>;
+
+    /// Read exactly enough bytes to fill the given buffer.
+    ///
+    /// # Errors:
+    ///
+    /// - `Custom` if the end of the source is reached before the buffer is filled.
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> FatFileSystemResult<()> {
+        while !buf.is_empty() {
+            match self.read(buf)? {
+                0 => {
+                    return Err(FatError::Custom {
+                        name: "Failed to fill whole buffer",
+                    })
+                }
+                read_size => buf = &mut buf[read_size..],
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Write bytes to a destination.
+pub trait Write {
+    /// Write bytes from the given buffer and return the count of bytes written.
+    fn write(&mut self, buf: &[u8]) -> FatFileSystemResult<usize>;
+
+    /// Write back the buffered modifications to the storage device.
+    fn flush(&mut self) -> FatFileSystemResult<()>;
+
+    /// Write the whole given buffer.
+    ///
+    /// # Errors:
+    ///
+    /// - `Custom` if the destination stops accepting bytes before the whole buffer is written.
+    fn write_all(&mut self, mut buf: &[u8]) -> FatFileSystemResult<()> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => {
+                    return Err(FatError::Custom {
+                        name: "Failed to write whole buffer",
+                    })
+                }
+                write_size => buf = &buf[write_size..],
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Move the position of a cursor in a stream of bytes.
+pub trait Seek {
+    /// Seek to the given position and return the new position from the start.
+    fn seek(&mut self, pos: SeekFrom) -> FatFileSystemResult<u64>;
+}
+
+#[cfg(feature = "std")]
+impl From<SeekFrom> for std::io::SeekFrom {
+    fn from(pos: SeekFrom) -> Self {
+        match pos {
+            SeekFrom::Start(offset) => std::io::SeekFrom::Start(offset),
+            SeekFrom::End(offset) => std::io::SeekFrom::End(offset),
+            SeekFrom::Current(offset) => std::io::SeekFrom::Current(offset),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::SeekFrom> for SeekFrom {
+    fn from(pos: std::io::SeekFrom) -> Self {
+        match pos {
+            std::io::SeekFrom::Start(offset) => SeekFrom::Start(offset),
+            std::io::SeekFrom::End(offset) => SeekFrom::End(offset),
+            std::io::SeekFrom::Current(offset) => SeekFrom::Current(offset),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<FatError> for std::io::Error {
+    fn from(error: FatError) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match error {
+            FatError::NotFound | FatError::PartitionNotFound => ErrorKind::NotFound,
+            FatError::AccessDenied | FatError::ReadOnlyFileSystem => ErrorKind::PermissionDenied,
+            FatError::FileExists => ErrorKind::AlreadyExists,
+            FatError::PathTooLong => ErrorKind::InvalidInput,
+            FatError::InvalidPartition => ErrorKind::InvalidData,
+            _ => ErrorKind::Other,
+        };
+
+        std::io::Error::new(kind, error)
+    }
+}
@@ -1,26 +1,61 @@
 //! A no_std FAT12/FAT16/FAT32 compatible crate.
-//! This crate currently supports FAT12/FAT16/FAT32 with a sector size >= 512 bytes.
+//! This crate currently supports FAT12/FAT16/FAT32 with a sector size of 512, 1024, 2048 or 4096 bytes.
+//!
+//! The `alloc` feature allows to create in-memory storage devices backed by a `Vec<u8>`.
+//! The `std` feature implies `alloc` and adds a storage device over any `std::io` stream, like a `std::fs::File`, and the `std::io` traits on file handles.
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+extern crate std;
+
 pub mod attribute;
+mod bitmap;
+mod cache;
+mod chain_cursor;
+pub mod check;
 mod cluster;
 mod datetime;
 pub mod directory;
 pub mod filesystem;
+mod format;
+mod handle;
+pub mod io;
+mod memory;
 mod name;
 mod offset_iter;
+pub mod partition;
+#[cfg(feature = "std")]
+mod std_device;
 mod table;
 mod utils;
 
 use cluster::Cluster;
 use core::convert::TryInto;
 use filesystem::FatFileSystem;
-use storage_device::Block;
+use name::VolumeLabel;
+use partition::gpt::GptHeader;
+use partition::mbr::{LogicalPartitionIter, MasterBootRecord};
+use partition::Partition;
 use storage_device::StorageDevice;
 
 /// The minimal block size supported.
 pub const MINIMAL_BLOCK_SIZE: usize = 512;
 
+/// The maximal block size supported.
+pub const MAXIMAL_BLOCK_SIZE: usize = 4096;
+
+pub use cache::CacheBlock;
+pub use chain_cursor::FileExtent;
+pub use datetime::{FatDateTime, TimeProvider};
+pub use format::FormatOptions;
+pub use handle::{FileHandle, OpenOptions};
+pub use memory::{MemoryStorage, WriteFault};
+pub use partition::{create_partition_table, list_partitions};
+#[cfg(feature = "std")]
+pub use std_device::IoStorageDevice;
 pub use utils::FileSystemIterator;
 
 /// Represent a FAT filesystem error.
@@ -59,6 +94,9 @@ pub enum FatError {
     /// The partition wasn't used as it's invalid.
     InvalidPartition,
 
+    /// The filesystem is mounted read only.
+    ReadOnlyFileSystem,
+
     /// Represent a custom error.
     Custom {
         /// The name of the custom error.
@@ -66,6 +104,31 @@ pub enum FatError {
     },
 }
 
+impl core::fmt::Display for FatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let description = match self {
+            FatError::NotFound => "not found",
+            FatError::NoSpaceLeft => "no space left on the filesystem",
+            FatError::AccessDenied => "access denied",
+            FatError::WriteFailed => "write to the storage device failed",
+            FatError::ReadFailed => "read from the storage device failed",
+            FatError::PartitionNotFound => "partition not found",
+            FatError::NotAFile => "not a file",
+            FatError::NotADirectory => "not a directory",
+            FatError::FileExists => "file exists",
+            FatError::PathTooLong => "path too long",
+            FatError::InvalidPartition => "invalid partition",
+            FatError::ReadOnlyFileSystem => "read only filesystem",
+            FatError::Custom { name } => name,
+        };
+
+        f.write_str(description)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FatError {}
+
 /// Represent a FAT filesystem result.
 pub type FatFileSystemResult<T> = core::result::Result<T, FatError>;
 
@@ -83,6 +146,7 @@ pub enum FatFsType {
 }
 
 /// Represent the FAT Volume BootRecord.
+#[derive(Clone)]
 struct FatVolumeBootRecord {
     /// The actual data of the boot record.
     data: [u8; MINIMAL_BLOCK_SIZE],
@@ -105,6 +169,9 @@ impl FatVolumeBootRecord {
     /// Offset of the FAT32 system identifier.
     const SYSTEM_IDENTIFIER_FAT32: usize = 82;
 
+    /// The extended boot signature.
+    const EXTENDED_BOOT_SIGNATURE: u8 = 0x29;
+
     /// Create a new FAT volume boot record from raw data.
     pub fn new(data: [u8; MINIMAL_BLOCK_SIZE]) -> Option<Self> {
         let mut res = Self::new_unchecked(data);
@@ -192,7 +259,14 @@ impl FatVolumeBootRecord {
             return false;
         }
 
-        if self.bytes_per_block() < MINIMAL_BLOCK_SIZE as u16 {
+        if self.bytes_per_block() < MINIMAL_BLOCK_SIZE as u16
+            || self.bytes_per_block() > MAXIMAL_BLOCK_SIZE as u16
+            || !self.bytes_per_block().is_power_of_two()
+        {
+            return false;
+        }
+
+        if !self.blocks_per_cluster().is_power_of_two() {
             return false;
         }
 
@@ -205,18 +279,64 @@ impl FatVolumeBootRecord {
         self.data[Self::BOOTABLE_SIGNATURE..Self::BOOTABLE_SIGNATURE + 2]
             .copy_from_slice(&0xAA55u16.to_le_bytes());
 
-        if let FatFsType::Fat32 = fat_type {
-            self.data[Self::SYSTEM_IDENTIFIER_FAT32..Self::SYSTEM_IDENTIFIER_FAT32 + 5]
-                .copy_from_slice(b"FAT32");
-        } else {
-            self.data[Self::SYSTEM_IDENTIFIER_FAT..Self::SYSTEM_IDENTIFIER_FAT + 3]
-                .copy_from_slice(b"FAT");
+        match fat_type {
+            FatFsType::Fat12 => self.data
+                [Self::SYSTEM_IDENTIFIER_FAT..Self::SYSTEM_IDENTIFIER_FAT + 8]
+                .copy_from_slice(b"FAT12   "),
+            FatFsType::Fat16 => self.data
+                [Self::SYSTEM_IDENTIFIER_FAT..Self::SYSTEM_IDENTIFIER_FAT + 8]
+                .copy_from_slice(b"FAT16   "),
+            FatFsType::Fat32 => self.data
+                [Self::SYSTEM_IDENTIFIER_FAT32..Self::SYSTEM_IDENTIFIER_FAT32 + 8]
+                .copy_from_slice(b"FAT32   "),
         }
 
         // Set jump for signature
         self.data[0..3].copy_from_slice(&[0xEB, 0xFE, 0x90]);
     }
 
+    /// Set the OEM name.
+    pub(crate) fn set_oem_name(&mut self, oem_name: [u8; 8]) {
+        self.data[3..11].copy_from_slice(&oem_name)
+    }
+
+    /// The OEM name.
+    pub fn oem_name(&self) -> [u8; 8] {
+        self.data[3..11].try_into().unwrap()
+    }
+
+    /// The volume serial number or None if the extended boot signature is missing.
+    pub fn volume_id(&self) -> Option<u32> {
+        if !self.has_extended_boot_signature() {
+            return None;
+        }
+
+        let offset = match self.fat_type {
+            FatFsType::Fat12 | FatFsType::Fat16 => 39,
+            FatFsType::Fat32 => 67,
+        };
+
+        Some(u32::from_le_bytes(
+            self.data[offset..offset + 4].try_into().unwrap(),
+        ))
+    }
+
+    /// Set the volume serial number for a FAT12/FAT16 filesystem.
+    ///
+    /// This also set the extended boot signature as the serial number and the volume label are only valid with it.
+    pub(crate) fn set_volume_id16(&mut self, volume_id: u32) {
+        self.data[38] = Self::EXTENDED_BOOT_SIGNATURE;
+        self.data[39..43].copy_from_slice(&volume_id.to_le_bytes())
+    }
+
+    /// Set the volume serial number for a FAT32 filesystem.
+    ///
+    /// This also set the extended boot signature as the serial number and the volume label are only valid with it.
+    pub(crate) fn set_volume_id32(&mut self, volume_id: u32) {
+        self.data[66] = Self::EXTENDED_BOOT_SIGNATURE;
+        self.data[67..71].copy_from_slice(&volume_id.to_le_bytes())
+    }
+
     /// Set the volume label for a FAT12/FAT16 filesystem.
     pub(crate) fn set_volume_label16(&mut self, label: [u8; 11]) {
         self.data[43..54].copy_from_slice(&label)
@@ -227,6 +347,42 @@ impl FatVolumeBootRecord {
         self.data[71..82].copy_from_slice(&label)
     }
 
+    /// Check if the extended boot signature is present, meaning the volume serial number and the volume label are valid.
+    pub fn has_extended_boot_signature(&self) -> bool {
+        let offset = match self.fat_type {
+            FatFsType::Fat12 | FatFsType::Fat16 => 38,
+            FatFsType::Fat32 => 66,
+        };
+
+        self.data[offset] == Self::EXTENDED_BOOT_SIGNATURE
+    }
+
+    /// The volume label stored in the boot record or None if the extended boot signature is missing.
+    pub fn volume_label(&self) -> Option<[u8; 11]> {
+        if !self.has_extended_boot_signature() {
+            return None;
+        }
+
+        let offset = match self.fat_type {
+            FatFsType::Fat12 | FatFsType::Fat16 => 43,
+            FatFsType::Fat32 => 71,
+        };
+
+        Some(self.data[offset..offset + 11].try_into().unwrap())
+    }
+
+    /// Set the volume label of the boot record if the extended boot signature is present.
+    pub(crate) fn set_volume_label(&mut self, label: [u8; 11]) {
+        if !self.has_extended_boot_signature() {
+            return;
+        }
+
+        match self.fat_type {
+            FatFsType::Fat12 | FatFsType::Fat16 => self.set_volume_label16(label),
+            FatFsType::Fat32 => self.set_volume_label32(label),
+        }
+    }
+
     /// The amount of bytes per block.
     pub fn bytes_per_block(&self) -> u16 {
         u16::from_le_bytes(self.data[11..13].try_into().unwrap())
@@ -446,12 +602,12 @@ impl FatVolumeBootRecord {
 /// # Note:
 ///
 /// uninitialized is used during a formating operation.
-fn parse_fat_boot_record<S: StorageDevice>(
+fn parse_fat_boot_record<'m, S: StorageDevice>(
     storage_device: S,
     partition_start: u64,
     partition_size: u64,
     uninitialized: bool,
-) -> FatFileSystemResult<FatFileSystem<S>> {
+) -> FatFileSystemResult<FatFileSystem<'m, S>> {
     let mut storage_device = storage_device;
     let boot_record = FatVolumeBootRecord::read(&mut storage_device, partition_start)?;
 
@@ -492,9 +648,9 @@ fn parse_fat_boot_record<S: StorageDevice>(
 }
 
 /// Treat the storage device directly as a filesystem.
-pub fn get_raw_partition<S: StorageDevice>(
+pub fn get_raw_partition<'m, S: StorageDevice>(
     storage_device: S,
-) -> FatFileSystemResult<FatFileSystem<S>> {
+) -> FatFileSystemResult<FatFileSystem<'m, S>> {
     let mut storage_device = storage_device;
     let storage_len = storage_device.len().unwrap();
 
@@ -502,11 +658,11 @@ pub fn get_raw_partition<S: StorageDevice>(
 }
 
 /// Treat the storage device directly as a filesystem.
-pub fn get_raw_partition_with_start<S: StorageDevice>(
+pub fn get_raw_partition_with_start<'m, S: StorageDevice>(
     storage_device: S,
     partition_start: u64,
     partition_size: u64,
-) -> FatFileSystemResult<FatFileSystem<S>> {
+) -> FatFileSystemResult<FatFileSystem<'m, S>> {
     parse_fat_boot_record(storage_device, partition_start, partition_size, false)
 }
 
@@ -526,84 +682,55 @@ pub fn format_partition<S: StorageDevice>(
     fat_type: FatFsType,
     partition_start: u64,
     partition_size: u64,
+) -> FatFileSystemResult<()> {
+    format_partition_with_options(
+        storage_device,
+        &FormatOptions::new().fat_type(fat_type),
+        partition_start,
+        partition_size,
+    )
+}
+
+/// Format the partition to hold a FAT filesystem using the given options.
+pub fn format_partition_with_options<S: StorageDevice>(
+    storage_device: S,
+    options: &FormatOptions,
+    partition_start: u64,
+    partition_size: u64,
 ) -> FatFileSystemResult<()> {
     let mut storage_device = storage_device;
 
+    let geometry = options.compute_geometry(partition_size)?;
+    let fat_type = geometry.fat_type;
+    let volume_label = VolumeLabel::from_data(options.volume_label);
+
     // Create an empty boot record
     let mut boot_record = FatVolumeBootRecord::new_unchecked([0x0u8; MINIMAL_BLOCK_SIZE]);
 
     let mut blocks_per_track = 63;
     let mut heads = 255;
-    let mut cluster_size = 4;
-
-    let block_count = partition_size / Block::LEN_U64;
 
     if partition_size < 512 * 1024 * 1024 {
         blocks_per_track = 32;
         heads = 64;
     }
 
-    let number_clusters = block_count / u64::from(cluster_size);
-
-    if let FatFsType::Fat32 = fat_type {
-        let size_mb = partition_size / (1024 * 1024);
-        if size_mb > 32 * 1024 {
-            cluster_size = 64;
-        } else if size_mb > 16 * 1024 {
-            cluster_size = 32;
-        } else if size_mb > 8 * 1024 {
-            cluster_size = 16;
-        } else if size_mb > 1024 {
-            cluster_size = 8;
-        } else {
-            cluster_size = 1;
-        }
-    } else if let FatFsType::Fat16 = fat_type {
-        // If we have too many clusters, try to make it in range of the FAT16
-        if number_clusters > 0xFFF5 {
-            cluster_size = 64;
-        }
-    } else {
-        // If we have too many clusters, try to make it in range of the FAT12
-        if number_clusters > 0xFF5 {
-            cluster_size = 64;
-        }
-    }
-
-    let number_clusters = block_count / u64::from(cluster_size);
-    if let FatFsType::Fat16 = fat_type {
-        // If we have too many clusters even now, this is too big to hold this filesystem type.
-        if number_clusters > 0xFFF5 {
-            return Err(FatError::InvalidPartition);
-        }
-    } else if let FatFsType::Fat12 = fat_type {
-        // If we have too many clusters even now, this is too big to hold this filesystem type.
-        if number_clusters > 0xFF5 {
-            return Err(FatError::InvalidPartition);
-        }
-    } else if let FatFsType::Fat32 = fat_type {
-        // If we have too many clusters even now, this is too big to hold this filesystem type.
-        if number_clusters > 0x0FFF_FFF5 {
-            return Err(FatError::InvalidPartition);
-        }
-    }
-
-    boot_record.set_media_type(0xf8);
+    boot_record.set_oem_name(options.oem_name);
+    boot_record.set_media_type(options.media_type);
     boot_record.set_num_heads(heads);
     boot_record.set_blocks_per_track(blocks_per_track);
-    boot_record.set_blocks_per_cluster(cluster_size);
-    boot_record.set_fats_count(2);
-    boot_record.set_bytes_per_block(512);
+    boot_record.set_blocks_per_cluster(geometry.blocks_per_cluster);
+    boot_record.set_fats_count(options.fats_count);
+    boot_record.set_bytes_per_block(options.bytes_per_block);
+    boot_record.set_reserved_block_count(geometry.reserved_block_count);
+    boot_record.set_root_dir_childs_count(geometry.root_dir_childs_count);
+    boot_record.set_total_blocks32(geometry.total_blocks);
 
     if let FatFsType::Fat32 = fat_type {
-        boot_record.set_root_dir_childs_count(0);
         boot_record.set_drive_number32(0x80);
-        boot_record.set_volume_label32(*b"NO NAME    ");
-        boot_record.set_reserved_block_count(32);
-        boot_record.set_total_blocks32(block_count as u32);
-
-        let fat_size = (number_clusters * 4 + 8 + Block::LEN_U64 - 1) / Block::LEN_U64;
-        boot_record.set_fat_size32(fat_size as u32);
+        boot_record.set_volume_id32(options.volume_id);
+        boot_record.set_volume_label32(volume_label.as_boot_record_bytes());
+        boot_record.set_fat_size32(geometry.fat_size);
 
         // FAT32 specific features
         boot_record.set_fs_info_block(1);
@@ -619,20 +746,10 @@ pub fn format_partition<S: StorageDevice>(
             )
             .or(Err(FatError::WriteFailed))?;
     } else {
-        boot_record.set_root_dir_childs_count(512);
         boot_record.set_drive_number16(0x80);
-        boot_record.set_volume_label16(*b"NO NAME    ");
-        boot_record.set_reserved_block_count(1);
-        boot_record.set_total_blocks32(block_count as u32);
-
-        let fat_byte_size = if number_clusters > 0xFF5 {
-            number_clusters * 2 + 4
-        } else {
-            (number_clusters * 3 + 1) / 2 + 3
-        };
-
-        let fat_size = (fat_byte_size + Block::LEN_U64 - 1) / Block::LEN_U64;
-        boot_record.set_fat_size16(fat_size as u16);
+        boot_record.set_volume_id16(options.volume_id);
+        boot_record.set_volume_label16(volume_label.as_boot_record_bytes());
+        boot_record.set_fat_size16(geometry.fat_size as u16);
     }
 
     boot_record.set_valid(fat_type);
@@ -642,6 +759,7 @@ pub fn format_partition<S: StorageDevice>(
     // Init the boot_record as it should be valid now
     boot_record.initialize_cache();
     assert!(boot_record.fat_type == fat_type);
+    assert!(boot_record.cluster_count == geometry.cluster_count + 2);
 
     // Write the boot record for FatFilesystem creation
     storage_device
@@ -658,6 +776,10 @@ pub fn format_partition<S: StorageDevice>(
 
     filesystem.create_root_directory()?;
 
+    if !volume_label.is_empty() {
+        filesystem.write_volume_label(&volume_label)?;
+    }
+
     {
         // Rewrite the boot record as it might be updated
         let mut storage_device = filesystem.storage_device.lock();
@@ -677,61 +799,101 @@ pub fn get_fat_type(
     Ok(FatVolumeBootRecord::read(storage_device, partition_start)?.fat_type)
 }
 
-/// Parse the MBR and return an instance to a filesystem at the given partition index.
-pub fn get_partition<S: StorageDevice>(
+/// Parse the partition table and return an instance to a filesystem at the given partition index.
+///
+/// On a MBR, the indexes 0 to 3 are the primary partitions and the logical partitions of the extended partition start at index 4.
+///
+/// If the MBR is a protective MBR, the GPT is used and the index is the index of the entry in the GPT partition entry array.
+pub fn get_partition<'m, S: StorageDevice>(
     storage_device: S,
     index: u64,
     block_size: u64,
-) -> FatFileSystemResult<FatFileSystem<S>> {
-    let mut block = [0x0u8; MINIMAL_BLOCK_SIZE];
-
-    if block_size < block.len() as u64 {
+) -> FatFileSystemResult<FatFileSystem<'m, S>> {
+    if block_size < MINIMAL_BLOCK_SIZE as u64 {
         return Err(FatError::InvalidPartition);
     }
 
-    /// The Partition Table offset.
-    const PARITION_TABLE_OFFSET: usize = 446;
-
-    /// The MBR signature offset.
-    const MBR_SIGNATURE: usize = 510;
-
-    /// The size of a partition table entry.
-    const PARITION_TABLE_ENTRY_SIZE: usize = 16;
-
     let mut storage_device = storage_device;
 
-    storage_device
-        .read(index, &mut block)
-        .or(Err(FatError::ReadFailed))?;
+    let mbr = MasterBootRecord::read(&mut storage_device, 0)?;
 
-    if u16::from_le_bytes(block[MBR_SIGNATURE..MBR_SIGNATURE + 2].try_into().unwrap()) != 0xAA55 {
-        return Err(FatError::InvalidPartition);
+    if mbr.is_protective() {
+        return get_gpt_partition(storage_device, index, block_size);
     }
 
-    let partition = if index < 4 {
-        let offset = PARITION_TABLE_OFFSET + (PARITION_TABLE_ENTRY_SIZE * (index as usize));
-        &block[offset..offset + PARITION_TABLE_ENTRY_SIZE]
+    let partition = if index < MasterBootRecord::ENTRY_COUNT as u64 {
+        mbr.entry(index as usize)
     } else {
-        return Err(FatError::PartitionNotFound);
+        // Logical partitions are indexed after the primary ones.
+        let extended_partition = mbr
+            .extended_partition()
+            .ok_or(FatError::PartitionNotFound)?;
+        let mut logical_iter = LogicalPartitionIter::new(&extended_partition);
+
+        let mut logical_index = index - MasterBootRecord::ENTRY_COUNT as u64;
+        loop {
+            let partition = logical_iter
+                .next(&mut storage_device, block_size)
+                .ok_or(FatError::PartitionNotFound)??;
+
+            if logical_index == 0 {
+                break partition;
+            }
+            logical_index -= 1;
+        }
     };
 
-    if (partition[0] & 0x7F) != 0 {
+    if !partition.is_valid() {
         return Err(FatError::InvalidPartition);
     }
 
-    let partition_start = u32::from_le_bytes(partition[0x8..0xC].try_into().unwrap());
-    let partition_block_count = u32::from_le_bytes(partition[0xC..0x10].try_into().unwrap());
-    let partition_type: u32 = partition[0x4].into();
+    if !partition.is_fat() {
+        return Err(FatError::Custom {
+            name: "Unknown Partition Type",
+        });
+    }
+
+    let partition_start = u64::from(partition.lba_start) * block_size;
 
-    match partition_type {
-        0xC => parse_fat_boot_record(
-            storage_device,
-            u64::from(partition_start) * block_size as u64,
-            u64::from(partition_block_count) * block_size as u64,
-            false,
-        ),
-        _ => Err(FatError::Custom {
+    // Ensure that the partition holds a FAT filesystem. Any FAT variant is accepted whatever the FAT partition type,
+    // small FAT16 volumes are often found under the FAT32 types and FAT12 volumes under the FAT16 ones.
+    get_fat_type(&mut storage_device, partition_start)?;
+
+    parse_fat_boot_record(
+        storage_device,
+        partition_start,
+        u64::from(partition.block_count) * block_size,
+        false,
+    )
+}
+
+/// Parse the GPT and return an instance to a filesystem at the given partition entry index.
+fn get_gpt_partition<'m, S: StorageDevice>(
+    storage_device: S,
+    index: u64,
+    block_size: u64,
+) -> FatFileSystemResult<FatFileSystem<'m, S>> {
+    let mut storage_device = storage_device;
+
+    let header = GptHeader::read_valid(&mut storage_device, block_size)?;
+
+    if index >= u64::from(header.partition_entry_count) {
+        return Err(FatError::PartitionNotFound);
+    }
+
+    let entry = header.read_entry(&mut storage_device, block_size, index as u32)?;
+
+    if entry.is_empty() {
+        return Err(FatError::PartitionNotFound);
+    }
+
+    if !entry.partition_type.is_fat() {
+        return Err(FatError::Custom {
             name: "Unknown Partition Type",
-        }),
+        });
     }
+
+    let partition = Partition::from_gpt_entry(index, &entry, &header, block_size)?;
+
+    parse_fat_boot_record(storage_device, partition.start, partition.length, false)
 }
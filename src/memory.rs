@@ -0,0 +1,134 @@
+//! Storage device keeping its content in memory.
+
+use core::convert::TryFrom;
+use storage_device::{StorageDevice, StorageDeviceError, StorageDeviceResult};
+
+/// A fault injected in the writes of a MemoryStorage.
+#[derive(Clone, Copy, Debug)]
+pub struct WriteFault {
+    /// The count of writes succeeding before the faulty write.
+    pub successful_writes: u64,
+
+    /// The count of bytes of the faulty write still written to memory, simulating a torn write.
+    pub torn_size: usize,
+
+    /// If true, every write after the faulty write also fails, simulating the removal of the device.
+    pub persistent: bool,
+}
+
+/// A storage device reading and writing a buffer in memory, like a `&mut [u8]` or a `Vec<u8>`.
+///
+/// Faults can be injected in the writes to check how the filesystem behaves on failing devices.
+#[derive(Debug)]
+pub struct MemoryStorage<B: AsRef<[u8]> + AsMut<[u8]>> {
+    /// The content of the storage device.
+    buffer: B,
+
+    /// The count of writes done, including the failed ones.
+    write_count: u64,
+
+    /// The fault to inject and the index of the faulty write.
+    write_fault: Option<(WriteFault, u64)>,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> MemoryStorage<B> {
+    /// Create a storage device over the given buffer.
+    pub fn new(buffer: B) -> Self {
+        MemoryStorage {
+            buffer,
+            write_count: 0,
+            write_fault: None,
+        }
+    }
+
+    /// Get the content of the storage device.
+    pub fn get_ref(&self) -> &[u8] {
+        self.buffer.as_ref()
+    }
+
+    /// Get the content of the storage device mutably.
+    pub fn get_mut(&mut self) -> &mut [u8] {
+        self.buffer.as_mut()
+    }
+
+    /// Give back the buffer.
+    pub fn into_inner(self) -> B {
+        self.buffer
+    }
+
+    /// Get the count of writes done, including the failed ones.
+    pub fn write_count(&self) -> u64 {
+        self.write_count
+    }
+
+    /// Inject a fault in the writes done from now on or remove it if None.
+    pub fn set_write_fault(&mut self, write_fault: Option<WriteFault>) {
+        self.write_fault = write_fault.map(|write_fault| {
+            (
+                write_fault,
+                self.write_count + write_fault.successful_writes,
+            )
+        });
+    }
+
+    /// Get the range of the buffer at the given offset, None if it's out of the buffer.
+    fn range(&self, offset: u64, len: usize) -> Option<core::ops::Range<usize>> {
+        let start = usize::try_from(offset).ok()?;
+        let end = start.checked_add(len)?;
+
+        if end > self.buffer.as_ref().len() {
+            return None;
+        }
+
+        Some(start..end)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl MemoryStorage<alloc::vec::Vec<u8>> {
+    /// Create a storage device of the given size filled with zeros.
+    pub fn zeroed(size: usize) -> Self {
+        MemoryStorage::new(alloc::vec![0x0; size])
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> StorageDevice for MemoryStorage<B> {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> StorageDeviceResult<()> {
+        let range = self
+            .range(offset, buf.len())
+            .ok_or(StorageDeviceError::ReadError)?;
+
+        buf.copy_from_slice(&self.buffer.as_ref()[range]);
+
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> StorageDeviceResult<()> {
+        let write_index = self.write_count;
+        self.write_count += 1;
+
+        let range = self
+            .range(offset, buf.len())
+            .ok_or(StorageDeviceError::WriteError)?;
+
+        if let Some((write_fault, faulty_write_index)) = self.write_fault {
+            if write_index == faulty_write_index {
+                let torn_size = core::cmp::min(write_fault.torn_size, buf.len());
+                self.buffer.as_mut()[range.start..range.start + torn_size]
+                    .copy_from_slice(&buf[..torn_size]);
+
+                return Err(StorageDeviceError::WriteError);
+            } else if write_index > faulty_write_index && write_fault.persistent {
+                return Err(StorageDeviceError::WriteError);
+            }
+        }
+
+        self.buffer.as_mut()[range].copy_from_slice(buf);
+
+        Ok(())
+    }
+
+    fn len(&mut self) -> StorageDeviceResult<u64> {
+        Ok(self.buffer.as_ref().len() as u64)
+    }
+}
@@ -1,5 +1,8 @@
 //! FAT filename representation.
 use super::directory::raw_dir_entry::LongFileNameDirEntry;
+use super::FatError;
+use super::FatFileSystemResult;
+use arrayvec::ArrayString;
 use core::num;
 
 /// Represent a 8.3 name.
@@ -15,6 +18,13 @@ pub struct LongFileName {
     contents: [u16; LongFileName::MAX_LEN],
 }
 
+/// Represent a volume label.
+#[derive(Clone, Copy)]
+pub struct VolumeLabel {
+    /// The buffer containing the volume label, padded with spaces.
+    contents: [u8; VolumeLabel::MAX_LEN],
+}
+
 /// An utilitary to generate 8.3 name out of VFAT long name.
 pub struct ShortFileNameGenerator;
 
@@ -359,3 +369,116 @@ impl LongFileName {
         self.contents
     }
 }
+
+impl VolumeLabel {
+    /// The max length of a volume label.
+    pub const MAX_LEN: usize = 11;
+
+    /// The max length of a volume label when represented as Unicode.
+    pub const MAX_LEN_UNICODE: usize = Self::MAX_LEN * 2;
+
+    /// The label stored in the boot record of a volume without label.
+    pub(crate) const NO_NAME: [u8; VolumeLabel::MAX_LEN] = *b"NO NAME    ";
+
+    /// Import a volume label from raw data.
+    pub(crate) fn from_data(label: [u8; VolumeLabel::MAX_LEN]) -> Self {
+        VolumeLabel { contents: label }
+    }
+
+    /// Import a volume label from a str.
+    ///
+    /// Lowercase letters are converted to uppercase.
+    ///
+    /// # Error
+    ///
+    /// - `Custom("Invalid volume label")`
+    ///    - When the label is longer than 11 characters or contains a character not allowed in a volume label.
+    pub fn from_utf8(label: &str) -> FatFileSystemResult<Self> {
+        if label.len() > Self::MAX_LEN {
+            return Err(FatError::Custom {
+                name: "Invalid volume label",
+            });
+        }
+
+        let mut contents = [b' '; Self::MAX_LEN];
+        for (dst, c) in contents.iter_mut().zip(label.bytes()) {
+            *dst = c.to_ascii_uppercase();
+        }
+
+        if !Self::is_valid(&contents) {
+            return Err(FatError::Custom {
+                name: "Invalid volume label",
+            });
+        }
+
+        Ok(Self::from_data(contents))
+    }
+
+    /// Check if a character can be used in a volume label.
+    fn is_valid_char(c: u8) -> bool {
+        matches!(
+            c,
+            b'A'..=b'Z'
+                | b'0'..=b'9'
+                | b' '
+                | b'!'
+                | b'#'
+                | b'$'
+                | b'%'
+                | b'&'
+                | b'\''
+                | b'('
+                | b')'
+                | b'-'
+                | b'@'
+                | b'^'
+                | b'_'
+                | b'`'
+                | b'{'
+                | b'}'
+                | b'~'
+        )
+    }
+
+    /// Check if raw data represent a valid volume label.
+    ///
+    /// A label must only use uppercase 8.3 characters and spaces, and can't start with a space unless it's empty.
+    pub(crate) fn is_valid(label: &[u8; VolumeLabel::MAX_LEN]) -> bool {
+        let is_empty = label.iter().all(|c| *c == b' ');
+
+        (is_empty || label[0] != b' ') && label.iter().all(|c| Self::is_valid_char(*c))
+    }
+
+    /// Check if the volume label is empty.
+    pub fn is_empty(&self) -> bool {
+        self.contents.iter().all(|c| *c == b' ')
+    }
+
+    /// Get the raw content of a volume label.
+    pub fn as_bytes(&self) -> [u8; VolumeLabel::MAX_LEN] {
+        self.contents
+    }
+
+    /// Get the raw content to store in the boot record, using "NO NAME" when the label is empty.
+    pub(crate) fn as_boot_record_bytes(&self) -> [u8; VolumeLabel::MAX_LEN] {
+        if self.is_empty() {
+            Self::NO_NAME
+        } else {
+            self.contents
+        }
+    }
+
+    /// Convert a volume label to a Rust str representation without the trailing spaces.
+    pub fn as_str(&self) -> ArrayString<[u8; VolumeLabel::MAX_LEN_UNICODE]> {
+        let mut res = ArrayString::<[_; VolumeLabel::MAX_LEN_UNICODE]>::new();
+
+        for c in &self.contents {
+            // unwrap will never fail here as a Latin-1 character is at most 2 bytes in UTF-8.
+            res.try_push(*c as char).unwrap();
+        }
+
+        let len = res.trim_end().len();
+        res.truncate(len);
+        res
+    }
+}
@@ -23,28 +23,32 @@ pub struct ClusterOffsetIter {
 
 impl ClusterOffsetIter {
     /// Create a new iterator from a cluster and a block index.
+    ///
+    /// The block index is relative to the given cluster and is resolved by following the cluster chain.
     pub fn new<S: StorageDevice>(
         fs: &FatFileSystem<S>,
         cluster: Cluster,
         start_cluster_offset: Option<u64>,
     ) -> Self {
         let blocks_per_cluster = u64::from(fs.boot_record.blocks_per_cluster());
+        let mut cluster_iter = FatClusterIter::new(fs, cluster);
 
-        let (cluster, start_cluster_offset) =
-            if let Some(start_cluster_offset) = start_cluster_offset {
-                let cluster_offset = start_cluster_offset / blocks_per_cluster;
-                let start_cluster_offset = start_cluster_offset % blocks_per_cluster;
-                (
-                    Cluster(cluster.0 + cluster_offset as u32),
-                    Some(start_cluster_offset),
-                )
-            } else {
-                (cluster, start_cluster_offset)
-            };
+        let start_cluster_offset = if let Some(start_cluster_offset) = start_cluster_offset {
+            // Skip the clusters before the one containing the requested block.
+            for _ in 0..start_cluster_offset / blocks_per_cluster {
+                if cluster_iter.next(fs).is_none() {
+                    break;
+                }
+            }
+
+            Some(start_cluster_offset % blocks_per_cluster)
+        } else {
+            None
+        };
 
         ClusterOffsetIter {
             counter: blocks_per_cluster as usize,
-            cluster_iter: FatClusterIter::new(fs, cluster),
+            cluster_iter,
             start_cluster_offset,
             last_cluster: None,
         }
@@ -0,0 +1,355 @@
+//! GPT partition table.
+
+use crate::utils;
+use crate::FatError;
+use crate::FatFileSystemResult;
+use crate::MINIMAL_BLOCK_SIZE;
+use core::convert::TryInto;
+use storage_device::StorageDevice;
+
+/// Represent a GUID as stored on disk (mixed-endian).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// The GUID of an unused partition entry.
+    pub const UNUSED: Guid = Guid([0x0; 16]);
+
+    /// Basic Data Partition type GUID (EBD0A0A2-B9E5-4433-87C0-68B6B72699C7).
+    pub const BASIC_DATA: Guid = Guid([
+        0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99,
+        0xC7,
+    ]);
+
+    /// EFI System Partition type GUID (C12A7328-F81F-11D2-BA4B-00A0C93EC93B).
+    pub const EFI_SYSTEM: Guid = Guid([
+        0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9,
+        0x3B,
+    ]);
+
+    /// Check if this partition type GUID can hold a FAT filesystem.
+    pub fn is_fat(&self) -> bool {
+        *self == Self::BASIC_DATA || *self == Self::EFI_SYSTEM
+    }
+}
+
+/// Represent an entry of a GPT partition entry array.
+#[derive(Clone, Copy, Debug)]
+pub struct GptPartitionEntry {
+    /// The partition type GUID.
+    pub partition_type: Guid,
+
+    /// The unique GUID of this partition.
+    pub unique_guid: Guid,
+
+    /// The LBA of the first block of the partition.
+    pub first_lba: u64,
+
+    /// The LBA of the last block of the partition (inclusive).
+    pub last_lba: u64,
+
+    /// The attribute flags of the partition.
+    pub attributes: u64,
+}
+
+impl GptPartitionEntry {
+    /// The minimal size of a partition entry.
+    pub const MIN_LEN: usize = 128;
+
+    /// Attribute flag marking the partition as bootable by legacy BIOS.
+    pub const LEGACY_BIOS_BOOTABLE: u64 = 1 << 2;
+
+    /// Create a new GPT partition entry from raw data.
+    pub fn from_raw(data: &[u8]) -> Self {
+        GptPartitionEntry {
+            partition_type: Guid(data[0..16].try_into().unwrap()),
+            unique_guid: Guid(data[16..32].try_into().unwrap()),
+            first_lba: u64::from_le_bytes(data[32..40].try_into().unwrap()),
+            last_lba: u64::from_le_bytes(data[40..48].try_into().unwrap()),
+            attributes: u64::from_le_bytes(data[48..56].try_into().unwrap()),
+        }
+    }
+
+    /// Write the entry into the given raw data.
+    pub fn to_raw(&self, data: &mut [u8]) {
+        data[0..16].copy_from_slice(&self.partition_type.0);
+        data[16..32].copy_from_slice(&self.unique_guid.0);
+        data[32..40].copy_from_slice(&self.first_lba.to_le_bytes());
+        data[40..48].copy_from_slice(&self.last_lba.to_le_bytes());
+        data[48..56].copy_from_slice(&self.attributes.to_le_bytes());
+    }
+
+    /// Check if this entry is unused.
+    pub fn is_empty(&self) -> bool {
+        self.partition_type == Guid::UNUSED
+    }
+
+    /// Return the count of blocks in the partition.
+    pub fn block_count(&self) -> FatFileSystemResult<u64> {
+        if self.last_lba < self.first_lba {
+            return Err(FatError::InvalidPartition);
+        }
+
+        Ok(self.last_lba - self.first_lba + 1)
+    }
+}
+
+/// Represent a GPT header.
+#[derive(Clone, Copy, Debug)]
+pub struct GptHeader {
+    /// The LBA of this header.
+    pub current_lba: u64,
+
+    /// The LBA of the other copy of the header.
+    pub backup_lba: u64,
+
+    /// The first usable LBA for partitions.
+    pub first_usable_lba: u64,
+
+    /// The last usable LBA for partitions (inclusive).
+    pub last_usable_lba: u64,
+
+    /// The GUID of the disk.
+    pub disk_guid: Guid,
+
+    /// The LBA of the partition entry array.
+    pub partition_entries_lba: u64,
+
+    /// The count of entries in the partition entry array.
+    pub partition_entry_count: u32,
+
+    /// The size of one entry in the partition entry array.
+    pub partition_entry_size: u32,
+
+    /// The CRC32 of the partition entry array.
+    pub partition_entries_crc32: u32,
+}
+
+impl GptHeader {
+    /// The signature of a GPT header.
+    const SIGNATURE: &'static [u8; 8] = b"EFI PART";
+
+    /// The minimal size of a GPT header.
+    pub const MIN_LEN: usize = 92;
+
+    /// The revision of the GPT header (1.0).
+    const REVISION: u32 = 0x0001_0000;
+
+    /// Offset of the header CRC32.
+    const HEADER_CRC32: usize = 16;
+
+    /// The maximal size of a partition entry array accepted when reading a GPT.
+    const MAX_ENTRIES_LEN: u64 = 1024 * 1024;
+
+    /// Read and validate the GPT header stored at the given LBA.
+    ///
+    /// This check the header CRC32 and the partition entry array CRC32.
+    pub fn read(
+        storage_device: &mut dyn StorageDevice,
+        lba: u64,
+        block_size: u64,
+    ) -> FatFileSystemResult<Self> {
+        let mut block = [0x0u8; MINIMAL_BLOCK_SIZE];
+
+        storage_device
+            .read(lba * block_size, &mut block)
+            .or(Err(FatError::ReadFailed))?;
+
+        if &block[0..8] != Self::SIGNATURE {
+            return Err(FatError::InvalidPartition);
+        }
+
+        let header_size = u32::from_le_bytes(block[12..16].try_into().unwrap()) as usize;
+        if !(Self::MIN_LEN..=MINIMAL_BLOCK_SIZE).contains(&header_size) {
+            return Err(FatError::InvalidPartition);
+        }
+
+        let header_crc32 = u32::from_le_bytes(
+            block[Self::HEADER_CRC32..Self::HEADER_CRC32 + 4]
+                .try_into()
+                .unwrap(),
+        );
+        block[Self::HEADER_CRC32..Self::HEADER_CRC32 + 4].copy_from_slice(&[0x0; 4]);
+
+        if utils::crc32(0, &block[..header_size]) != header_crc32 {
+            return Err(FatError::InvalidPartition);
+        }
+
+        let header = GptHeader {
+            current_lba: u64::from_le_bytes(block[24..32].try_into().unwrap()),
+            backup_lba: u64::from_le_bytes(block[32..40].try_into().unwrap()),
+            first_usable_lba: u64::from_le_bytes(block[40..48].try_into().unwrap()),
+            last_usable_lba: u64::from_le_bytes(block[48..56].try_into().unwrap()),
+            disk_guid: Guid(block[56..72].try_into().unwrap()),
+            partition_entries_lba: u64::from_le_bytes(block[72..80].try_into().unwrap()),
+            partition_entry_count: u32::from_le_bytes(block[80..84].try_into().unwrap()),
+            partition_entry_size: u32::from_le_bytes(block[84..88].try_into().unwrap()),
+            partition_entries_crc32: u32::from_le_bytes(block[88..92].try_into().unwrap()),
+        };
+
+        if header.current_lba != lba || !header.partition_entry_size.is_power_of_two() {
+            return Err(FatError::InvalidPartition);
+        }
+
+        if header.compute_entries_crc32(storage_device, block_size)?
+            != header.partition_entries_crc32
+        {
+            return Err(FatError::InvalidPartition);
+        }
+
+        Ok(header)
+    }
+
+    /// Write the GPT header at its LBA.
+    ///
+    /// The header CRC32 is computed while writing, the partition entry array CRC32 must already be set.
+    pub fn write(
+        &self,
+        storage_device: &mut dyn StorageDevice,
+        block_size: u64,
+    ) -> FatFileSystemResult<()> {
+        let mut block = [0x0u8; MINIMAL_BLOCK_SIZE];
+
+        block[0..8].copy_from_slice(Self::SIGNATURE);
+        block[8..12].copy_from_slice(&Self::REVISION.to_le_bytes());
+        block[12..16].copy_from_slice(&(Self::MIN_LEN as u32).to_le_bytes());
+        block[24..32].copy_from_slice(&self.current_lba.to_le_bytes());
+        block[32..40].copy_from_slice(&self.backup_lba.to_le_bytes());
+        block[40..48].copy_from_slice(&self.first_usable_lba.to_le_bytes());
+        block[48..56].copy_from_slice(&self.last_usable_lba.to_le_bytes());
+        block[56..72].copy_from_slice(&self.disk_guid.0);
+        block[72..80].copy_from_slice(&self.partition_entries_lba.to_le_bytes());
+        block[80..84].copy_from_slice(&self.partition_entry_count.to_le_bytes());
+        block[84..88].copy_from_slice(&self.partition_entry_size.to_le_bytes());
+        block[88..92].copy_from_slice(&self.partition_entries_crc32.to_le_bytes());
+
+        let header_crc32 = utils::crc32(0, &block[..Self::MIN_LEN]);
+        block[Self::HEADER_CRC32..Self::HEADER_CRC32 + 4]
+            .copy_from_slice(&header_crc32.to_le_bytes());
+
+        let offset = self.current_lba * block_size;
+
+        storage_device
+            .write(offset, &block)
+            .or(Err(FatError::WriteFailed))?;
+
+        // Clean the rest of the block.
+        let zero_block = [0x0u8; MINIMAL_BLOCK_SIZE];
+        for index in 1..block_size / MINIMAL_BLOCK_SIZE as u64 {
+            storage_device
+                .write(offset + index * MINIMAL_BLOCK_SIZE as u64, &zero_block)
+                .or(Err(FatError::WriteFailed))?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the primary GPT header, falling back to the backup header if the primary one can't be read or is corrupted.
+    pub fn read_valid(
+        storage_device: &mut dyn StorageDevice,
+        block_size: u64,
+    ) -> FatFileSystemResult<Self> {
+        let primary_res = Self::read(storage_device, 1, block_size);
+
+        if primary_res.is_ok() {
+            return primary_res;
+        }
+
+        // The backup header is always on the last block of the disk.
+        let storage_len = storage_device.len().or(Err(FatError::ReadFailed))?;
+        let block_count = storage_len / block_size;
+
+        if block_count < 2 {
+            return Err(FatError::InvalidPartition);
+        }
+
+        Self::read(storage_device, block_count - 1, block_size)
+    }
+
+    /// Return the size in bytes of the partition entry array described by this header.
+    ///
+    /// Entries smaller than ``GptPartitionEntry::MIN_LEN`` or arrays bigger than 1MiB are rejected.
+    fn entries_len(&self) -> FatFileSystemResult<u64> {
+        let entries_len =
+            u64::from(self.partition_entry_count) * u64::from(self.partition_entry_size);
+
+        if (self.partition_entry_size as usize) < GptPartitionEntry::MIN_LEN
+            || entries_len > Self::MAX_ENTRIES_LEN
+        {
+            return Err(FatError::InvalidPartition);
+        }
+
+        Ok(entries_len)
+    }
+
+    /// Return the offset in bytes of the partition entry array described by this header.
+    ///
+    /// Arrays that don't fit in the 64 bits offsets of the storage device are rejected.
+    fn entries_offset(&self, block_size: u64) -> FatFileSystemResult<u64> {
+        let entries_len = self.entries_len()?;
+
+        self.partition_entries_lba
+            .checked_mul(block_size)
+            .filter(|offset| offset.checked_add(entries_len).is_some())
+            .ok_or(FatError::InvalidPartition)
+    }
+
+    /// Compute the CRC32 of the partition entry array described by this header.
+    fn compute_entries_crc32(
+        &self,
+        storage_device: &mut dyn StorageDevice,
+        block_size: u64,
+    ) -> FatFileSystemResult<u32> {
+        let mut block = [0x0u8; MINIMAL_BLOCK_SIZE];
+
+        let mut offset = self.entries_offset(block_size)?;
+        let mut bytes_left = self.entries_len()?;
+
+        let mut crc = 0;
+
+        while bytes_left != 0 {
+            let mut read_size = block.len() as u64;
+            if bytes_left < read_size {
+                read_size = bytes_left;
+            }
+
+            let buf_slice = &mut block[..read_size as usize];
+
+            storage_device
+                .read(offset, buf_slice)
+                .or(Err(FatError::ReadFailed))?;
+
+            crc = utils::crc32(crc, buf_slice);
+
+            offset += read_size;
+            bytes_left -= read_size;
+        }
+
+        Ok(crc)
+    }
+
+    /// Read the partition entry at the given index.
+    pub fn read_entry(
+        &self,
+        storage_device: &mut dyn StorageDevice,
+        block_size: u64,
+        index: u32,
+    ) -> FatFileSystemResult<GptPartitionEntry> {
+        let entries_offset = self.entries_offset(block_size)?;
+
+        if index >= self.partition_entry_count {
+            return Err(FatError::PartitionNotFound);
+        }
+
+        let mut data = [0x0u8; GptPartitionEntry::MIN_LEN];
+
+        storage_device
+            .read(
+                entries_offset + u64::from(index) * u64::from(self.partition_entry_size),
+                &mut data,
+            )
+            .or(Err(FatError::ReadFailed))?;
+
+        Ok(GptPartitionEntry::from_raw(&data))
+    }
+}
@@ -0,0 +1,242 @@
+//! MBR partition table.
+
+use crate::FatError;
+use crate::FatFileSystemResult;
+use crate::MINIMAL_BLOCK_SIZE;
+use core::convert::TryInto;
+use storage_device::StorageDevice;
+
+/// Represent an entry of a MBR partition table.
+#[derive(Clone, Copy, Debug)]
+pub struct MbrPartitionEntry {
+    /// The status of the partition (0x80 if bootable, 0x0 otherwise).
+    pub status: u8,
+
+    /// The partition type identifier.
+    pub partition_type: u8,
+
+    /// The LBA of the first block of the partition.
+    pub lba_start: u32,
+
+    /// The count of blocks in the partition.
+    pub block_count: u32,
+}
+
+impl MbrPartitionEntry {
+    /// The size of a partition table entry.
+    pub const LEN: usize = 16;
+
+    /// Partition type of a GPT protective MBR entry.
+    pub const PROTECTIVE_TYPE: u8 = 0xEE;
+
+    /// Create a new MBR partition entry from raw data.
+    pub fn from_raw(data: &[u8]) -> Self {
+        MbrPartitionEntry {
+            status: data[0x0],
+            partition_type: data[0x4],
+            lba_start: u32::from_le_bytes(data[0x8..0xC].try_into().unwrap()),
+            block_count: u32::from_le_bytes(data[0xC..0x10].try_into().unwrap()),
+        }
+    }
+
+    /// Write the entry into the given raw data.
+    pub fn to_raw(&self, data: &mut [u8]) {
+        data[0x0] = self.status;
+        // CHS addresses aren't used, mark them as LBA only.
+        data[0x1..0x4].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+        data[0x4] = self.partition_type;
+        data[0x5..0x8].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+        data[0x8..0xC].copy_from_slice(&self.lba_start.to_le_bytes());
+        data[0xC..0x10].copy_from_slice(&self.block_count.to_le_bytes());
+    }
+
+    /// Check if this entry is unused.
+    pub fn is_empty(&self) -> bool {
+        self.partition_type == 0
+    }
+
+    /// Check if the status of this entry is valid.
+    pub fn is_valid(&self) -> bool {
+        (self.status & 0x7F) == 0
+    }
+
+    /// Check if this entry describes an extended partition (EBR chain).
+    pub fn is_extended(&self) -> bool {
+        matches!(self.partition_type, 0x05 | 0x0F | 0x85)
+    }
+
+    /// Check if the partition type identifier is one used by FAT filesystems.
+    pub fn is_fat(&self) -> bool {
+        matches!(
+            self.partition_type,
+            0x01 | 0x04 | 0x06 | 0x0B | 0x0C | 0x0E | 0xEF
+        )
+    }
+}
+
+/// Represent a MBR.
+pub struct MasterBootRecord {
+    /// The raw data of the MBR.
+    data: [u8; MINIMAL_BLOCK_SIZE],
+}
+
+impl MasterBootRecord {
+    /// The Partition Table offset.
+    const PARITION_TABLE_OFFSET: usize = 446;
+
+    /// The MBR signature offset.
+    const MBR_SIGNATURE: usize = 510;
+
+    /// The count of entries in the partition table.
+    pub const ENTRY_COUNT: usize = 4;
+
+    /// Create a new empty MBR.
+    pub fn new_empty() -> Self {
+        let mut data = [0x0u8; MINIMAL_BLOCK_SIZE];
+
+        data[Self::MBR_SIGNATURE..Self::MBR_SIGNATURE + 2]
+            .copy_from_slice(&0xAA55u16.to_le_bytes());
+
+        MasterBootRecord { data }
+    }
+
+    /// Read a MBR at the given offset of the storage device.
+    pub fn read(storage_device: &mut dyn StorageDevice, offset: u64) -> FatFileSystemResult<Self> {
+        let mut data = [0x0u8; MINIMAL_BLOCK_SIZE];
+
+        storage_device
+            .read(offset, &mut data)
+            .or(Err(FatError::ReadFailed))?;
+
+        let res = MasterBootRecord { data };
+
+        if !res.is_valid() {
+            return Err(FatError::InvalidPartition);
+        }
+
+        Ok(res)
+    }
+
+    /// Checks the signature of the MBR.
+    pub fn is_valid(&self) -> bool {
+        u16::from_le_bytes(
+            self.data[Self::MBR_SIGNATURE..Self::MBR_SIGNATURE + 2]
+                .try_into()
+                .unwrap(),
+        ) == 0xAA55
+    }
+
+    /// Get the partition entry at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if index >= ENTRY_COUNT.
+    pub fn entry(&self, index: usize) -> MbrPartitionEntry {
+        assert!(index < Self::ENTRY_COUNT);
+
+        let offset = Self::PARITION_TABLE_OFFSET + (MbrPartitionEntry::LEN * index);
+        MbrPartitionEntry::from_raw(&self.data[offset..offset + MbrPartitionEntry::LEN])
+    }
+
+    /// Set the partition entry at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if index >= ENTRY_COUNT.
+    pub fn set_entry(&mut self, index: usize, entry: &MbrPartitionEntry) {
+        assert!(index < Self::ENTRY_COUNT);
+
+        let offset = Self::PARITION_TABLE_OFFSET + (MbrPartitionEntry::LEN * index);
+        entry.to_raw(&mut self.data[offset..offset + MbrPartitionEntry::LEN]);
+    }
+
+    /// Write the MBR at the given offset of the storage device.
+    pub fn write(
+        &self,
+        storage_device: &mut dyn StorageDevice,
+        offset: u64,
+    ) -> FatFileSystemResult<()> {
+        storage_device
+            .write(offset, &self.data)
+            .or(Err(FatError::WriteFailed))
+    }
+
+    /// Check if this MBR is a protective MBR (the disk uses a GPT).
+    pub fn is_protective(&self) -> bool {
+        (0..Self::ENTRY_COUNT)
+            .any(|index| self.entry(index).partition_type == MbrPartitionEntry::PROTECTIVE_TYPE)
+    }
+
+    /// Get the first extended partition entry of this MBR if any.
+    pub fn extended_partition(&self) -> Option<MbrPartitionEntry> {
+        (0..Self::ENTRY_COUNT)
+            .map(|index| self.entry(index))
+            .find(|entry| entry.is_extended())
+    }
+}
+
+/// Iterator over the logical partitions of an extended partition (EBR chain).
+///
+/// # Note:
+///
+/// The returned entries have their ``lba_start`` relative to the start of the disk.
+pub struct LogicalPartitionIter {
+    /// The LBA of the extended partition.
+    extended_lba_start: u64,
+
+    /// The LBA of the next EBR to read.
+    next_ebr_lba: Option<u64>,
+
+    /// The count of EBR read.
+    ebr_count: u32,
+}
+
+impl LogicalPartitionIter {
+    /// The maximum count of EBR to read before considering the chain as looping.
+    const MAX_EBR_COUNT: u32 = 1024;
+
+    /// Create a new logical partition iterator from an extended partition entry.
+    pub fn new(extended_partition: &MbrPartitionEntry) -> Self {
+        LogicalPartitionIter {
+            extended_lba_start: u64::from(extended_partition.lba_start),
+            next_ebr_lba: Some(u64::from(extended_partition.lba_start)),
+            ebr_count: 0,
+        }
+    }
+
+    /// Advances the iterator and returns the next logical partition.
+    pub fn next(
+        &mut self,
+        storage_device: &mut dyn StorageDevice,
+        block_size: u64,
+    ) -> Option<FatFileSystemResult<MbrPartitionEntry>> {
+        let ebr_lba = self.next_ebr_lba.take()?;
+
+        self.ebr_count += 1;
+        if self.ebr_count > Self::MAX_EBR_COUNT {
+            return Some(Err(FatError::InvalidPartition));
+        }
+
+        let ebr = match MasterBootRecord::read(storage_device, ebr_lba * block_size) {
+            Ok(ebr) => ebr,
+            Err(error) => return Some(Err(error)),
+        };
+
+        let mut partition = ebr.entry(0);
+        let next_ebr = ebr.entry(1);
+
+        if !next_ebr.is_empty() && next_ebr.lba_start != 0 {
+            self.next_ebr_lba = Some(self.extended_lba_start + u64::from(next_ebr.lba_start));
+        }
+
+        // Logical partitions are relative to their EBR.
+        let lba_start = ebr_lba + u64::from(partition.lba_start);
+        if lba_start > u64::from(u32::MAX) {
+            return Some(Err(FatError::InvalidPartition));
+        }
+
+        partition.lba_start = lba_start as u32;
+
+        Some(Ok(partition))
+    }
+}
@@ -0,0 +1,500 @@
+//! Partition table managment.
+//!
+//! This module contains parsers for the MBR and GPT partition table formats.
+
+pub mod gpt;
+pub mod mbr;
+
+use crate::utils;
+use crate::utils::StorageDeviceRef;
+use crate::FatError;
+use crate::FatFileSystemResult;
+use crate::FatFsType;
+use crate::FormatOptions;
+use crate::MAXIMAL_BLOCK_SIZE;
+use crate::MINIMAL_BLOCK_SIZE;
+use arrayvec::ArrayVec;
+use gpt::{GptHeader, GptPartitionEntry, Guid};
+use mbr::{LogicalPartitionIter, MasterBootRecord, MbrPartitionEntry};
+use storage_device::StorageDevice;
+
+/// The maximum count of partitions returned by ``list_partitions``.
+pub const MAX_PARTITIONS: usize = 128;
+
+/// The alignment in bytes of the partitions created by ``create_partition_table``.
+const PARTITION_ALIGNMENT: u64 = 1024 * 1024;
+
+/// The count of entries in the GPT partition entry array created by ``create_partition_table``.
+const GPT_ENTRY_COUNT: u32 = 128;
+
+/// Represent the type of a partition.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PartitionType {
+    /// A MBR partition type identifier.
+    Mbr(u8),
+
+    /// A GPT partition type GUID.
+    Gpt(Guid),
+}
+
+/// Represent a partition described by a partition table.
+#[derive(Clone, Copy, Debug)]
+pub struct Partition {
+    /// The index of the partition (as expected by ``get_partition``).
+    pub index: u64,
+
+    /// The offset in bytes of the start of the partition.
+    pub start: u64,
+
+    /// The size in bytes of the partition.
+    pub length: u64,
+
+    /// The type of the partition.
+    pub partition_type: PartitionType,
+
+    /// True if the partition is marked as bootable.
+    pub bootable: bool,
+
+    /// The type of the FAT filesystem detected on the partition, if any.
+    pub fat_type: Option<FatFsType>,
+}
+
+impl Partition {
+    /// Create a partition from a MBR/EBR entry.
+    fn from_mbr_entry(index: u64, entry: &MbrPartitionEntry, block_size: u64) -> Self {
+        Partition {
+            index,
+            start: u64::from(entry.lba_start) * block_size,
+            length: u64::from(entry.block_count) * block_size,
+            partition_type: PartitionType::Mbr(entry.partition_type),
+            bootable: (entry.status & 0x80) != 0,
+            fat_type: None,
+        }
+    }
+
+    /// Create a partition from a GPT entry.
+    ///
+    /// Entries outside of the usable blocks of the header or that don't fit in the 64 bits offsets of the storage device are rejected.
+    pub(crate) fn from_gpt_entry(
+        index: u64,
+        entry: &GptPartitionEntry,
+        header: &GptHeader,
+        block_size: u64,
+    ) -> FatFileSystemResult<Self> {
+        if entry.first_lba < header.first_usable_lba || entry.last_lba > header.last_usable_lba {
+            return Err(FatError::InvalidPartition);
+        }
+
+        let start = entry.first_lba.checked_mul(block_size);
+        let length = entry.block_count()?.checked_mul(block_size);
+
+        let (start, length) = match (start, length) {
+            (Some(start), Some(length)) if start.checked_add(length).is_some() => (start, length),
+            _ => return Err(FatError::InvalidPartition),
+        };
+
+        Ok(Partition {
+            index,
+            start,
+            length,
+            partition_type: PartitionType::Gpt(entry.partition_type),
+            bootable: (entry.attributes & GptPartitionEntry::LEGACY_BIOS_BOOTABLE) != 0,
+            fat_type: None,
+        })
+    }
+}
+
+/// Represent the type of partition table to create.
+#[derive(Clone, Copy, Debug)]
+pub enum PartitionTableType {
+    /// A MBR partition table (up to 4 primary partitions).
+    Mbr,
+
+    /// A GPT partition table with a protective MBR.
+    Gpt {
+        /// The GUID of the disk.
+        disk_guid: Guid,
+    },
+}
+
+/// Describe a partition to create with ``create_partition_table``.
+#[derive(Clone, Copy, Debug)]
+pub struct PartitionLayout {
+    /// The size in bytes of the partition. If zero, the partition takes all the remaining space.
+    pub size: u64,
+
+    /// The type of the partition. This must match the type of the partition table.
+    pub partition_type: PartitionType,
+
+    /// The unique GUID of the partition. Unused on MBR.
+    pub unique_guid: Guid,
+
+    /// True if the partition should be marked as bootable.
+    pub bootable: bool,
+
+    /// If present, the partition is formatted with the given options.
+    pub format: Option<FormatOptions>,
+}
+
+/// Write a new partition table on the storage device and format the requested partitions.
+///
+/// Partitions are laid out in order and aligned on 1MiB. On GPT, the protective MBR, the backup header and the backup partition entry array are also written.
+pub fn create_partition_table(
+    storage_device: &mut dyn StorageDevice,
+    table_type: PartitionTableType,
+    partitions: &[PartitionLayout],
+    block_size: u64,
+) -> FatFileSystemResult<()> {
+    if block_size < MINIMAL_BLOCK_SIZE as u64
+        || block_size > MAXIMAL_BLOCK_SIZE as u64
+        || !block_size.is_power_of_two()
+    {
+        return Err(FatError::InvalidPartition);
+    }
+
+    let block_count = storage_device.len().or(Err(FatError::ReadFailed))? / block_size;
+
+    let gpt_entries_blocks =
+        (u64::from(GPT_ENTRY_COUNT) * GptPartitionEntry::MIN_LEN as u64 + block_size - 1)
+            / block_size;
+
+    let (first_usable_lba, last_usable_lba) = match table_type {
+        PartitionTableType::Mbr => {
+            if partitions.len() > MasterBootRecord::ENTRY_COUNT {
+                return Err(FatError::Custom {
+                    name: "Too many partitions",
+                });
+            }
+
+            if block_count < 2 {
+                return Err(FatError::NoSpaceLeft);
+            }
+
+            (1, block_count - 1)
+        }
+        PartitionTableType::Gpt { .. } => {
+            if partitions.len() > GPT_ENTRY_COUNT as usize {
+                return Err(FatError::Custom {
+                    name: "Too many partitions",
+                });
+            }
+
+            // MBR + (header + entries) * 2
+            if block_count < 2 * gpt_entries_blocks + 4 {
+                return Err(FatError::NoSpaceLeft);
+            }
+
+            (2 + gpt_entries_blocks, block_count - 2 - gpt_entries_blocks)
+        }
+    };
+
+    // Compute the position of every partition.
+    let alignment = if PARTITION_ALIGNMENT > block_size {
+        PARTITION_ALIGNMENT / block_size
+    } else {
+        1
+    };
+
+    let mut ranges = ArrayVec::<[(u64, u64); MAX_PARTITIONS]>::new();
+    let mut next_lba = utils::align_up(first_usable_lba, alignment);
+
+    for partition in partitions {
+        if next_lba > last_usable_lba {
+            return Err(FatError::NoSpaceLeft);
+        }
+
+        let partition_block_count = if partition.size == 0 {
+            last_usable_lba - next_lba + 1
+        } else {
+            partition.size / block_size
+        };
+
+        if partition_block_count == 0 || partition_block_count > last_usable_lba - next_lba + 1 {
+            return Err(FatError::NoSpaceLeft);
+        }
+
+        ranges.push((next_lba, partition_block_count));
+        next_lba = utils::align_up(next_lba + partition_block_count, alignment);
+    }
+
+    // Validate every partition before touching the storage device.
+    for (partition, (lba_start, partition_block_count)) in partitions.iter().zip(ranges.iter()) {
+        validate_layout(
+            table_type,
+            partition,
+            *lba_start,
+            *partition_block_count,
+            block_size,
+        )?;
+    }
+
+    match table_type {
+        PartitionTableType::Mbr => {
+            let mut mbr = MasterBootRecord::new_empty();
+
+            for (index, (partition, (lba_start, partition_block_count))) in
+                partitions.iter().zip(ranges.iter()).enumerate()
+            {
+                // Partition types and ranges were validated before.
+                if let PartitionType::Mbr(partition_type) = partition.partition_type {
+                    mbr.set_entry(
+                        index,
+                        &MbrPartitionEntry {
+                            status: if partition.bootable { 0x80 } else { 0x0 },
+                            partition_type,
+                            lba_start: *lba_start as u32,
+                            block_count: *partition_block_count as u32,
+                        },
+                    );
+                }
+            }
+
+            mbr.write(storage_device, 0)?;
+        }
+        PartitionTableType::Gpt { disk_guid } => {
+            let last_lba = block_count - 1;
+            let backup_entries_lba = last_lba - gpt_entries_blocks;
+
+            let mut entries_crc32 = 0;
+
+            // The partition entry array is written one block at a time on both copies.
+            let mut block = [0x0u8; MAXIMAL_BLOCK_SIZE];
+            let block = &mut block[..block_size as usize];
+            let entries_per_block = block_size as usize / GptPartitionEntry::MIN_LEN;
+
+            for block_index in 0..gpt_entries_blocks {
+                for byte in block.iter_mut() {
+                    *byte = 0;
+                }
+
+                for (entry_index, data) in block.chunks_mut(GptPartitionEntry::MIN_LEN).enumerate()
+                {
+                    let index = block_index as usize * entries_per_block + entry_index;
+
+                    if let (
+                        Some(PartitionLayout {
+                            partition_type: PartitionType::Gpt(partition_type),
+                            unique_guid,
+                            bootable,
+                            ..
+                        }),
+                        Some((lba_start, partition_block_count)),
+                    ) = (partitions.get(index), ranges.get(index))
+                    {
+                        GptPartitionEntry {
+                            partition_type: *partition_type,
+                            unique_guid: *unique_guid,
+                            first_lba: *lba_start,
+                            last_lba: lba_start + partition_block_count - 1,
+                            attributes: if *bootable {
+                                GptPartitionEntry::LEGACY_BIOS_BOOTABLE
+                            } else {
+                                0
+                            },
+                        }
+                        .to_raw(data);
+                    }
+                }
+
+                entries_crc32 = utils::crc32(entries_crc32, block);
+
+                for entries_lba in &[2, backup_entries_lba] {
+                    storage_device
+                        .write((entries_lba + block_index) * block_size, block)
+                        .or(Err(FatError::WriteFailed))?;
+                }
+            }
+
+            let mut header = GptHeader {
+                current_lba: 1,
+                backup_lba: last_lba,
+                first_usable_lba,
+                last_usable_lba,
+                disk_guid,
+                partition_entries_lba: 2,
+                partition_entry_count: GPT_ENTRY_COUNT,
+                partition_entry_size: GptPartitionEntry::MIN_LEN as u32,
+                partition_entries_crc32: entries_crc32,
+            };
+
+            header.write(storage_device, block_size)?;
+
+            header.current_lba = last_lba;
+            header.backup_lba = 1;
+            header.partition_entries_lba = backup_entries_lba;
+            header.write(storage_device, block_size)?;
+
+            // Finally write the protective MBR.
+            let mut mbr = MasterBootRecord::new_empty();
+            let protective_block_count = if last_lba > u64::from(u32::MAX) {
+                u32::MAX
+            } else {
+                last_lba as u32
+            };
+
+            mbr.set_entry(
+                0,
+                &MbrPartitionEntry {
+                    status: 0x0,
+                    partition_type: MbrPartitionEntry::PROTECTIVE_TYPE,
+                    lba_start: 1,
+                    block_count: protective_block_count,
+                },
+            );
+            mbr.write(storage_device, 0)?;
+        }
+    }
+
+    for (partition, (lba_start, partition_block_count)) in partitions.iter().zip(ranges.iter()) {
+        if let Some(options) = &partition.format {
+            crate::format_partition_with_options(
+                StorageDeviceRef(storage_device),
+                options,
+                lba_start * block_size,
+                partition_block_count * block_size,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a partition can be created as described by its layout.
+///
+/// The partition type must match the partition table type, the range must fit in a MBR entry and, if the partition is formatted, the type must be a FAT partition type.
+fn validate_layout(
+    table_type: PartitionTableType,
+    partition: &PartitionLayout,
+    lba_start: u64,
+    partition_block_count: u64,
+    block_size: u64,
+) -> FatFileSystemResult<()> {
+    let fat_type = match &partition.format {
+        Some(options) => Some(
+            options
+                .compute_geometry(partition_block_count * block_size)?
+                .fat_type,
+        ),
+        None => None,
+    };
+
+    match (table_type, partition.partition_type) {
+        (PartitionTableType::Mbr, PartitionType::Mbr(partition_type)) => {
+            if lba_start > u64::from(u32::MAX) || partition_block_count > u64::from(u32::MAX) {
+                return Err(FatError::InvalidPartition);
+            }
+
+            let entry = MbrPartitionEntry {
+                status: 0x0,
+                partition_type,
+                lba_start: lba_start as u32,
+                block_count: partition_block_count as u32,
+            };
+
+            // Any FAT partition type can hold any FAT variant.
+            if fat_type.is_some() && !entry.is_fat() {
+                return Err(FatError::InvalidPartition);
+            }
+
+            Ok(())
+        }
+        (PartitionTableType::Gpt { .. }, PartitionType::Gpt(partition_type)) => {
+            if fat_type.is_some() && !partition_type.is_fat() {
+                return Err(FatError::InvalidPartition);
+            }
+
+            Ok(())
+        }
+        _ => Err(FatError::InvalidPartition),
+    }
+}
+
+/// List every partition of the storage device (MBR primary and logical partitions or GPT partitions).
+///
+/// Unused entries and extended partition containers are not returned. Invalid GPT entries are skipped and a broken EBR chain ends the list of logical partitions, so the valid partitions are still listed.
+pub fn list_partitions(
+    storage_device: &mut dyn StorageDevice,
+    block_size: u64,
+) -> FatFileSystemResult<ArrayVec<[Partition; MAX_PARTITIONS]>> {
+    if block_size < MINIMAL_BLOCK_SIZE as u64 {
+        return Err(FatError::InvalidPartition);
+    }
+
+    let mut res = ArrayVec::new();
+
+    let mbr = MasterBootRecord::read(storage_device, 0)?;
+
+    if mbr.is_protective() {
+        let header = GptHeader::read_valid(storage_device, block_size)?;
+
+        for index in 0..header.partition_entry_count {
+            let entry = header.read_entry(storage_device, block_size, index)?;
+            if entry.is_empty() {
+                continue;
+            }
+
+            let partition =
+                match Partition::from_gpt_entry(u64::from(index), &entry, &header, block_size) {
+                    Ok(partition) => partition,
+                    Err(_) => continue,
+                };
+
+            push_partition(storage_device, &mut res, partition)?;
+        }
+
+        return Ok(res);
+    }
+
+    for index in 0..MasterBootRecord::ENTRY_COUNT {
+        let entry = mbr.entry(index);
+        if entry.is_empty() || entry.is_extended() {
+            continue;
+        }
+
+        push_partition(
+            storage_device,
+            &mut res,
+            Partition::from_mbr_entry(index as u64, &entry, block_size),
+        )?;
+    }
+
+    if let Some(extended_partition) = mbr.extended_partition() {
+        let mut logical_iter = LogicalPartitionIter::new(&extended_partition);
+        let mut index = MasterBootRecord::ENTRY_COUNT as u64;
+
+        while let Some(entry) = logical_iter.next(storage_device, block_size) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => break,
+            };
+
+            if !entry.is_empty() {
+                push_partition(
+                    storage_device,
+                    &mut res,
+                    Partition::from_mbr_entry(index, &entry, block_size),
+                )?;
+            }
+
+            index += 1;
+        }
+    }
+
+    Ok(res)
+}
+
+/// Detect the FAT filesystem type of a partition and add it to the partition list.
+fn push_partition(
+    storage_device: &mut dyn StorageDevice,
+    partitions: &mut ArrayVec<[Partition; MAX_PARTITIONS]>,
+    partition: Partition,
+) -> FatFileSystemResult<()> {
+    let mut partition = partition;
+
+    // A partition that can't be read is still listed.
+    partition.fat_type = crate::get_fat_type(storage_device, partition.start).ok();
+
+    partitions.try_push(partition).or(Err(FatError::Custom {
+        name: "Too many partitions",
+    }))
+}
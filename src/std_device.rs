@@ -0,0 +1,62 @@
+//! Storage device over the streams of the standard library.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use storage_device::{StorageDevice, StorageDeviceError, StorageDeviceResult};
+
+/// A storage device reading and writing any seekable stream, like a `std::fs::File` holding a disk image.
+///
+/// # Example
+///
+/// ```ignore
+/// let image = std::fs::OpenOptions::new().read(true).write(true).open("disk.img")?;
+/// let fs = libfat::get_raw_partition(IoStorageDevice::new(image))?;
+/// ```
+#[derive(Debug)]
+pub struct IoStorageDevice<T: Read + Write + Seek> {
+    /// The stream holding the content of the storage device.
+    inner: T,
+}
+
+impl<T: Read + Write + Seek> IoStorageDevice<T> {
+    /// Create a storage device over the given stream.
+    pub fn new(inner: T) -> Self {
+        IoStorageDevice { inner }
+    }
+
+    /// Get a reference to the underlying stream.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Give back the underlying stream.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Read + Write + Seek> StorageDevice for IoStorageDevice<T> {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> StorageDeviceResult<()> {
+        self.inner
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.inner.read_exact(buf))
+            .or(Err(StorageDeviceError::ReadError))
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> StorageDeviceResult<()> {
+        self.inner
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.inner.write_all(buf))
+            .or(Err(StorageDeviceError::WriteError))
+    }
+
+    fn len(&mut self) -> StorageDeviceResult<u64> {
+        self.inner
+            .seek(SeekFrom::End(0))
+            .or(Err(StorageDeviceError::Unknown))
+    }
+}
@@ -6,6 +6,7 @@ use super::Cluster;
 use super::FatError;
 use super::FatFileSystemResult;
 use super::FatFsType;
+use super::MINIMAL_BLOCK_SIZE;
 use storage_device::StorageDevice;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -242,7 +243,7 @@ impl FatValue {
                     data[1] = (value >> 4) as u8;
                 } else {
                     data[0] = value as u8;
-                    data[1] = (data[0] & 0xF0) | ((value >> 8) & 0x0F) as u8;
+                    data[1] = (data[1] & 0xF0) | ((value >> 8) & 0x0F) as u8;
                 }
 
                 fs.storage_device
@@ -264,12 +265,22 @@ impl FatValue {
         for fat_index in 0..u32::from(fs.boot_record.fats_count()) {
             Self::raw_put(fs, cluster, value, fat_index)?;
         }
+
+        fs.allocation_bitmap
+            .lock()
+            .set_used(cluster, value != FatValue::Free);
         Ok(())
     }
 
     /// Initialize clean FATs.
+    ///
+    /// The two reserved entries hold the media type and an end of chain marker like other implementations expect.
     pub(crate) fn initialize<S: StorageDevice>(fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
-        for i in 0..fs.boot_record.cluster_count {
+        let media_value = 0x0FFF_FF00 | u32::from(fs.boot_record.media_type());
+        Self::put(fs, Cluster(0), FatValue::Data(media_value))?;
+        Self::put(fs, Cluster(1), FatValue::EndOfChain)?;
+
+        for i in 2..fs.boot_record.cluster_count {
             Self::put(fs, Cluster(i), FatValue::Free)?;
         }
         Ok(())
@@ -300,19 +311,82 @@ pub fn get_last_and_previous_cluster<S: StorageDevice>(
     Ok((current_cluster, previous_cluster))
 }
 
+/// Call the given function with every cluster of the data region and its value in the first FAT.
+///
+/// The FAT is read block by block instead of entry by entry.
+pub(crate) fn for_each_cluster_value<S: StorageDevice, F: FnMut(Cluster, FatValue)>(
+    fs: &FatFileSystem<S>,
+    mut function: F,
+) -> FatFileSystemResult<()> {
+    let fat_type = fs.boot_record.fat_type;
+    let fat_start = fs.partition_start
+        + u64::from(fs.boot_record.reserved_block_count())
+            * u64::from(fs.boot_record.bytes_per_block());
+    let fat_len =
+        fs.boot_record.fat_size() as usize * usize::from(fs.boot_record.bytes_per_block());
+
+    // One byte more than a block so a FAT12 entry is never split between two reads.
+    let mut window = [0x0u8; MINIMAL_BLOCK_SIZE + 1];
+    let mut window_start = None;
+
+    for index in 2..fs.boot_record.cluster_count {
+        let cluster = Cluster(index);
+        let entry_offset = cluster.to_fat_offset(fat_type) as usize;
+        let entry_len = match fat_type {
+            FatFsType::Fat32 => 4,
+            FatFsType::Fat16 | FatFsType::Fat12 => 2,
+        };
+
+        let start = match window_start {
+            Some(start) if entry_offset + entry_len <= start + window.len() => start,
+            _ => {
+                let start = entry_offset - entry_offset % MINIMAL_BLOCK_SIZE;
+
+                // The last window stops at the end of the FAT.
+                let len = core::cmp::min(window.len(), fat_len.saturating_sub(start));
+                fs.storage_device
+                    .lock()
+                    .read(fat_start + start as u64, &mut window[..len])
+                    .or(Err(FatError::ReadFailed))?;
+                window_start = Some(start);
+                start
+            }
+        };
+
+        let entry = &window[entry_offset - start..entry_offset - start + entry_len];
+        let value = match fat_type {
+            FatFsType::Fat32 => FatValue::from_fat32_value(
+                u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) & 0x0FFF_FFFF,
+            ),
+            FatFsType::Fat16 => {
+                FatValue::from_fat16_value(u16::from_le_bytes([entry[0], entry[1]]))
+            }
+            FatFsType::Fat12 => {
+                let value = u16::from_le_bytes([entry[0], entry[1]]);
+
+                FatValue::from_fat12_value(if (index & 1) == 1 {
+                    value >> 4
+                } else {
+                    value & 0x0FFF
+                })
+            }
+        };
+
+        function(cluster, value);
+    }
+
+    Ok(())
+}
+
 /// Compute the whole cluster count of a given FileSystem.
 pub fn get_free_cluster_count<S: StorageDevice>(fs: &FatFileSystem<S>) -> FatFileSystemResult<u32> {
-    let mut current_cluster = Cluster(2);
-
     let mut res = 0;
 
-    while current_cluster.0 < fs.boot_record.cluster_count {
-        if let FatValue::Free = FatValue::get(fs, current_cluster)? {
+    for_each_cluster_value(fs, |_, value| {
+        if value == FatValue::Free {
             res += 1;
         }
-
-        current_cluster = Cluster(current_cluster.0 + 1);
-    }
+    })?;
 
     Ok(res)
 }
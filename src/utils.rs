@@ -3,7 +3,7 @@
 use crate::filesystem::FatFileSystem;
 use core::ops::{BitAnd, Not};
 use num_traits::Num;
-use storage_device::StorageDevice;
+use storage_device::{StorageDevice, StorageDeviceResult};
 
 /// Align the address to the next alignment.
 ///
@@ -28,6 +28,45 @@ pub fn align_down<T: Num + Not<Output = T> + BitAnd<Output = T> + Copy>(addr: T,
     addr & !(align - T::one())
 }
 
+/// A StorageDevice forwarding all operations to a borrowed StorageDevice.
+///
+/// This permit to create a filesystem on a storage device without giving up its ownership.
+pub(crate) struct StorageDeviceRef<'a>(pub &'a mut dyn StorageDevice);
+
+impl<'a> StorageDevice for StorageDeviceRef<'a> {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> StorageDeviceResult<()> {
+        self.0.read(offset, buf)
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> StorageDeviceResult<()> {
+        self.0.write(offset, buf)
+    }
+
+    fn len(&mut self) -> StorageDeviceResult<u64> {
+        self.0.len()
+    }
+}
+
+/// Compute the CRC32 (IEEE 802.3) of the given data.
+///
+/// The crc argument permit to continue a CRC32 computation on chunked data (0 at the start).
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            if (crc & 1) != 0 {
+                crc = (crc >> 1) ^ 0xEDB8_8320;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+
+    !crc
+}
+
 /// Retrieve the parent of a given path.
 ///
 /// Returns a tuple of the parts before and after the cut.
@@ -95,22 +134,22 @@ pub fn split_path(path: &str) -> (&str, Option<&str>) {
 /// A simple FileSystemIterator wrapper that implement Iterator.
 ///
 /// This permit to expose all Iterator methods not availaible in FileSystemIterator.
-pub struct GenericFileSystemIterator<'a, S: StorageDevice, T: FileSystemIterator<S>> {
+pub struct GenericFileSystemIterator<'a, 'm, S: StorageDevice, T: FileSystemIterator<S>> {
     /// A reference to the filesystem.
-    fs: &'a FatFileSystem<S>,
+    fs: &'a FatFileSystem<'m, S>,
     /// The filesystem iterator
     inner: T,
 }
 
-impl<'a, S: StorageDevice, T: FileSystemIterator<S>> GenericFileSystemIterator<'a, S, T> {
+impl<'a, 'm, S: StorageDevice, T: FileSystemIterator<S>> GenericFileSystemIterator<'a, 'm, S, T> {
     /// Create a new GenericFileSystemIterator
-    pub fn new(fs: &'a FatFileSystem<S>, inner: T) -> Self {
+    pub fn new(fs: &'a FatFileSystem<'m, S>, inner: T) -> Self {
         GenericFileSystemIterator { fs, inner }
     }
 }
 
-impl<'a, S: StorageDevice, T: FileSystemIterator<S>> Iterator
-    for GenericFileSystemIterator<'a, S, T>
+impl<'a, 'm, S: StorageDevice, T: FileSystemIterator<S>> Iterator
+    for GenericFileSystemIterator<'a, 'm, S, T>
 {
     type Item = T::Item;
 
@@ -143,18 +182,27 @@ pub trait FileSystemIterator<S: StorageDevice>: Sized {
     }
 
     /// Convert the FileSystemIterator to a regular iterator.
-    fn to_iterator<'a>(
+    fn to_iterator<'a, 'm>(
         self,
-        filesystem: &'a FatFileSystem<S>,
-    ) -> GenericFileSystemIterator<'a, S, Self> {
+        filesystem: &'a FatFileSystem<'m, S>,
+    ) -> GenericFileSystemIterator<'a, 'm, S, Self> {
         GenericFileSystemIterator::new(filesystem, self)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::crc32;
     use super::get_parent;
     use super::split_path;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(0, b""), 0);
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF4_3926);
+    }
+
     #[test]
     fn test_get_parent() {
         assert_eq!(
@@ -0,0 +1,172 @@
+//! Check that the allocation bitmap avoids reading the FAT without changing the allocation decisions.
+
+use libfat::FatFsType;
+
+mod common;
+
+use common::{format_image, CountingDevice, DeviceOperations, MemoryDevice};
+
+/// The size of the test images.
+const IMAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Fragment the filesystem and write a file over the holes, using a bitmap of the given size if any.
+fn modify_image(image: &mut [u8], bitmap_len: Option<usize>) {
+    let mut bitmap = Vec::new();
+    let mut fs = libfat::get_raw_partition(MemoryDevice(image)).unwrap();
+    let free_clusters = fs.volume_info().free_clusters;
+
+    if let Some(bitmap_len) = bitmap_len {
+        bitmap.resize(core::cmp::min(bitmap_len, fs.allocation_bitmap_len()), 0x0);
+        assert!(fs.set_allocation_bitmap(&mut bitmap).unwrap().is_none());
+        assert_eq!(fs.volume_info().free_clusters, free_clusters);
+    }
+
+    let cluster_size = fs.volume_info().cluster_size() as usize;
+
+    for index in 0..40u8 {
+        let path = format!("/file_{}.bin", index);
+        fs.create_file(&path).unwrap();
+
+        let mut file = fs.open_file(&path).unwrap();
+        file.write(&fs, 0, &vec![index; cluster_size * 2], true)
+            .unwrap();
+    }
+
+    for index in (0..40).step_by(2) {
+        fs.delete_file(&format!("/file_{}.bin", index)).unwrap();
+    }
+
+    fs.create_file("/big.bin").unwrap();
+    let mut file = fs.open_file("/big.bin").unwrap();
+    file.write(&fs, 0, &vec![0xAB; cluster_size * 50], true)
+        .unwrap();
+}
+
+/// Compare the modifications with bitmaps of different sizes to the modifications without bitmap.
+fn check_allocation_bitmap(fat_type: FatFsType) {
+    let mut expected_image = format_image(fat_type, IMAGE_SIZE);
+    modify_image(&mut expected_image, None);
+
+    // A full bitmap, a bitmap covering some of the fragmented clusters and an empty bitmap.
+    for bitmap_len in &[usize::MAX, 8, 0] {
+        let mut image = format_image(fat_type, IMAGE_SIZE);
+        modify_image(&mut image, Some(*bitmap_len));
+        assert!(image == expected_image, "bitmap of {} bytes", bitmap_len);
+    }
+}
+
+#[test]
+fn allocation_bitmap_fat12() {
+    check_allocation_bitmap(FatFsType::Fat12);
+}
+
+#[test]
+fn allocation_bitmap_fat16() {
+    check_allocation_bitmap(FatFsType::Fat16);
+}
+
+#[test]
+fn allocation_bitmap_fat32() {
+    check_allocation_bitmap(FatFsType::Fat32);
+}
+
+/// Count the reads done to write a file over a fragmented filesystem.
+fn count_fragmented_write_reads(use_bitmap: bool) -> usize {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    let operations = DeviceOperations::default();
+    let mut bitmap = Vec::new();
+
+    let mut fs = libfat::get_raw_partition(CountingDevice {
+        inner: MemoryDevice(&mut image),
+        operations: &operations,
+    })
+    .unwrap();
+
+    if use_bitmap {
+        bitmap.resize(fs.allocation_bitmap_len(), 0x0);
+        fs.set_allocation_bitmap(&mut bitmap).unwrap();
+    }
+
+    let cluster_size = fs.volume_info().cluster_size() as usize;
+
+    for index in 0..100 {
+        let path = format!("/file_{}.bin", index);
+        fs.create_file(&path).unwrap();
+
+        let mut file = fs.open_file(&path).unwrap();
+        file.write(&fs, 0, &vec![0x42; cluster_size * 4], true)
+            .unwrap();
+    }
+
+    for index in (0..100).step_by(2) {
+        fs.delete_file(&format!("/file_{}.bin", index)).unwrap();
+    }
+
+    fs.create_file("/big.bin").unwrap();
+    let mut file = fs.open_file("/big.bin").unwrap();
+
+    let read_count = operations.read_count();
+    file.write(&fs, 0, &vec![0xAB; cluster_size * 300], true)
+        .unwrap();
+    operations.read_count() - read_count
+}
+
+#[test]
+fn allocation_without_fat_reads() {
+    let reads_with_bitmap = count_fragmented_write_reads(true);
+    let reads_without_bitmap = count_fragmented_write_reads(false);
+
+    assert!(reads_with_bitmap < reads_without_bitmap);
+}
+
+#[test]
+fn replace_allocation_bitmap() {
+    let mut image = format_image(FatFsType::Fat32, IMAGE_SIZE);
+    let mut bitmap = Vec::new();
+    let mut small_bitmap = [0x0u8; 16];
+
+    let mut fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let free_clusters = fs.volume_info().free_clusters;
+
+    let bitmap_len = fs.allocation_bitmap_len();
+    bitmap.resize(bitmap_len, 0x0);
+    assert!(fs.set_allocation_bitmap(&mut bitmap).unwrap().is_none());
+
+    fs.create_directory("/dir").unwrap();
+    assert_eq!(fs.volume_info().free_clusters, free_clusters - 1);
+
+    // Replacing the bitmap gives back the previous memory and recounts the free clusters.
+    let previous_bitmap = fs.set_allocation_bitmap(&mut small_bitmap).unwrap();
+    assert_eq!(previous_bitmap.unwrap().len(), bitmap_len);
+    assert_eq!(fs.volume_info().free_clusters, free_clusters - 1);
+}
+
+#[test]
+fn fat_at_the_end_of_the_device() {
+    for fat_type in &[FatFsType::Fat12, FatFsType::Fat16, FatFsType::Fat32] {
+        let mut image = format_image(*fat_type, IMAGE_SIZE);
+        let info = libfat::get_raw_partition(MemoryDevice(&mut image))
+            .unwrap()
+            .volume_info();
+        let fat_end = (u64::from(info.reserved_block_count) + u64::from(info.fat_size))
+            * u64::from(info.bytes_per_block);
+
+        // Nothing after the first FAT can be read.
+        let mut bitmap = Vec::new();
+        let mut fs = libfat::get_raw_partition_with_start(
+            MemoryDevice(&mut image[..fat_end as usize]),
+            0,
+            IMAGE_SIZE as u64,
+        )
+        .unwrap();
+
+        bitmap.resize(fs.allocation_bitmap_len(), 0x0);
+        fs.set_allocation_bitmap(&mut bitmap).unwrap();
+        assert_eq!(
+            fs.volume_info().free_clusters,
+            info.free_clusters,
+            "{:?}",
+            fat_type
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Change the attributes of files and directories.
+
+use libfat::attribute::Attributes;
+use libfat::FatFsType;
+
+mod common;
+
+use common::{format_image, MemoryDevice};
+
+/// The size of the test image.
+const IMAGE_SIZE: usize = 8 * 1024 * 1024;
+
+#[test]
+fn set_attributes() {
+    let mut image = format_image(FatFsType::Fat12, IMAGE_SIZE);
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        fs.create_directory("/system").unwrap();
+        fs.create_file("/system/boot.bin").unwrap();
+
+        // New files need to be backed up.
+        let attribute = fs.search_entry("/system/boot.bin").unwrap().attribute;
+        assert!(attribute.is_archive());
+
+        fs.set_attributes(
+            "/system/boot.bin",
+            attribute.set(Attributes::HIDDEN | Attributes::SYSTEM),
+        )
+        .unwrap();
+
+        let attribute = fs.search_entry("/system").unwrap().attribute;
+        fs.set_attributes("/system", attribute.set(Attributes::HIDDEN))
+            .unwrap();
+
+        // The directory, volume and device bits are protected.
+        assert!(fs
+            .set_attributes("/system", attribute.clear(Attributes::DIRECTORY))
+            .is_err());
+        assert!(fs
+            .set_attributes("/system/boot.bin", Attributes::new(Attributes::DIRECTORY))
+            .is_err());
+        assert!(fs
+            .set_attributes("/system/boot.bin", Attributes::new(Attributes::LFN))
+            .is_err());
+        assert!(fs
+            .set_attributes("/system/boot.bin", Attributes::new(Attributes::DEVICE))
+            .is_err());
+        assert!(fs.set_attributes("/", Attributes::new(0)).is_err());
+    }
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+    let attribute = fs.search_entry("/system/boot.bin").unwrap().attribute;
+    assert!(attribute.is_hidden() && attribute.is_system() && attribute.is_archive());
+    assert!(!attribute.is_directory());
+
+    let attribute = fs.search_entry("/system").unwrap().attribute;
+    assert!(attribute.is_hidden() && attribute.is_directory());
+
+    // Clear the archive bit like a backup tool would do.
+    fs.set_attributes("/system/boot.bin", Attributes::new(0))
+        .unwrap();
+    let mut file = fs.open_file("/system/boot.bin").unwrap();
+    assert_eq!(file.file_info.attribute.get_value(), 0);
+
+    // Any modification sets it back.
+    file.write(&fs, 0, b"boot", true).unwrap();
+    assert!(file.file_info.attribute.is_archive());
+    assert!(fs
+        .search_entry("/system/boot.bin")
+        .unwrap()
+        .attribute
+        .is_archive());
+
+    fs.set_attributes("/system/boot.bin", Attributes::new(0))
+        .unwrap();
+    let mut file = fs.open_file("/system/boot.bin").unwrap();
+    file.write(&fs, 1, b"O", false).unwrap();
+    assert!(fs
+        .search_entry("/system/boot.bin")
+        .unwrap()
+        .attribute
+        .is_archive());
+}
@@ -0,0 +1,183 @@
+//! Check that the block cache reduces the device accesses and writes back every modification.
+
+use libfat::{CacheBlock, FatFsType, FormatOptions};
+
+mod common;
+
+use common::{
+    format_image, format_image_with_options, CountingDevice, DeviceOperations, MemoryDevice,
+};
+
+/// The size of the test images.
+const IMAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The content of the file at the given index.
+fn file_content(index: usize) -> Vec<u8> {
+    (0..index * 700)
+        .map(|value| (value + index) as u8)
+        .collect()
+}
+
+/// Create, write, rename and delete files using a cache of the given size.
+fn modify_image(image: &mut [u8], bytes_per_block: u16, cache_size: usize) {
+    let mut cache = vec![CacheBlock::EMPTY; cache_size];
+    let mut memory = vec![0x0u8; cache_size * usize::from(bytes_per_block)];
+    let mut fs = libfat::get_raw_partition(MemoryDevice(image)).unwrap();
+    assert_eq!(fs.cache_memory_len(cache_size), memory.len());
+    fs.set_cache(&mut cache, &mut memory).unwrap();
+
+    fs.create_directory("/dir").unwrap();
+    for index in 0..20 {
+        let path = format!("/dir/file_{}.bin", index);
+        fs.create_file(&path).unwrap();
+
+        let mut file = fs.open_file(&path).unwrap();
+        file.write(&fs, 0, &file_content(index), true).unwrap();
+    }
+
+    fs.rename_file("/dir/file_3.bin", "/moved.bin").unwrap();
+    fs.delete_file("/dir/file_4.bin").unwrap();
+
+    let mut file = fs.open_file("/dir/file_5.bin").unwrap();
+    file.set_len(&fs, 100).unwrap();
+
+    // Partial write over blocks already written to the storage device.
+    let mut file = fs.open_file("/dir/file_19.bin").unwrap();
+    file.write(&fs, 511, &[0xAA; 3], false).unwrap();
+
+    fs.flush().unwrap();
+}
+
+/// Modify an image through a cache of the given size and compare it to the same modifications without cache.
+fn check_cache(fat_type: FatFsType, bytes_per_block: u16, cache_size: usize) {
+    let options = FormatOptions::new()
+        .fat_type(fat_type)
+        .bytes_per_block(bytes_per_block);
+
+    let mut expected_image = format_image_with_options(&options, IMAGE_SIZE);
+    modify_image(&mut expected_image, bytes_per_block, 0);
+
+    let mut image = format_image_with_options(&options, IMAGE_SIZE);
+    modify_image(&mut image, bytes_per_block, cache_size);
+    assert!(image == expected_image);
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+    for index in 0..20 {
+        let path = match index {
+            3 => String::from("/moved.bin"),
+            _ => format!("/dir/file_{}.bin", index),
+        };
+
+        if index == 4 {
+            assert!(fs.search_entry(&path).is_err());
+            continue;
+        }
+
+        let mut expected = file_content(index);
+        match index {
+            5 => expected.truncate(100),
+            19 => expected[511..514].copy_from_slice(&[0xAA; 3]),
+            _ => {}
+        }
+
+        let mut file = fs.open_file(&path).unwrap();
+        let mut content = vec![0x0u8; expected.len()];
+        assert_eq!(
+            file.read(&fs, 0, &mut content).unwrap(),
+            expected.len() as u64
+        );
+        assert_eq!(content, expected, "{}", path);
+    }
+}
+
+#[test]
+fn cache_fat12() {
+    check_cache(FatFsType::Fat12, 512, 16);
+}
+
+#[test]
+fn cache_fat16() {
+    check_cache(FatFsType::Fat16, 512, 16);
+}
+
+#[test]
+fn cache_fat32() {
+    check_cache(FatFsType::Fat32, 512, 16);
+}
+
+#[test]
+fn tiny_cache() {
+    check_cache(FatFsType::Fat12, 512, 1);
+    check_cache(FatFsType::Fat32, 512, 2);
+}
+
+#[test]
+fn large_blocks() {
+    check_cache(FatFsType::Fat12, 4096, 16);
+    check_cache(FatFsType::Fat16, 2048, 2);
+}
+
+#[test]
+fn write_back() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    let operations = DeviceOperations::default();
+    let mut cache = [CacheBlock::EMPTY; 32];
+    let mut memory = [0x0u8; 32 * 512];
+
+    {
+        let mut fs = libfat::get_raw_partition(CountingDevice {
+            inner: MemoryDevice(&mut image),
+            operations: &operations,
+        })
+        .unwrap();
+        fs.set_cache(&mut cache, &mut memory).unwrap();
+
+        fs.create_file("/file.txt").unwrap();
+        fs.search_entry("/file.txt").unwrap();
+
+        // Once cached, looking up an entry doesn't access the storage device.
+        let read_count = operations.read_count();
+        for _ in 0..10 {
+            fs.search_entry("/file.txt").unwrap();
+        }
+        assert_eq!(operations.read_count(), read_count);
+
+        // Nothing is written until the cache is flushed.
+        assert_eq!(operations.write_count(), 0);
+        fs.flush().unwrap();
+        assert_ne!(operations.write_count(), 0);
+
+        // Flushing a clean cache doesn't write anything.
+        let write_count = operations.write_count();
+        fs.flush().unwrap();
+        assert_eq!(operations.write_count(), write_count);
+
+        fs.create_directory("/dir").unwrap();
+    }
+
+    // The cache is written back when the filesystem is dropped.
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    fs.search_entry("/file.txt").unwrap();
+    fs.search_entry("/dir").unwrap();
+}
+
+#[test]
+fn cache_size_follows_memory() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    let operations = DeviceOperations::default();
+    let mut cache = [CacheBlock::EMPTY; 32];
+
+    // Without memory for a whole block, nothing is cached.
+    let mut memory = [0x0u8; 511];
+
+    let mut fs = libfat::get_raw_partition(CountingDevice {
+        inner: MemoryDevice(&mut image),
+        operations: &operations,
+    })
+    .unwrap();
+    fs.set_cache(&mut cache, &mut memory).unwrap();
+
+    fs.create_file("/file.txt").unwrap();
+    assert_ne!(operations.write_count(), 0);
+}
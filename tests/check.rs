@@ -0,0 +1,481 @@
+//! Check the consistency checker and the repairs on clean filesystems and on images corrupted on purpose.
+
+use libfat::check::{CheckOptions, CheckReport, Problem, Repair, RepairOptions};
+use libfat::filesystem::VolumeInfo;
+use libfat::{FatError, FatFsType};
+
+mod common;
+
+use common::{format_image, MemoryDevice};
+
+/// The size of the FAT16 test images.
+const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+
+/// Read a little endian u16 at the given offset.
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Write a little endian u16 at the given offset.
+fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Write a little endian u32 at the given offset.
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Format an image and fill it with a few files and directories.
+///
+/// `/dir` spans several clusters and `/dir/later` is created in its last one.
+/// `/moved` and `/dir/later/inner` are moved after their creation.
+fn build_image(fat_type: FatFsType, image_size: usize) -> (Vec<u8>, VolumeInfo) {
+    let mut image = format_image(fat_type, image_size);
+
+    let info = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let cluster_size = fs.volume_info().cluster_size() as usize;
+
+        fs.create_file("/small.txt").unwrap();
+        let mut file = fs.open_file("/small.txt").unwrap();
+        file.write(&fs, 0, &[0x42; 100], true).unwrap();
+
+        fs.create_file("/A long file name.bin").unwrap();
+        let mut file = fs.open_file("/A long file name.bin").unwrap();
+        file.write(&fs, 0, &vec![0x43; cluster_size * 3], true)
+            .unwrap();
+
+        fs.create_directory("/dir").unwrap();
+        fs.create_directory("/dir/sub").unwrap();
+        for index in 0..cluster_size / 32 {
+            fs.create_file(&format!("/dir/file_{}.bin", index)).unwrap();
+        }
+        fs.create_directory("/dir/later").unwrap();
+        fs.create_file("/dir/later/empty.txt").unwrap();
+
+        // Move directories to and from the root directory.
+        fs.create_directory("/dir/moved").unwrap();
+        fs.create_file("/dir/moved/file.txt").unwrap();
+        fs.rename_directory("/dir/moved", "/moved").unwrap();
+        fs.create_directory("/outer").unwrap();
+        fs.rename_directory("/outer", "/dir/later/inner").unwrap();
+
+        fs.volume_info()
+    };
+
+    (image, info)
+}
+
+/// Check an image and collect the problems found.
+fn check_image(image: &mut [u8], options: &CheckOptions) -> (CheckReport, Vec<Problem>) {
+    let fs = libfat::get_raw_partition(MemoryDevice(image)).unwrap();
+    let mut memory = vec![0x0u8; fs.check_memory_len()];
+    let mut problems = Vec::new();
+
+    let report = fs
+        .check(options, &mut memory, |problem| problems.push(*problem))
+        .unwrap();
+    assert_eq!(report.problem_count as usize, problems.len());
+
+    (report, problems)
+}
+
+/// Get the byte offset of the first FAT.
+fn fat_offset(info: &VolumeInfo) -> usize {
+    info.reserved_block_count as usize * info.bytes_per_block as usize
+}
+
+/// Get the byte offset of the root directory of a FAT16 image, or of the data region of a FAT32 image.
+fn root_offset(info: &VolumeInfo) -> usize {
+    fat_offset(info)
+        + info.fats_count as usize * info.fat_size as usize * info.bytes_per_block as usize
+}
+
+/// Get the byte offset of a cluster.
+fn cluster_offset(info: &VolumeInfo, cluster: u16) -> usize {
+    root_offset(info)
+        + info.root_dir_childs_count as usize * 32
+        + (cluster as usize - 2) * info.cluster_size() as usize
+}
+
+/// Write a FAT16 entry in every FAT.
+fn put_fat16(image: &mut [u8], info: &VolumeInfo, cluster: u16, value: u16) {
+    let fat_len = info.fat_size as usize * info.bytes_per_block as usize;
+
+    for index in 0..info.fats_count as usize {
+        write_u16(
+            image,
+            fat_offset(info) + index * fat_len + cluster as usize * 2,
+            value,
+        );
+    }
+}
+
+/// Write a FAT32 entry in every FAT.
+fn put_fat32(image: &mut [u8], info: &VolumeInfo, cluster: u16, value: u32) {
+    let fat_len = info.fat_size as usize * info.bytes_per_block as usize;
+
+    for index in 0..info.fats_count as usize {
+        write_u32(
+            image,
+            fat_offset(info) + index * fat_len + cluster as usize * 4,
+            value,
+        );
+    }
+}
+
+/// Find the byte offset of the 8.3 entry starting with the given name in the given entries.
+fn find_entry(image: &[u8], start: usize, len: usize, name: &[u8]) -> usize {
+    (start..start + len)
+        .step_by(32)
+        .find(|offset| image[offset + 11] != 0x0F && image[*offset..offset + name.len()] == *name)
+        .unwrap()
+}
+
+/// Find the byte offset of the 8.3 entry starting with the given name in the root directory of a FAT16 image.
+fn find_root_entry(image: &[u8], info: &VolumeInfo, name: &[u8]) -> usize {
+    find_entry(
+        image,
+        root_offset(info),
+        info.root_dir_childs_count as usize * 32,
+        name,
+    )
+}
+
+/// Check an image, plan and apply the repairs of every problem, then check that the image is clean.
+fn repair_image(image: &mut [u8], options: &RepairOptions) -> Vec<Repair> {
+    let (_, problems) = check_image(image, &CheckOptions::new());
+    let fs = libfat::get_raw_partition(MemoryDevice(image)).unwrap();
+
+    let repairs: Vec<Repair> = problems
+        .iter()
+        .map(|problem| fs.plan_repair(problem, options).unwrap())
+        .collect();
+    for repair in &repairs {
+        fs.apply_repair(repair).unwrap();
+    }
+    drop(fs);
+
+    let (report, problems) = check_image(image, &CheckOptions::new());
+    assert!(report.is_clean(), "{:?}", problems);
+
+    repairs
+}
+
+#[test]
+fn clean_filesystems() {
+    let geometries = [
+        (FatFsType::Fat12, 1440 * 1024),
+        (FatFsType::Fat16, IMAGE_SIZE),
+        (FatFsType::Fat32, 64 * 1024 * 1024),
+    ];
+
+    for (fat_type, image_size) in &geometries {
+        let (mut image, info) = build_image(*fat_type, *image_size);
+        let (report, problems) = check_image(&mut image, &CheckOptions::new());
+
+        assert!(report.is_clean(), "{:?}: {:?}", fat_type, problems);
+        assert_eq!(report.directory_count, 5);
+        assert_eq!(report.file_count, 4 + info.cluster_size() / 32);
+        assert_eq!(report.free_clusters, info.free_clusters);
+        assert_eq!(report.lost_clusters, 0);
+        assert_eq!(
+            report.used_clusters + report.free_clusters,
+            info.total_clusters
+        );
+    }
+}
+
+#[test]
+fn fat_mismatch() {
+    let (mut image, info) = build_image(FatFsType::Fat16, IMAGE_SIZE);
+    let fat_len = info.fat_size as usize * info.bytes_per_block as usize;
+    image[fat_offset(&info) + fat_len + 1000] ^= 0x01;
+
+    let (_, problems) = check_image(&mut image, &CheckOptions::new());
+    assert_eq!(problems.len(), 1);
+    assert!(matches!(
+        problems[0],
+        Problem::FatMismatch {
+            fat_index: 1,
+            offset: 512
+        }
+    ));
+}
+
+#[test]
+fn lost_chain() {
+    let (mut image, info) = build_image(FatFsType::Fat16, IMAGE_SIZE);
+    put_fat16(&mut image, &info, 1000, 1001);
+    put_fat16(&mut image, &info, 1001, 1002);
+    put_fat16(&mut image, &info, 1002, 0xFFFF);
+
+    // A lost loop has no first cluster.
+    put_fat16(&mut image, &info, 2000, 2001);
+    put_fat16(&mut image, &info, 2001, 2000);
+
+    let (report, problems) = check_image(&mut image, &CheckOptions::new());
+    assert_eq!(report.lost_clusters, 5);
+    assert_eq!(problems.len(), 2);
+    assert!(matches!(
+        problems[0],
+        Problem::LostChain {
+            start_cluster: 1000,
+            cluster_count: 3
+        }
+    ));
+    assert!(matches!(
+        problems[1],
+        Problem::LostChain {
+            start_cluster: 2000,
+            cluster_count: 2
+        }
+    ));
+}
+
+#[test]
+fn cross_linked_chains() {
+    let (mut image, info) = build_image(FatFsType::Fat16, IMAGE_SIZE);
+    let long_name_entry = find_root_entry(&image, &info, b"ALONGF");
+    let small_entry = find_root_entry(&image, &info, b"SMALL   TXT");
+    let small_cluster = read_u16(&image, small_entry + 26);
+
+    // Make the small file share the last two clusters of the other file.
+    let long_name_cluster = read_u16(&image, long_name_entry + 26);
+    write_u16(&mut image, small_entry + 26, long_name_cluster + 1);
+
+    let (report, problems) = check_image(&mut image, &CheckOptions::new());
+    assert_eq!(report.lost_clusters, 1);
+
+    let cross_linked = problems
+        .iter()
+        .filter(|problem| matches!(problem, Problem::CrossLinkedChain { .. }))
+        .count();
+    assert_eq!(cross_linked, 1);
+    assert!(problems.iter().any(|problem| matches!(
+        problem,
+        Problem::LostChain { start_cluster, cluster_count: 1 } if *start_cluster == u32::from(small_cluster)
+    )));
+}
+
+#[test]
+fn file_size_mismatch() {
+    let (mut image, info) = build_image(FatFsType::Fat16, IMAGE_SIZE);
+    let small_entry = find_root_entry(&image, &info, b"SMALL   TXT");
+    let long_name_entry = find_root_entry(&image, &info, b"ALONGF");
+    write_u32(&mut image, small_entry + 28, info.cluster_size() + 1);
+    write_u32(&mut image, long_name_entry + 28, 1);
+
+    let (_, problems) = check_image(&mut image, &CheckOptions::new());
+    assert_eq!(problems.len(), 2);
+    assert!(problems.iter().any(|problem| matches!(
+        problem,
+        Problem::ChainTooShort { entry, cluster_count: 1 } if &entry.short_name == b"SMALL   TXT"
+    )));
+    assert!(problems.iter().any(|problem| matches!(
+        problem,
+        Problem::ChainTooLong { entry, cluster_count: 3 } if entry.file_size == 1
+    )));
+
+    // Longer chains can be accepted for preallocated files.
+    let (report, problems) = check_image(&mut image, &CheckOptions::new().allow_preallocated(true));
+    assert_eq!(problems.len(), 1);
+    assert_eq!(report.preallocated_files, 1);
+}
+
+#[test]
+fn invalid_chain() {
+    let (mut image, info) = build_image(FatFsType::Fat16, IMAGE_SIZE);
+    let long_name_entry = find_root_entry(&image, &info, b"ALONGF");
+    let long_name_cluster = read_u16(&image, long_name_entry + 26);
+    put_fat16(&mut image, &info, long_name_cluster + 1, 0xFFF0);
+
+    let (report, problems) = check_image(&mut image, &CheckOptions::new());
+    assert_eq!(report.lost_clusters, 1);
+    assert!(problems.iter().any(|problem| matches!(
+        problem,
+        Problem::InvalidChain { entry, cluster_count: 2 } if entry.location.entry_count > 1
+    )));
+}
+
+#[test]
+fn invalid_parent_entry() {
+    let (mut image, info) = build_image(FatFsType::Fat16, IMAGE_SIZE);
+    let dir_entry = find_root_entry(&image, &info, b"DIR        ");
+    let dir_cluster = read_u16(&image, dir_entry + 26);
+    let sub_entry = find_entry(
+        &image,
+        cluster_offset(&info, dir_cluster),
+        info.cluster_size() as usize,
+        b"SUB        ",
+    );
+    let sub_cluster = read_u16(&image, sub_entry + 26);
+
+    // Point the ".." entry of /dir/sub to the root directory.
+    write_u16(&mut image, cluster_offset(&info, sub_cluster) + 32 + 26, 0);
+
+    let (_, problems) = check_image(&mut image, &CheckOptions::new());
+    assert_eq!(problems.len(), 1);
+    assert!(matches!(
+        problems[0],
+        Problem::InvalidParentEntry { entry, parent_cluster } if parent_cluster == u32::from(dir_cluster) && entry.is_directory
+    ));
+}
+
+#[test]
+fn orphan_long_name() {
+    let (mut image, info) = build_image(FatFsType::Fat16, IMAGE_SIZE);
+    let long_name_entry = find_root_entry(&image, &info, b"ALONGF");
+
+    // Break the checksum stored in the last long name entry.
+    image[long_name_entry - 32 + 13] ^= 0xFF;
+
+    let (report, problems) = check_image(&mut image, &CheckOptions::new());
+    assert_eq!(report.file_count, 4 + info.cluster_size() / 32);
+    assert_eq!(problems.len(), 1);
+    assert!(matches!(
+        problems[0],
+        Problem::OrphanLongName { location } if location.directory_cluster == 0 && location.entry_count == 2
+    ));
+}
+
+#[test]
+fn wrong_free_count() {
+    let (mut image, info) = build_image(FatFsType::Fat32, 64 * 1024 * 1024);
+    let fs_info_offset = info.fs_info_block.unwrap() as usize * info.bytes_per_block as usize;
+    write_u32(&mut image, fs_info_offset + 488, info.free_clusters + 5);
+
+    let (_, problems) = check_image(&mut image, &CheckOptions::new());
+    assert_eq!(problems.len(), 1);
+    assert!(matches!(
+        problems[0],
+        Problem::WrongFreeCount { stored, actual } if stored == info.free_clusters + 5 && actual == info.free_clusters
+    ));
+
+    // An unknown count isn't a problem.
+    write_u32(&mut image, fs_info_offset + 488, 0xFFFF_FFFF);
+    let (report, _) = check_image(&mut image, &CheckOptions::new());
+    assert!(report.is_clean());
+}
+
+#[test]
+fn not_enough_memory() {
+    let (mut image, _) = build_image(FatFsType::Fat12, 1440 * 1024);
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let mut memory = vec![0x0u8; fs.check_memory_len() - 1];
+
+    assert!(matches!(
+        fs.check(&CheckOptions::new(), &mut memory, |_| {}),
+        Err(FatError::Custom { .. })
+    ));
+}
+
+#[test]
+fn repair_problems() {
+    let (mut image, info) = build_image(FatFsType::Fat16, IMAGE_SIZE);
+    let fat_len = info.fat_size as usize * info.bytes_per_block as usize;
+    let long_name_entry = find_root_entry(&image, &info, b"ALONGF");
+    let small_entry = find_root_entry(&image, &info, b"SMALL   TXT");
+    let dir_entry = find_root_entry(&image, &info, b"DIR        ");
+    let dir_cluster = read_u16(&image, dir_entry + 26);
+    let sub_entry = find_entry(
+        &image,
+        cluster_offset(&info, dir_cluster),
+        info.cluster_size() as usize,
+        b"SUB        ",
+    );
+    let sub_cluster = read_u16(&image, sub_entry + 26);
+
+    image[fat_offset(&info) + fat_len + 1000] ^= 0x01;
+    put_fat16(&mut image, &info, 1000, 1001);
+    put_fat16(&mut image, &info, 1001, 0xFFFF);
+    put_fat16(&mut image, &info, 2000, 2001);
+    put_fat16(&mut image, &info, 2001, 2000);
+    image[long_name_entry - 32 + 13] ^= 0xFF;
+    let long_name_cluster = read_u16(&image, long_name_entry + 26);
+    write_u16(&mut image, small_entry + 26, long_name_cluster + 1);
+    write_u16(&mut image, cluster_offset(&info, sub_cluster) + 32 + 26, 0);
+
+    // Planning the repairs doesn't modify the image.
+    let corrupted_image = image.clone();
+    let (_, problems) = check_image(&mut image, &CheckOptions::new());
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        for problem in &problems {
+            assert!(fs.plan_repair(problem, &RepairOptions::new()).is_some());
+        }
+    }
+    assert!(image == corrupted_image);
+
+    let repairs = repair_image(&mut image, &RepairOptions::new());
+    assert_eq!(repairs.len(), problems.len());
+    assert!(repairs.iter().any(|repair| matches!(
+        repair,
+        Repair::FreeChain {
+            start_cluster: 1000,
+            cluster_count: 2
+        }
+    )));
+
+    // The FATs are identical again and the other files are untouched.
+    let fat = fat_offset(&info);
+    assert!(image[fat..fat + fat_len] == image[fat + fat_len..fat + 2 * fat_len]);
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let report = {
+        let mut memory = vec![0x0u8; fs.check_memory_len()];
+        fs.check(&CheckOptions::new(), &mut memory, |_| {}).unwrap()
+    };
+    assert_eq!(report.lost_clusters, 0);
+    assert_eq!(fs.volume_info().free_clusters, report.free_clusters);
+    assert!(fs.open_directory("/dir/later/inner").is_ok());
+    assert!(fs.open_file("/moved/file.txt").is_ok());
+
+    let mut file = fs.open_file("/small.txt").unwrap();
+    let mut content = [0x0u8; 100];
+    assert_eq!(file.read(&fs, 0, &mut content).unwrap(), 100);
+    assert!(content.iter().all(|byte| *byte == 0x43));
+}
+
+#[test]
+fn save_lost_chains() {
+    let (mut image, info) = build_image(FatFsType::Fat32, 64 * 1024 * 1024);
+    let cluster_size = info.cluster_size() as usize;
+    let fs_info_offset = info.fs_info_block.unwrap() as usize * info.bytes_per_block as usize;
+
+    put_fat32(&mut image, &info, 5000, 5001);
+    put_fat32(&mut image, &info, 5001, 5002);
+    put_fat32(&mut image, &info, 5002, 0x0FFF_FFFF);
+    for cluster in 5000..5003 {
+        let offset = cluster_offset(&info, cluster);
+        image[offset..offset + cluster_size]
+            .iter_mut()
+            .for_each(|byte| *byte = cluster as u8);
+    }
+    write_u32(&mut image, fs_info_offset + 488, 42);
+
+    let repairs = repair_image(&mut image, &RepairOptions::new().save_lost_chains(true));
+    assert!(matches!(
+        repairs[..],
+        [
+            Repair::SaveChain {
+                start_cluster: 5000,
+                cluster_count: 3
+            },
+            Repair::UpdateFreeCount
+        ]
+    ));
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let mut file = fs.open_file("/FOUND.000/FILE0000.CHK").unwrap();
+    assert_eq!(file.file_info.file_size as usize, 3 * cluster_size);
+
+    let mut content = vec![0x0u8; 3 * cluster_size];
+    file.read(&fs, 0, &mut content).unwrap();
+    for (index, chunk) in content.chunks(cluster_size).enumerate() {
+        assert!(chunk
+            .iter()
+            .all(|byte| usize::from(*byte) == (5000 + index) % 256));
+    }
+}
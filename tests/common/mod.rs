@@ -0,0 +1,99 @@
+//! Helpers shared by the integration tests.
+
+// Every test doesn't use every helper.
+#![allow(dead_code)]
+
+use core::cell::RefCell;
+use libfat::{FatFsType, FormatOptions};
+use storage_device::{StorageDevice, StorageDeviceError, StorageDeviceResult};
+
+/// A storage device backed by a borrowed byte buffer.
+pub struct MemoryDevice<'a>(pub &'a mut [u8]);
+
+impl<'a> StorageDevice for MemoryDevice<'a> {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> StorageDeviceResult<()> {
+        let offset = offset as usize;
+        let data = self
+            .0
+            .get(offset..offset + buf.len())
+            .ok_or(StorageDeviceError::ReadError)?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> StorageDeviceResult<()> {
+        let offset = offset as usize;
+        let data = self
+            .0
+            .get_mut(offset..offset + buf.len())
+            .ok_or(StorageDeviceError::WriteError)?;
+        data.copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn len(&mut self) -> StorageDeviceResult<u64> {
+        Ok(self.0.len() as u64)
+    }
+}
+
+/// The operations forwarded by a CountingDevice, kept by the test while the filesystem owns the device.
+#[derive(Default)]
+pub struct DeviceOperations {
+    /// The sizes of the read operations.
+    pub reads: RefCell<Vec<usize>>,
+
+    /// The sizes of the write operations.
+    pub writes: RefCell<Vec<usize>>,
+}
+
+impl DeviceOperations {
+    /// Get the count of read operations.
+    pub fn read_count(&self) -> usize {
+        self.reads.borrow().len()
+    }
+
+    /// Get the count of write operations.
+    pub fn write_count(&self) -> usize {
+        self.writes.borrow().len()
+    }
+}
+
+/// A storage device counting the operations forwarded to another storage device and recording their sizes.
+pub struct CountingDevice<'a> {
+    /// The storage device doing the operations.
+    pub inner: MemoryDevice<'a>,
+
+    /// The operations done.
+    pub operations: &'a DeviceOperations,
+}
+
+impl<'a> StorageDevice for CountingDevice<'a> {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> StorageDeviceResult<()> {
+        self.operations.reads.borrow_mut().push(buf.len());
+        self.inner.read(offset, buf)
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> StorageDeviceResult<()> {
+        self.operations.writes.borrow_mut().push(buf.len());
+        self.inner.write(offset, buf)
+    }
+
+    fn len(&mut self) -> StorageDeviceResult<u64> {
+        self.inner.len()
+    }
+}
+
+/// Format an image of the given type and size.
+pub fn format_image(fat_type: FatFsType, size: usize) -> Vec<u8> {
+    format_image_with_options(&FormatOptions::new().fat_type(fat_type), size)
+}
+
+/// Format an image of the given size with the given options.
+pub fn format_image_with_options(options: &FormatOptions, size: usize) -> Vec<u8> {
+    let mut image = vec![0x0u8; size];
+
+    libfat::format_partition_with_options(MemoryDevice(&mut image), options, 0, size as u64)
+        .unwrap();
+
+    image
+}
@@ -0,0 +1,179 @@
+//! Check that the contiguous parts of files are read and written with a single storage device operation.
+
+use libfat::FatFsType;
+
+mod common;
+
+use common::{format_image, CountingDevice, DeviceOperations, MemoryDevice};
+
+/// The size of the test images.
+const IMAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The content of a file of the given size.
+fn file_content(size: usize) -> Vec<u8> {
+    (0..size).map(|value| (value % 251) as u8).collect()
+}
+
+/// Write and read back a contiguous file, checking the size of the storage device operations.
+fn check_contiguous_io(fat_type: FatFsType) {
+    let mut image = format_image(fat_type, IMAGE_SIZE);
+    let operations = DeviceOperations::default();
+
+    let fs = libfat::get_raw_partition(CountingDevice {
+        inner: MemoryDevice(&mut image),
+        operations: &operations,
+    })
+    .unwrap();
+
+    let cluster_size = fs.volume_info().cluster_size() as usize;
+    let content = file_content(cluster_size * 32 + 100);
+
+    fs.create_file("/file.bin").unwrap();
+    let mut file = fs.open_file("/file.bin").unwrap();
+    file.set_len(&fs, content.len() as u64).unwrap();
+
+    operations.writes.borrow_mut().clear();
+    file.write(&fs, 0, &content, false).unwrap();
+    assert!(operations.writes.borrow().contains(&content.len()));
+
+    operations.reads.borrow_mut().clear();
+    let mut read_content = vec![0x0u8; content.len()];
+    assert_eq!(
+        file.read(&fs, 0, &mut read_content).unwrap(),
+        content.len() as u64
+    );
+
+    // Only the FAT is read besides the content.
+    let data_reads: Vec<usize> = operations
+        .reads
+        .borrow()
+        .iter()
+        .copied()
+        .filter(|size| *size > 4)
+        .collect();
+    assert_eq!(data_reads, vec![content.len()]);
+    assert!(read_content == content);
+
+    // Unaligned ranges crossing cluster boundaries.
+    for (offset, size) in &[
+        (1, cluster_size),
+        (cluster_size - 3, 7),
+        (511, cluster_size * 5 + 2),
+    ] {
+        let mut read_content = vec![0x0u8; *size];
+        file.read(&fs, *offset as u64, &mut read_content).unwrap();
+        assert!(read_content[..] == content[*offset..*offset + *size]);
+    }
+
+    // Reads stop at the end of the file.
+    let mut read_content = vec![0x0u8; 300];
+    assert_eq!(
+        file.read(&fs, (content.len() - 100) as u64, &mut read_content)
+            .unwrap(),
+        100
+    );
+    assert!(read_content[..100] == content[content.len() - 100..]);
+}
+
+#[test]
+fn contiguous_io_fat12() {
+    check_contiguous_io(FatFsType::Fat12);
+}
+
+#[test]
+fn contiguous_io_fat16() {
+    check_contiguous_io(FatFsType::Fat16);
+}
+
+#[test]
+fn contiguous_io_fat32() {
+    check_contiguous_io(FatFsType::Fat32);
+}
+
+#[test]
+fn fragmented_io() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    let operations = DeviceOperations::default();
+
+    let fs = libfat::get_raw_partition(CountingDevice {
+        inner: MemoryDevice(&mut image),
+        operations: &operations,
+    })
+    .unwrap();
+
+    let cluster_size = fs.volume_info().cluster_size() as usize;
+
+    // Interleave the clusters of two files so that every cluster of the first file is a run.
+    fs.create_file("/first.bin").unwrap();
+    fs.create_file("/second.bin").unwrap();
+    let mut first = fs.open_file("/first.bin").unwrap();
+    let mut second = fs.open_file("/second.bin").unwrap();
+
+    let content = file_content(cluster_size * 4);
+    for index in 0..4 {
+        let range = index * cluster_size..(index + 1) * cluster_size;
+        first
+            .write(&fs, range.start as u64, &content[range.clone()], true)
+            .unwrap();
+        second
+            .write(&fs, range.start as u64, &content[range], true)
+            .unwrap();
+    }
+
+    operations.reads.borrow_mut().clear();
+    let mut read_content = vec![0x0u8; content.len() - 10];
+    first.read(&fs, 10, &mut read_content).unwrap();
+    assert!(read_content[..] == content[10..]);
+
+    let data_reads = operations
+        .reads
+        .borrow()
+        .iter()
+        .filter(|size| **size >= cluster_size - 10)
+        .count();
+    assert_eq!(data_reads, 4);
+}
+
+#[test]
+fn chain_shorter_than_file() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    let cluster_size;
+
+    let info = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        cluster_size = fs.volume_info().cluster_size() as usize;
+
+        fs.create_file("/file.bin").unwrap();
+        let mut file = fs.open_file("/file.bin").unwrap();
+        file.write(&fs, 0, &file_content(cluster_size * 3), true)
+            .unwrap();
+
+        fs.volume_info()
+    };
+
+    // The first file uses the first clusters, end its chain after one cluster in every FAT.
+    let block_size = info.bytes_per_block as usize;
+    for fat_index in 0..info.fats_count as usize {
+        let offset = (info.reserved_block_count as usize + fat_index * info.fat_size as usize)
+            * block_size
+            + 2 * 2;
+        image[offset..offset + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+    }
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let mut file = fs.open_file("/file.bin").unwrap();
+
+    let mut read_content = vec![0x0u8; cluster_size * 3];
+    assert_eq!(
+        file.read(&fs, 0, &mut read_content).unwrap(),
+        cluster_size as u64
+    );
+
+    // Writing past the end of the chain fails instead of silently dropping the data.
+    assert!(file
+        .write(&fs, 0, &file_content(cluster_size * 3), false)
+        .is_err());
+    assert!(file
+        .write(&fs, 0, &file_content(cluster_size), false)
+        .is_ok());
+}
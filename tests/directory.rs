@@ -0,0 +1,90 @@
+//! Check the special entries of created and moved directories.
+
+use libfat::filesystem::FatFileSystem;
+use libfat::{FatFsType, FileSystemIterator};
+use storage_device::StorageDevice;
+
+mod common;
+
+use common::{format_image, MemoryDevice};
+
+/// The size of the test images.
+const IMAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The FAT types of the test images, the FAT12 ones are too small for the default geometry.
+const FAT_TYPES: [FatFsType; 2] = [FatFsType::Fat16, FatFsType::Fat32];
+
+/// Get the sorted names of the entries of a directory.
+fn entry_names<S: StorageDevice>(fs: &FatFileSystem<S>, path: &str) -> Vec<String> {
+    let mut names: Vec<String> = fs
+        .open_directory(path)
+        .unwrap()
+        .iter()
+        .to_iterator(fs)
+        .map(|entry| String::from(entry.unwrap().file_name.as_str()))
+        .collect();
+    names.sort();
+
+    names
+}
+
+#[test]
+fn created_directory_parent_entry() {
+    for fat_type in &FAT_TYPES {
+        let mut image = format_image(*fat_type, IMAGE_SIZE);
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let cluster_size = fs.volume_info().cluster_size() as usize;
+
+        fs.create_directory("/dir").unwrap();
+        fs.create_directory("/dir/first").unwrap();
+
+        // Fill the first cluster of "/dir" so the entry of "/dir/later" is in its second cluster.
+        for index in 0..cluster_size / 32 {
+            fs.create_file(&format!("/dir/file_{}.bin", index)).unwrap();
+        }
+        fs.create_directory("/dir/later").unwrap();
+
+        // ".." leads back to the parent directory.
+        assert_eq!(entry_names(&fs, "/dir/first/.."), entry_names(&fs, "/dir"));
+        assert_eq!(
+            entry_names(&fs, "/dir/later/.."),
+            entry_names(&fs, "/dir"),
+            "{:?}",
+            fat_type
+        );
+        assert_eq!(entry_names(&fs, "/dir/.."), entry_names(&fs, "/"));
+    }
+}
+
+#[test]
+fn moved_directory_parent_entry() {
+    for fat_type in &FAT_TYPES {
+        let mut image = format_image(*fat_type, IMAGE_SIZE);
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+        fs.create_directory("/source").unwrap();
+        fs.create_directory("/destination").unwrap();
+        fs.create_directory("/source/moved").unwrap();
+        fs.create_file("/source/moved/file.txt").unwrap();
+        let mut file = fs.open_file("/source/moved/file.txt").unwrap();
+        file.write(&fs, 0, b"content", true).unwrap();
+
+        fs.rename_directory("/source/moved", "/destination/moved")
+            .unwrap();
+        assert_eq!(
+            entry_names(&fs, "/destination/moved/.."),
+            entry_names(&fs, "/destination"),
+            "{:?}",
+            fat_type
+        );
+
+        fs.rename_directory("/destination/moved", "/moved").unwrap();
+        assert_eq!(entry_names(&fs, "/moved/.."), entry_names(&fs, "/"));
+
+        // The first child of the moved directory is left untouched.
+        let mut file = fs.open_file("/moved/file.txt").unwrap();
+        let mut content = [0x0u8; 7];
+        assert_eq!(file.read(&fs, 0, &mut content).unwrap(), 7);
+        assert_eq!(&content, b"content");
+    }
+}
@@ -0,0 +1,53 @@
+//! Check the packing of the 12 bits FAT entries.
+
+use libfat::{FatFsType, FormatOptions};
+
+mod common;
+
+use common::MemoryDevice;
+
+/// The size of the test image.
+const IMAGE_SIZE: usize = 1440 * 1024;
+
+/// Read an entry of the first FAT of a FAT12 image.
+fn fat12_entry(image: &[u8], cluster: usize) -> u16 {
+    let bytes_per_block = usize::from(u16::from_le_bytes([image[11], image[12]]));
+    let reserved_block_count = usize::from(u16::from_le_bytes([image[14], image[15]]));
+    let offset = reserved_block_count * bytes_per_block + cluster * 3 / 2;
+
+    let value = u16::from_le_bytes([image[offset], image[offset + 1]]);
+    if cluster & 1 == 1 {
+        value >> 4
+    } else {
+        value & 0xFFF
+    }
+}
+
+#[test]
+fn odd_entries_kept_when_writing_even_entries() {
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+
+    let options = FormatOptions::new()
+        .fat_type(FatFsType::Fat12)
+        .blocks_per_cluster(1);
+    libfat::format_partition_with_options(MemoryDevice(&mut image), &options, 0, IMAGE_SIZE as u64)
+        .unwrap();
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+        // Every file takes a single cluster, so each even entry is written next to a free odd one.
+        for index in 0..4u8 {
+            let path = format!("/file_{}.bin", index);
+            fs.create_file(&path).unwrap();
+
+            let mut file = fs.open_file(&path).unwrap();
+            file.write(&fs, 0, &[index; 512], true).unwrap();
+        }
+    }
+
+    for cluster in 2..6 {
+        assert!(fat12_entry(&image, cluster) >= 0xFF8, "cluster {}", cluster);
+    }
+    assert_eq!(fat12_entry(&image, 6), 0);
+}
@@ -0,0 +1,182 @@
+//! Check the file handles keeping track of their position.
+
+use libfat::io::{Read, Seek, SeekFrom, Write};
+use libfat::{FatError, FatFsType, OpenOptions};
+
+mod common;
+
+use common::{format_image, MemoryDevice};
+
+/// The size of the test image.
+const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+
+#[test]
+fn read_write_seek() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+    let options = OpenOptions::new().read(true).write(true).create(true);
+    let mut file = fs.open_file_with_options("/file.txt", &options).unwrap();
+
+    let content: Vec<u8> = (0..10000u32).map(|value| (value % 253) as u8).collect();
+    file.write_all(&content[..6000]).unwrap();
+    file.write_all(&content[6000..]).unwrap();
+    assert_eq!(file.len(), content.len() as u64);
+
+    assert_eq!(file.seek(SeekFrom::Start(100)).unwrap(), 100);
+    let mut buf = vec![0x0u8; 5000];
+    file.read_exact(&mut buf).unwrap();
+    assert!(buf[..] == content[100..5100]);
+
+    assert_eq!(file.seek(SeekFrom::Current(-100)).unwrap(), 5000);
+    file.write_all(&[0xFF; 10]).unwrap();
+
+    assert_eq!(file.seek(SeekFrom::End(-20)).unwrap(), 9980);
+    let mut buf = vec![0x0u8; 50];
+    assert_eq!(file.read(&mut buf).unwrap(), 20);
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+    assert!(file.read_exact(&mut buf).is_err());
+    assert!(file.seek(SeekFrom::Current(-20000)).is_err());
+
+    file.flush().unwrap();
+
+    // The modifications are visible when opening the file again.
+    let mut file = fs
+        .open_file_with_options("/file.txt", &OpenOptions::new().read(true))
+        .unwrap();
+    let mut read_content = vec![0x0u8; content.len()];
+    file.read_exact(&mut read_content).unwrap();
+
+    let mut expected = content.clone();
+    expected[5000..5010].copy_from_slice(&[0xFF; 10]);
+    assert!(read_content == expected);
+}
+
+#[test]
+fn short_chain_write() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+
+    let info = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let options = OpenOptions::new().write(true).create(true);
+        let mut file = fs.open_file_with_options("/file.bin", &options).unwrap();
+
+        let content = vec![0x42; fs.volume_info().cluster_size() as usize * 3];
+        assert_eq!(file.write(&content).unwrap(), content.len());
+        assert_eq!(
+            file.seek(SeekFrom::Current(0)).unwrap(),
+            content.len() as u64
+        );
+
+        fs.volume_info()
+    };
+
+    // End the chain of the file after its first cluster in every FAT.
+    let block_size = info.bytes_per_block as usize;
+    for fat_index in 0..info.fats_count as usize {
+        let offset = (info.reserved_block_count as usize + fat_index * info.fat_size as usize)
+            * block_size
+            + 2 * 2;
+        image[offset..offset + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+    }
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let options = OpenOptions::new().write(true);
+    let mut file = fs.open_file_with_options("/file.bin", &options).unwrap();
+
+    // The position only moves by what was actually written.
+    let content = vec![0x24; info.cluster_size() as usize * 2];
+    assert!(file.write(&content).is_err());
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 0);
+}
+
+#[test]
+fn open_modes() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+    // The file must exist without the create option.
+    let options = OpenOptions::new().read(true).write(true);
+    assert!(matches!(
+        fs.open_file_with_options("/file.txt", &options),
+        Err(FatError::NotFound)
+    ));
+
+    // Creating and truncating a file needs the write access.
+    for options in &[
+        OpenOptions::new().read(true).create(true),
+        OpenOptions::new().read(true).truncate(true),
+    ] {
+        assert!(matches!(
+            fs.open_file_with_options("/file.txt", options),
+            Err(FatError::AccessDenied)
+        ));
+    }
+
+    let options = OpenOptions::new().write(true).create(true);
+    let mut file = fs.open_file_with_options("/file.txt", &options).unwrap();
+    file.write_all(b"Hello").unwrap();
+    assert!(file.read(&mut [0x0; 5]).is_err());
+
+    let mut file = fs
+        .open_file_with_options("/file.txt", &OpenOptions::new().read(true))
+        .unwrap();
+    assert!(file.write(b"World").is_err());
+
+    // Append mode writes at the end whatever the position is.
+    let options = OpenOptions::new().read(true).append(true);
+    let mut file = fs.open_file_with_options("/file.txt", &options).unwrap();
+    file.write_all(b" World").unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.write_all(b"!").unwrap();
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut buf = [0x0u8; 12];
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello World!");
+
+    // Truncating empties the existing file.
+    let options = OpenOptions::new().write(true).truncate(true);
+    let file = fs.open_file_with_options("/file.txt", &options).unwrap();
+    assert!(file.is_empty());
+    assert_eq!(fs.search_entry("/file.txt").unwrap().file_size, 0);
+
+    fs.create_directory("/dir").unwrap();
+    assert!(matches!(
+        fs.open_file_with_options("/dir", &OpenOptions::new().read(true)),
+        Err(FatError::NotAFile)
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn std_io() {
+    use std::io::{BufRead, BufReader};
+
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+    let options = OpenOptions::new().write(true).create(true);
+    let mut file = fs.open_file_with_options("/lines.txt", &options).unwrap();
+    for index in 0..100 {
+        std::io::Write::write_fmt(&mut file, format_args!("line {}\n", index)).unwrap();
+    }
+
+    let file = fs
+        .open_file_with_options("/lines.txt", &OpenOptions::new().read(true))
+        .unwrap();
+    let lines: Vec<String> = BufReader::new(file).lines().map(Result::unwrap).collect();
+    assert_eq!(lines.len(), 100);
+    assert_eq!(lines[42], "line 42");
+
+    let mut file = fs
+        .open_file_with_options("/lines.txt", &OpenOptions::new().read(true))
+        .unwrap();
+    assert_eq!(
+        std::io::Seek::seek(&mut file, std::io::SeekFrom::End(-8)).unwrap(),
+        file.len() - 8
+    );
+
+    let error = std::io::Write::write(&mut file, b"denied").unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+}
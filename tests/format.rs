@@ -0,0 +1,326 @@
+//! Check the on-disk structures written by the formatter and kept by the allocator.
+
+use libfat::filesystem::VolumeInfo;
+use libfat::{FatFsType, FormatOptions};
+
+mod common;
+
+use common::MemoryDevice;
+
+/// Read a little endian u16 at the given offset.
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Read a little endian u32 at the given offset.
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Get the raw content of the FAT at the given index.
+fn fat_table<'a>(image: &'a [u8], info: &VolumeInfo, index: usize) -> &'a [u8] {
+    let block_size = info.bytes_per_block as usize;
+    let fat_len = info.fat_size as usize * block_size;
+    let start = info.reserved_block_count as usize * block_size + index * fat_len;
+
+    &image[start..start + fat_len]
+}
+
+/// Decode the FAT entry of the given cluster, with FAT12 entries packed two in three bytes.
+fn fat_entry(fat: &[u8], fat_type: FatFsType, cluster: u32) -> u32 {
+    let cluster = cluster as usize;
+
+    match fat_type {
+        FatFsType::Fat12 => {
+            let value = u32::from(read_u16(fat, cluster + cluster / 2));
+            if (cluster & 1) == 1 {
+                value >> 4
+            } else {
+                value & 0xFFF
+            }
+        }
+        FatFsType::Fat16 => u32::from(read_u16(fat, cluster * 2)),
+        FatFsType::Fat32 => read_u32(fat, cluster * 4) & 0x0FFF_FFFF,
+    }
+}
+
+/// Check the boot sector, the FATs and the FS Info structure of an image.
+fn check_structures(image: &[u8], info: &VolumeInfo) {
+    let block_size = info.bytes_per_block as usize;
+
+    // Boot sector.
+    assert!(image[0] == 0xEB && image[2] == 0x90 || image[0] == 0xE9);
+    assert_eq!(&image[510..512], &[0x55, 0xAA]);
+    assert_eq!(read_u16(image, 11), info.bytes_per_block);
+    assert_eq!(image[13], info.blocks_per_cluster);
+    assert_eq!(read_u16(image, 14), info.reserved_block_count);
+    assert_eq!(image[16], info.fats_count);
+    assert_eq!(read_u16(image, 17), info.root_dir_childs_count);
+    assert_eq!(image[21], info.media_type);
+
+    let total_blocks = match read_u16(image, 19) {
+        0 => read_u32(image, 32),
+        total_blocks => u32::from(total_blocks),
+    };
+    assert_eq!(total_blocks, info.total_blocks);
+    assert_eq!(total_blocks as usize * block_size, image.len());
+
+    // The cluster count decides the FAT type.
+    match info.fat_type {
+        FatFsType::Fat12 => assert!(info.total_clusters < 4085),
+        FatFsType::Fat16 => assert!(info.total_clusters >= 4085 && info.total_clusters < 65525),
+        FatFsType::Fat32 => assert!(info.total_clusters >= 65525),
+    }
+
+    let entry_bits = match info.fat_type {
+        FatFsType::Fat12 => 12,
+        FatFsType::Fat16 => 16,
+        FatFsType::Fat32 => 32,
+    };
+    assert!(
+        info.fat_size as usize * block_size * 8 / entry_bits >= info.total_clusters as usize + 2
+    );
+
+    let data_blocks = total_blocks
+        - u32::from(info.reserved_block_count)
+        - u32::from(info.fats_count) * info.fat_size
+        - (u32::from(info.root_dir_childs_count) * 32 + u32::from(info.bytes_per_block) - 1)
+            / u32::from(info.bytes_per_block);
+    assert_eq!(
+        info.total_clusters,
+        data_blocks / u32::from(info.blocks_per_cluster)
+    );
+
+    // Every FAT is identical and its reserved entries hold the media type and an end of chain marker.
+    let fat = fat_table(image, info, 0);
+    for index in 1..info.fats_count as usize {
+        assert!(fat_table(image, info, index) == fat, "FAT {}", index);
+    }
+
+    let (mask, end_of_chain) = match info.fat_type {
+        FatFsType::Fat12 => (0xFFF, 0xFF8),
+        FatFsType::Fat16 => (0xFFFF, 0xFFF8),
+        FatFsType::Fat32 => (0x0FFF_FFFF, 0x0FFF_FFF8),
+    };
+    assert_eq!(
+        fat_entry(fat, info.fat_type, 0),
+        mask & (0x0FFF_FF00 | u32::from(info.media_type))
+    );
+    assert!(fat_entry(fat, info.fat_type, 1) >= end_of_chain);
+
+    let free_clusters = (2..info.total_clusters + 2)
+        .filter(|cluster| fat_entry(fat, info.fat_type, *cluster) == 0)
+        .count();
+    assert_eq!(free_clusters, info.free_clusters as usize);
+
+    if info.fat_type == FatFsType::Fat32 {
+        let fs_info_offset = info.fs_info_block.unwrap() as usize * block_size;
+        let fs_info = &image[fs_info_offset..fs_info_offset + block_size];
+        assert_eq!(read_u32(fs_info, 0), 0x4161_5252);
+        assert_eq!(read_u32(fs_info, 484), 0x6141_7272);
+        assert_eq!(read_u32(fs_info, 508), 0xAA55_0000);
+        assert_eq!(read_u32(fs_info, 488), info.free_clusters);
+
+        let backup_offset = info.backup_boot_record_block.unwrap() as usize * block_size;
+        assert!(image[backup_offset..backup_offset + 512] == image[..512]);
+    }
+}
+
+/// Format an image with the given options, check it, fill it and check it again after a remount.
+fn check_format(fat_type: FatFsType, blocks_per_cluster: u8, image_size: usize) {
+    let mut image = vec![0x0u8; image_size];
+
+    let options = FormatOptions::new()
+        .fat_type(fat_type)
+        .blocks_per_cluster(blocks_per_cluster);
+    libfat::format_partition_with_options(MemoryDevice(&mut image), &options, 0, image_size as u64)
+        .unwrap();
+
+    let info = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let info = fs.volume_info();
+        assert_eq!(info.fat_type, fat_type);
+        assert_eq!(info.blocks_per_cluster, blocks_per_cluster);
+        info
+    };
+    check_structures(&image, &info);
+
+    let info = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+        fs.create_directory("/dir").unwrap();
+        for index in 0..30 {
+            let path = format!("/dir/file_{}.bin", index);
+            fs.create_file(&path).unwrap();
+
+            let mut file = fs.open_file(&path).unwrap();
+            file.write(&fs, 0, &vec![index as u8; index * 1000], true)
+                .unwrap();
+        }
+
+        for index in (0..30).step_by(3) {
+            fs.delete_file(&format!("/dir/file_{}.bin", index)).unwrap();
+        }
+
+        fs.volume_info()
+    };
+    check_structures(&image, &info);
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    assert_eq!(fs.volume_info().free_clusters, info.free_clusters);
+}
+
+#[test]
+fn format_fat12() {
+    check_format(FatFsType::Fat12, 1, 1440 * 1024);
+    check_format(FatFsType::Fat12, 4, 4 * 1024 * 1024);
+    check_format(FatFsType::Fat12, 16, 16 * 1024 * 1024);
+}
+
+#[test]
+fn format_fat16() {
+    check_format(FatFsType::Fat16, 1, 16 * 1024 * 1024);
+    check_format(FatFsType::Fat16, 4, 32 * 1024 * 1024);
+    check_format(FatFsType::Fat16, 32, 128 * 1024 * 1024);
+}
+
+#[test]
+fn format_fat32() {
+    check_format(FatFsType::Fat32, 1, 48 * 1024 * 1024);
+    check_format(FatFsType::Fat32, 1, 64 * 1024 * 1024);
+}
+
+#[test]
+fn reserved_fat_entries() {
+    for (fat_type, image_size) in &[
+        (FatFsType::Fat12, 1440 * 1024),
+        (FatFsType::Fat16, 16 * 1024 * 1024),
+        (FatFsType::Fat32, 48 * 1024 * 1024),
+    ] {
+        let mut image = vec![0x0u8; *image_size];
+
+        libfat::format_partition_with_options(
+            MemoryDevice(&mut image),
+            &FormatOptions::new().fat_type(*fat_type).media_type(0xF0),
+            0,
+            *image_size as u64,
+        )
+        .unwrap();
+
+        let info = libfat::get_raw_partition(MemoryDevice(&mut image))
+            .unwrap()
+            .volume_info();
+
+        // The first entry is the media type with every other bit set, the second one an end of chain marker.
+        let expected: &[u8] = match fat_type {
+            FatFsType::Fat12 => &[0xF0, 0xFF, 0xFF],
+            FatFsType::Fat16 => &[0xF0, 0xFF, 0xFF, 0xFF],
+            FatFsType::Fat32 => &[0xF0, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F],
+        };
+
+        for index in 0..info.fats_count as usize {
+            let fat = fat_table(&image, &info, index);
+            assert_eq!(&fat[..expected.len()], expected, "{:?}", fat_type);
+        }
+    }
+}
+
+#[test]
+fn root_directory_fills_whole_blocks() {
+    const IMAGE_SIZE: usize = 16 * 1024 * 1024;
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+
+    for (bytes_per_block, root_dir_childs_count, valid) in &[
+        (512, 100, false),
+        (512, 112, true),
+        (4096, 512, true),
+        (4096, 112, false),
+    ] {
+        let options = FormatOptions::new()
+            .fat_type(FatFsType::Fat16)
+            .bytes_per_block(*bytes_per_block)
+            .root_dir_childs_count(*root_dir_childs_count);
+        let res = libfat::format_partition_with_options(
+            MemoryDevice(&mut image),
+            &options,
+            0,
+            IMAGE_SIZE as u64,
+        );
+
+        assert_eq!(res.is_ok(), *valid, "{}", root_dir_childs_count);
+    }
+}
+
+#[test]
+fn fat12_packing() {
+    const IMAGE_SIZE: usize = 1440 * 1024;
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+
+    libfat::format_partition_with_options(
+        MemoryDevice(&mut image),
+        &FormatOptions::new().fat_type(FatFsType::Fat12),
+        0,
+        IMAGE_SIZE as u64,
+    )
+    .unwrap();
+
+    let info = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let cluster_size = fs.volume_info().cluster_size() as usize;
+
+        // Interleave files so that their chains use odd and even clusters sharing FAT bytes.
+        for index in 0..3 {
+            fs.create_file(&format!("/file_{}.bin", index)).unwrap();
+        }
+
+        for round in 0..9 {
+            for index in 0..3u8 {
+                let mut file = fs.open_file(&format!("/file_{}.bin", index)).unwrap();
+                let offset = (round * cluster_size) as u64;
+                file.write(&fs, offset, &vec![index; cluster_size], true)
+                    .unwrap();
+            }
+        }
+
+        fs.delete_file("/file_1.bin").unwrap();
+
+        fs.volume_info()
+    };
+
+    check_structures(&image, &info);
+
+    // Follow the chains of the remaining files from the raw FAT and check their content.
+    let fat = fat_table(&image, &info, 0);
+    let block_size = info.bytes_per_block as usize;
+    let root_start = (info.reserved_block_count as usize
+        + info.fats_count as usize * info.fat_size as usize)
+        * block_size;
+    let data_start = root_start + info.root_dir_childs_count as usize * 32;
+    let cluster_size = info.cluster_size() as usize;
+
+    for expected_byte in &[0u8, 2] {
+        let name = format!("FILE_{}  BIN", expected_byte);
+        let entry = image[root_start..data_start]
+            .chunks(32)
+            .find(|entry| entry[11] != 0x0F && entry[..11] == *name.as_bytes())
+            .unwrap();
+
+        let mut cluster = u32::from(read_u16(entry, 26));
+        let mut chain_len = 0;
+        while cluster < 0xFF8 {
+            let offset = data_start + (cluster as usize - 2) * cluster_size;
+            assert!(image[offset..offset + cluster_size]
+                .iter()
+                .all(|byte| byte == expected_byte));
+
+            chain_len += 1;
+            cluster = fat_entry(fat, FatFsType::Fat12, cluster);
+        }
+        assert_eq!(chain_len, 9);
+    }
+}
@@ -0,0 +1,338 @@
+//! Check the filesystem against reference images built by other implementations.
+//!
+//! The images listed in `tests/images/images.txt` are built by `tests/images/generate.sh` with `mkfs.fat` and `mtools`.
+//! They all hold the tree described in `tests/images/tree.txt`.
+//! They aren't checked in yet, so the test reading them is ignored by default: run `generate.sh`, then `cargo test -- --ignored`.
+//! The images modified by the filesystem and the images it formats are checked with the consistency checker,
+//! and with `fsck.fat` too when it is available.
+
+use libfat::check::CheckOptions;
+use libfat::filesystem::FatFileSystem;
+use libfat::{FatFsType, FileSystemIterator, FormatOptions};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use storage_device::StorageDevice;
+
+mod common;
+
+use common::MemoryDevice;
+
+/// An entry of the reference tree.
+struct TreeEntry {
+    /// The absolute path of the entry.
+    path: String,
+
+    /// The size of the file, None for a directory.
+    size: Option<usize>,
+}
+
+/// A reference image.
+struct ReferenceImage {
+    /// The name of the image, without the extension.
+    name: String,
+
+    /// The FAT type of the image.
+    fat_type: FatFsType,
+
+    /// The count of blocks per cluster.
+    blocks_per_cluster: u8,
+
+    /// The size of the image in bytes.
+    size: usize,
+}
+
+/// Get the directory containing the reference images.
+fn images_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("images")
+}
+
+/// Get the lines of a description file without the comments.
+fn description_lines(name: &str) -> Vec<String> {
+    std::fs::read_to_string(images_dir().join(name))
+        .unwrap()
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Parse the reference tree.
+fn reference_tree() -> Vec<TreeEntry> {
+    description_lines("tree.txt")
+        .iter()
+        .map(|line| {
+            let mut parts = line.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some("d"), Some(path)) => TreeEntry {
+                    path: String::from(path),
+                    size: None,
+                },
+                (Some("f"), Some(rest)) => {
+                    let mut parts = rest.splitn(2, ' ');
+                    TreeEntry {
+                        size: Some(parts.next().unwrap().parse().unwrap()),
+                        path: String::from(parts.next().unwrap()),
+                    }
+                }
+                _ => panic!("invalid tree entry {:?}", line),
+            }
+        })
+        .collect()
+}
+
+/// Parse the list of reference images.
+fn reference_images() -> Vec<ReferenceImage> {
+    description_lines("images.txt")
+        .iter()
+        .map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            ReferenceImage {
+                name: String::from(parts[0]),
+                fat_type: match parts[1] {
+                    "12" => FatFsType::Fat12,
+                    "16" => FatFsType::Fat16,
+                    "32" => FatFsType::Fat32,
+                    fat_type => panic!("invalid FAT type {:?}", fat_type),
+                },
+                blocks_per_cluster: parts[2].parse().unwrap(),
+                size: parts[3].parse::<usize>().unwrap() * 1024,
+            }
+        })
+        .collect()
+}
+
+/// Get the content of a file of the reference tree: its path repeated up to its size.
+fn file_content(path: &str, size: usize) -> Vec<u8> {
+    path.bytes().cycle().take(size).collect()
+}
+
+/// Get the parent directory and the name of a path.
+fn split_path(path: &str) -> (&str, &str) {
+    let index = path.rfind('/').unwrap();
+    match &path[..index] {
+        "" => ("/", &path[index + 1..]),
+        parent => (parent, &path[index + 1..]),
+    }
+}
+
+/// Create the given tree on a filesystem.
+fn create_tree<S: StorageDevice>(fs: &FatFileSystem<S>, tree: &[TreeEntry]) {
+    for entry in tree {
+        match entry.size {
+            None => fs.create_directory(&entry.path).unwrap(),
+            Some(size) => {
+                fs.create_file(&entry.path).unwrap();
+
+                let mut file = fs.open_file(&entry.path).unwrap();
+                file.write(fs, 0, &file_content(&entry.path, size), true)
+                    .unwrap();
+            }
+        }
+    }
+}
+
+/// Check that a filesystem holds exactly the given tree.
+///
+/// Names are compared without case as short names only store uppercase letters.
+fn check_tree<S: StorageDevice>(fs: &FatFileSystem<S>, tree: &[TreeEntry]) {
+    let mut directories = vec![String::from("/")];
+
+    for entry in tree {
+        let dir_entry = fs.search_entry(&entry.path).unwrap();
+
+        match entry.size {
+            None => {
+                assert!(dir_entry.attribute.is_directory(), "{}", entry.path);
+                directories.push(entry.path.clone());
+            }
+            Some(size) => {
+                assert!(!dir_entry.attribute.is_directory(), "{}", entry.path);
+                assert_eq!(dir_entry.file_size as usize, size, "{}", entry.path);
+
+                let mut file = fs.open_file(&entry.path).unwrap();
+                let mut content = vec![0x0u8; size];
+                assert_eq!(file.read(fs, 0, &mut content).unwrap() as usize, size);
+                assert!(content == file_content(&entry.path, size), "{}", entry.path);
+            }
+        }
+    }
+
+    // No directory has any other entry.
+    for directory in &directories {
+        let mut expected: Vec<String> = tree
+            .iter()
+            .filter(|entry| split_path(&entry.path).0 == directory)
+            .map(|entry| split_path(&entry.path).1.to_lowercase())
+            .collect();
+        expected.sort();
+
+        let mut names: Vec<String> = fs
+            .open_directory(directory)
+            .unwrap()
+            .iter()
+            .to_iterator(fs)
+            .map(|entry| entry.unwrap().file_name.as_str().to_lowercase())
+            .filter(|name| name != "." && name != "..")
+            .collect();
+        names.sort();
+
+        assert_eq!(names, expected, "{}", directory);
+    }
+}
+
+/// Modify the reference tree on a filesystem and give back the expected tree.
+fn modify_tree<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    mut tree: Vec<TreeEntry>,
+) -> Vec<TreeEntry> {
+    fs.delete_file("/multi.bin").unwrap();
+    fs.rename_file("/MixedCase/lower.txt", "/deep/level1/renamed.txt")
+        .unwrap();
+    tree.retain(|entry| entry.path != "/multi.bin" && entry.path != "/MixedCase/lower.txt");
+
+    let mut file = fs.open_file("/deep/level1/renamed.txt").unwrap();
+    let content = file_content("/deep/level1/renamed.txt", 33);
+    file.write(fs, 0, &content, false).unwrap();
+
+    let added = vec![
+        TreeEntry {
+            path: String::from("/deep/level1/renamed.txt"),
+            size: Some(33),
+        },
+        TreeEntry {
+            path: String::from("/libfat"),
+            size: None,
+        },
+        TreeEntry {
+            path: String::from("/libfat/A file written by libfat.bin"),
+            size: Some(150_000),
+        },
+    ];
+    create_tree(fs, &added[1..]);
+    tree.extend(added);
+
+    tree
+}
+
+/// Check that the consistency checker doesn't find any problem on a filesystem.
+fn check_consistency<S: StorageDevice>(fs: &FatFileSystem<S>, name: &str) {
+    let mut memory = vec![0x0u8; fs.check_memory_len()];
+    let report = fs
+        .check(&CheckOptions::new(), &mut memory, |problem| {
+            eprintln!("{}: {:?}", name, problem)
+        })
+        .unwrap();
+
+    assert!(report.is_clean(), "{}", name);
+}
+
+/// Check if `fsck.fat` can be run.
+fn fsck_available() -> bool {
+    Command::new("fsck.fat").arg("--help").output().is_ok()
+}
+
+/// Run `fsck.fat -n` on an image and return true if no error was found.
+fn fsck(name: &str, image: &[u8]) -> bool {
+    let path = std::env::temp_dir().join(format!("libfat-{}-{}.img", name, std::process::id()));
+    std::fs::write(&path, image).unwrap();
+
+    let output = Command::new("fsck.fat")
+        .arg("-n")
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stdout));
+    }
+
+    output.status.success()
+}
+
+#[test]
+#[ignore]
+fn reference_images_content() {
+    for reference in reference_images() {
+        let path = images_dir().join(format!("{}.img.gz", reference.name));
+        assert!(
+            path.exists(),
+            "missing reference image {}: run tests/images/generate.sh to build it",
+            reference.name
+        );
+
+        let output = Command::new("gzip").arg("-dc").arg(&path).output().unwrap();
+        assert!(output.status.success());
+        let mut image = output.stdout;
+        assert_eq!(
+            &image[3..11],
+            b"mkfs.fat",
+            "{} wasn't built by mkfs.fat",
+            reference.name
+        );
+
+        let tree = {
+            let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+            let info = fs.volume_info();
+            assert_eq!(info.fat_type, reference.fat_type);
+            assert_eq!(info.blocks_per_cluster, reference.blocks_per_cluster);
+            assert_eq!(info.volume_id, Some(0x1234_5678));
+
+            let tree = reference_tree();
+            check_tree(&fs, &tree);
+            modify_tree(&fs, tree)
+        };
+
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        check_tree(&fs, &tree);
+        check_consistency(&fs, &reference.name);
+        drop(fs);
+
+        if fsck_available() {
+            assert!(fsck(&reference.name, &image), "{}", reference.name);
+        }
+    }
+}
+
+#[test]
+fn format_round_trip() {
+    for reference in reference_images() {
+        let mut image = vec![0x0u8; reference.size];
+
+        // Use the same geometry as the reference image.
+        let options = FormatOptions::new()
+            .fat_type(reference.fat_type)
+            .blocks_per_cluster(reference.blocks_per_cluster);
+        libfat::format_partition_with_options(
+            MemoryDevice(&mut image),
+            &options,
+            0,
+            reference.size as u64,
+        )
+        .unwrap();
+
+        let tree = {
+            let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+            let tree = reference_tree();
+            create_tree(&fs, &tree);
+            check_tree(&fs, &tree);
+            modify_tree(&fs, tree)
+        };
+
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        check_tree(&fs, &tree);
+        check_consistency(&fs, &reference.name);
+        drop(fs);
+
+        if fsck_available() {
+            assert!(fsck(&reference.name, &image), "{}", reference.name);
+        } else {
+            eprintln!(
+                "skipping the fsck.fat check of {}: fsck.fat isn't available",
+                reference.name
+            );
+        }
+    }
+}
@@ -0,0 +1,36 @@
+#!/bin/sh
+# Build the reference images listed in images.txt with mkfs.fat and mtools.
+#
+# Every image holds the tree described in tree.txt and is stored compressed as <name>.img.gz.
+# The images are reproducible: the volume id is fixed and mkfs.fat runs in invariant mode.
+set -eu
+
+cd "$(dirname "$0")"
+
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+grep -v '^#' images.txt | while read -r name fat_type sectors_per_cluster size; do
+    image="$work/$name.img"
+    rm -f "$image"
+
+    mkfs.fat -C --invariant -i 12345678 -n REFERENCE -F "$fat_type" -s "$sectors_per_cluster" "$image" "$size" >/dev/null
+
+    grep -v '^#' tree.txt | while read -r kind rest; do
+        case "$kind" in
+        d)
+            mmd -i "$image" "::$rest"
+            ;;
+        f)
+            size=${rest%% *}
+            path=${rest#* }
+            yes "$path" | tr -d '\n' | head -c "$size" >"$work/content"
+            mcopy -m -i "$image" "$work/content" "::$path"
+            ;;
+        esac
+    done
+
+    fsck.fat -n "$image" >/dev/null
+    gzip -9n <"$image" >"$name.img.gz"
+    rm -f "$image"
+done
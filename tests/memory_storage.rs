@@ -0,0 +1,132 @@
+//! Check the in-memory storage device and its fault injection.
+
+use libfat::{FatError, FatFsType, MemoryStorage, WriteFault};
+use storage_device::StorageDevice;
+
+mod common;
+
+use common::format_image;
+
+/// The size of the test images.
+const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+
+#[test]
+fn slice_storage() {
+    let mut image = format_image(FatFsType::Fat12, IMAGE_SIZE);
+
+    {
+        let fs = libfat::get_raw_partition(MemoryStorage::new(&mut image[..])).unwrap();
+        fs.create_file("/file.txt").unwrap();
+
+        let mut file = fs.open_file("/file.txt").unwrap();
+        file.write(&fs, 0, b"Hello World", true).unwrap();
+    }
+
+    // The buffer can also be owned by the storage device.
+    let fs = libfat::get_raw_partition(MemoryStorage::new(image)).unwrap();
+    let mut file = fs.open_file("/file.txt").unwrap();
+    let mut content = [0x0u8; 11];
+    file.read(&fs, 0, &mut content).unwrap();
+    assert_eq!(&content, b"Hello World");
+}
+
+#[test]
+fn out_of_range() {
+    let mut storage = MemoryStorage::new([0x0u8; 1024]);
+
+    assert_eq!(storage.len().unwrap(), 1024);
+    assert!(storage.write(1000, &[0x42; 24]).is_ok());
+    assert!(storage.write(1000, &[0x42; 25]).is_err());
+    assert!(storage.read(u64::MAX, &mut [0x0; 1]).is_err());
+    assert!(storage.read(1024, &mut []).is_ok());
+}
+
+#[test]
+fn torn_write() {
+    let mut storage = MemoryStorage::new(vec![0x0u8; 4096]);
+    storage.set_write_fault(Some(WriteFault {
+        successful_writes: 1,
+        torn_size: 100,
+        persistent: false,
+    }));
+
+    storage.write(0, &[0x11; 512]).unwrap();
+    assert!(storage.write(512, &[0x22; 512]).is_err());
+    storage.write(1024, &[0x33; 512]).unwrap();
+    assert_eq!(storage.write_count(), 3);
+
+    let content = storage.into_inner();
+    assert!(content[..512].iter().all(|byte| *byte == 0x11));
+    assert!(content[512..612].iter().all(|byte| *byte == 0x22));
+    assert!(content[612..1024].iter().all(|byte| *byte == 0x0));
+    assert!(content[1024..1536].iter().all(|byte| *byte == 0x33));
+}
+
+#[test]
+fn persistent_fault() {
+    let mut storage = MemoryStorage::new(vec![0x0u8; 4096]);
+    storage.set_write_fault(Some(WriteFault {
+        successful_writes: 0,
+        torn_size: 0,
+        persistent: true,
+    }));
+
+    for index in 0..4 {
+        assert!(storage.write(index * 512, &[0x42; 512]).is_err());
+    }
+    assert!(storage.get_ref().iter().all(|byte| *byte == 0x0));
+
+    // Removing the fault makes the writes succeed again.
+    storage.set_write_fault(None);
+    storage.write(0, &[0x42; 512]).unwrap();
+    assert_eq!(storage.get_ref()[0], 0x42);
+}
+
+#[test]
+fn failing_filesystem_writes() {
+    let image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    let mut failed_count = 0;
+
+    // Fail every write of creating and writing a file in turn.
+    for successful_writes in 0..64 {
+        let mut storage = MemoryStorage::new(image.clone());
+        storage.set_write_fault(Some(WriteFault {
+            successful_writes,
+            torn_size: 0,
+            persistent: true,
+        }));
+
+        let fs = libfat::get_raw_partition(storage).unwrap();
+        let result = fs.create_file("/file.bin").and_then(|_| {
+            let mut file = fs.open_file("/file.bin")?;
+            file.write(&fs, 0, &[0x42; 10000], true)
+        });
+
+        match result {
+            Ok(_) => break,
+            Err(FatError::WriteFailed) => failed_count += 1,
+            Err(error) => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    assert_ne!(failed_count, 0);
+    assert_ne!(failed_count, 64);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn zeroed_storage() {
+    let mut storage = MemoryStorage::zeroed(IMAGE_SIZE);
+    assert_eq!(storage.len().unwrap(), IMAGE_SIZE as u64);
+
+    libfat::format_partition(
+        MemoryStorage::new(storage.get_mut()),
+        FatFsType::Fat16,
+        0,
+        IMAGE_SIZE as u64,
+    )
+    .unwrap();
+
+    let fs = libfat::get_raw_partition(storage).unwrap();
+    fs.create_directory("/dir").unwrap();
+}
@@ -0,0 +1,577 @@
+//! Create, list and mount partitions of MBR and GPT partition tables.
+
+use libfat::partition::gpt::{GptHeader, GptPartitionEntry, Guid};
+use libfat::partition::mbr::{MasterBootRecord, MbrPartitionEntry};
+use libfat::partition::{PartitionLayout, PartitionTableType, PartitionType};
+use libfat::{FatError, FatFsType, FormatOptions};
+
+mod common;
+
+use common::MemoryDevice;
+
+/// The size of the test images.
+const IMAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// The block size used by the partition tables.
+const BLOCK_SIZE: u64 = 512;
+
+/// The alignment of the partitions created by ``create_partition_table``.
+const ALIGNMENT: u64 = 1024 * 1024;
+
+/// Compute the CRC32 used by the GPT.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+/// A partition formatted with the given FAT type.
+fn layout(size: u64, partition_type: PartitionType, fat_type: FatFsType) -> PartitionLayout {
+    PartitionLayout {
+        size,
+        partition_type,
+        unique_guid: Guid([0x42; 16]),
+        bootable: false,
+        format: Some(FormatOptions::new().fat_type(fat_type)),
+    }
+}
+
+/// Mount the partition at the given index, write a file in it and read it back.
+fn check_mount(image: &mut [u8], index: u64, fat_type: FatFsType) {
+    let content = format!("partition {}", index);
+
+    {
+        let fs = libfat::get_partition(MemoryDevice(image), index, BLOCK_SIZE).unwrap();
+        assert_eq!(fs.volume_info().fat_type, fat_type);
+
+        fs.create_file("/file.txt").unwrap();
+        let mut file = fs.open_file("/file.txt").unwrap();
+        file.write(&fs, 0, content.as_bytes(), true).unwrap();
+    }
+
+    let fs = libfat::get_partition(MemoryDevice(image), index, BLOCK_SIZE).unwrap();
+    let mut file = fs.open_file("/file.txt").unwrap();
+    let mut data = vec![0x0u8; content.len()];
+    file.read(&fs, 0, &mut data).unwrap();
+    assert_eq!(data, content.as_bytes());
+}
+
+/// Create a MBR partition table with two formatted partitions.
+fn create_mbr_image() -> Vec<u8> {
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+    let mut bootable = layout(4 * ALIGNMENT, PartitionType::Mbr(0x01), FatFsType::Fat12);
+    bootable.bootable = true;
+
+    libfat::create_partition_table(
+        &mut MemoryDevice(&mut image),
+        PartitionTableType::Mbr,
+        &[
+            bootable,
+            layout(0, PartitionType::Mbr(0x06), FatFsType::Fat16),
+        ],
+        BLOCK_SIZE,
+    )
+    .unwrap();
+
+    image
+}
+
+/// Create a GPT with two formatted partitions.
+fn create_gpt_image() -> Vec<u8> {
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+
+    libfat::create_partition_table(
+        &mut MemoryDevice(&mut image),
+        PartitionTableType::Gpt {
+            disk_guid: Guid([0x24; 16]),
+        },
+        &[
+            layout(
+                8 * ALIGNMENT,
+                PartitionType::Gpt(Guid::EFI_SYSTEM),
+                FatFsType::Fat16,
+            ),
+            layout(0, PartitionType::Gpt(Guid::BASIC_DATA), FatFsType::Fat12),
+        ],
+        BLOCK_SIZE,
+    )
+    .unwrap();
+
+    image
+}
+
+#[test]
+fn mbr_round_trip() {
+    let mut image = create_mbr_image();
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 2);
+
+    assert_eq!(partitions[0].index, 0);
+    assert_eq!(partitions[0].start, ALIGNMENT);
+    assert_eq!(partitions[0].length, 4 * ALIGNMENT);
+    assert_eq!(partitions[0].partition_type, PartitionType::Mbr(0x01));
+    assert!(partitions[0].bootable);
+    assert_eq!(partitions[0].fat_type, Some(FatFsType::Fat12));
+
+    assert_eq!(partitions[1].index, 1);
+    assert_eq!(partitions[1].start, 5 * ALIGNMENT);
+    assert_eq!(partitions[1].length, IMAGE_SIZE as u64 - 5 * ALIGNMENT);
+    assert_eq!(partitions[1].partition_type, PartitionType::Mbr(0x06));
+    assert!(!partitions[1].bootable);
+    assert_eq!(partitions[1].fat_type, Some(FatFsType::Fat16));
+
+    check_mount(&mut image, 0, FatFsType::Fat12);
+    check_mount(&mut image, 1, FatFsType::Fat16);
+
+    assert!(matches!(
+        libfat::get_partition(MemoryDevice(&mut image), 2, BLOCK_SIZE),
+        Err(FatError::Custom { .. })
+    ));
+}
+
+#[test]
+fn mbr_types_of_other_fat_variants() {
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+
+    // Small FAT16 volumes are often found under the FAT32 types and FAT12 volumes under the FAT16 ones.
+    libfat::create_partition_table(
+        &mut MemoryDevice(&mut image),
+        PartitionTableType::Mbr,
+        &[
+            layout(8 * ALIGNMENT, PartitionType::Mbr(0x0C), FatFsType::Fat16),
+            layout(0, PartitionType::Mbr(0x06), FatFsType::Fat12),
+        ],
+        BLOCK_SIZE,
+    )
+    .unwrap();
+
+    check_mount(&mut image, 0, FatFsType::Fat16);
+    check_mount(&mut image, 1, FatFsType::Fat12);
+}
+
+#[test]
+fn gpt_round_trip() {
+    let mut image = create_gpt_image();
+
+    // The protective MBR covers the whole disk.
+    let mbr = MasterBootRecord::read(&mut MemoryDevice(&mut image), 0).unwrap();
+    assert!(mbr.is_protective());
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 2);
+
+    assert_eq!(partitions[0].index, 0);
+    assert_eq!(partitions[0].start, ALIGNMENT);
+    assert_eq!(partitions[0].length, 8 * ALIGNMENT);
+    assert_eq!(
+        partitions[0].partition_type,
+        PartitionType::Gpt(Guid::EFI_SYSTEM)
+    );
+    assert_eq!(partitions[0].fat_type, Some(FatFsType::Fat16));
+
+    // The last partition ends before the backup partition entry array.
+    assert_eq!(partitions[1].index, 1);
+    assert_eq!(partitions[1].start, 9 * ALIGNMENT);
+    assert!(partitions[1].start + partitions[1].length <= IMAGE_SIZE as u64 - 33 * BLOCK_SIZE);
+    assert_eq!(
+        partitions[1].partition_type,
+        PartitionType::Gpt(Guid::BASIC_DATA)
+    );
+    assert_eq!(partitions[1].fat_type, Some(FatFsType::Fat12));
+
+    check_mount(&mut image, 0, FatFsType::Fat16);
+    check_mount(&mut image, 1, FatFsType::Fat12);
+
+    assert!(matches!(
+        libfat::get_partition(MemoryDevice(&mut image), 2, BLOCK_SIZE),
+        Err(FatError::PartitionNotFound)
+    ));
+}
+
+#[test]
+fn gpt_backup_header() {
+    let pristine = create_gpt_image();
+
+    // Corrupt the primary header.
+    let mut image = pristine.clone();
+    image[BLOCK_SIZE as usize] ^= 0xFF;
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 2);
+    check_mount(&mut image, 1, FatFsType::Fat12);
+
+    // Corrupt the primary partition entry array.
+    let mut image = pristine;
+    image[2 * BLOCK_SIZE as usize + 40] ^= 0xFF;
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 2);
+    assert_eq!(partitions[0].length, 8 * ALIGNMENT);
+    check_mount(&mut image, 0, FatFsType::Fat16);
+}
+
+#[test]
+fn gpt_invalid_primary_header() {
+    let pristine = create_gpt_image();
+
+    // A primary header with a valid CRC pointing to an entry array outside of the disk.
+    let mut image = pristine.clone();
+    let mut header = GptHeader::read(&mut MemoryDevice(&mut image), 1, BLOCK_SIZE).unwrap();
+    header.partition_entries_lba = IMAGE_SIZE as u64 / BLOCK_SIZE;
+    header
+        .write(&mut MemoryDevice(&mut image), BLOCK_SIZE)
+        .unwrap();
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 2);
+    check_mount(&mut image, 0, FatFsType::Fat16);
+
+    // A primary header describing a huge entry array.
+    let mut image = pristine;
+    let mut header = GptHeader::read(&mut MemoryDevice(&mut image), 1, BLOCK_SIZE).unwrap();
+    header.partition_entry_count = u32::MAX;
+    header
+        .write(&mut MemoryDevice(&mut image), BLOCK_SIZE)
+        .unwrap();
+
+    assert!(matches!(
+        GptHeader::read(&mut MemoryDevice(&mut image), 1, BLOCK_SIZE),
+        Err(FatError::InvalidPartition)
+    ));
+    assert!(matches!(
+        header.read_entry(&mut MemoryDevice(&mut image), BLOCK_SIZE, 0),
+        Err(FatError::InvalidPartition)
+    ));
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 2);
+    check_mount(&mut image, 1, FatFsType::Fat12);
+}
+
+#[test]
+fn gpt_invalid_entries_are_skipped() {
+    let mut image = create_gpt_image();
+    let mut header = GptHeader::read(&mut MemoryDevice(&mut image), 1, BLOCK_SIZE).unwrap();
+    let entry_offset = 2 * BLOCK_SIZE as usize;
+
+    // Make the first entry end before its start and add an entry outside of the disk.
+    let mut entry = GptPartitionEntry::from_raw(&image[entry_offset..]);
+    entry.last_lba = entry.first_lba - 1;
+    entry.to_raw(&mut image[entry_offset..]);
+
+    let mut entry = GptPartitionEntry::from_raw(&image[entry_offset + 128..]);
+    entry.first_lba = IMAGE_SIZE as u64 / BLOCK_SIZE;
+    entry.last_lba = entry.first_lba + 10;
+    entry.to_raw(&mut image[entry_offset + 2 * 128..]);
+
+    let mut entries = vec![0x0u8; 128 * 128];
+    entries.copy_from_slice(&image[entry_offset..entry_offset + 128 * 128]);
+    header.partition_entries_crc32 = crc32(&entries);
+    header
+        .write(&mut MemoryDevice(&mut image), BLOCK_SIZE)
+        .unwrap();
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 1);
+    assert_eq!(partitions[0].index, 1);
+    check_mount(&mut image, 1, FatFsType::Fat12);
+}
+
+#[test]
+fn gpt_hostile_lbas() {
+    let pristine = create_gpt_image();
+
+    // A primary header with a valid CRC and an entry array offset overflowing 64 bits.
+    let mut image = pristine.clone();
+    let mut header = GptHeader::read(&mut MemoryDevice(&mut image), 1, BLOCK_SIZE).unwrap();
+    header.partition_entries_lba = u64::MAX / 4;
+    header
+        .write(&mut MemoryDevice(&mut image), BLOCK_SIZE)
+        .unwrap();
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 2);
+    check_mount(&mut image, 0, FatFsType::Fat16);
+
+    let mut image = pristine;
+    let mut header = GptHeader::read(&mut MemoryDevice(&mut image), 1, BLOCK_SIZE).unwrap();
+    let entry_offset = 2 * BLOCK_SIZE as usize;
+    let mut entry = GptPartitionEntry::from_raw(&image[entry_offset..]);
+
+    // An entry before the first usable block of the header.
+    entry.first_lba = header.first_usable_lba - 1;
+    entry.last_lba = entry.first_lba + 10;
+    entry.to_raw(&mut image[entry_offset + 2 * 128..]);
+
+    // An entry in the usable blocks of the header whose offset in bytes overflows 64 bits.
+    header.last_usable_lba = u64::MAX - 1;
+    entry.first_lba = u64::MAX / 2;
+    entry.last_lba = u64::MAX - 1;
+    entry.to_raw(&mut image[entry_offset + 3 * 128..]);
+
+    header.partition_entries_crc32 = crc32(&image[entry_offset..entry_offset + 128 * 128]);
+    header
+        .write(&mut MemoryDevice(&mut image), BLOCK_SIZE)
+        .unwrap();
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 2);
+
+    // get_partition rejects the same entries as list_partitions.
+    for index in 2..4 {
+        assert!(matches!(
+            libfat::get_partition(MemoryDevice(&mut image), index, BLOCK_SIZE),
+            Err(FatError::InvalidPartition)
+        ));
+    }
+    check_mount(&mut image, 1, FatFsType::Fat12);
+}
+
+#[test]
+fn invalid_layouts_are_not_written() {
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+
+    // A GPT partition in a MBR.
+    assert!(matches!(
+        libfat::create_partition_table(
+            &mut MemoryDevice(&mut image),
+            PartitionTableType::Mbr,
+            &[
+                layout(ALIGNMENT, PartitionType::Mbr(0x01), FatFsType::Fat12),
+                layout(0, PartitionType::Gpt(Guid::BASIC_DATA), FatFsType::Fat12),
+            ],
+            BLOCK_SIZE,
+        ),
+        Err(FatError::InvalidPartition)
+    ));
+
+    // A MBR partition in a GPT.
+    assert!(matches!(
+        libfat::create_partition_table(
+            &mut MemoryDevice(&mut image),
+            PartitionTableType::Gpt {
+                disk_guid: Guid([0x24; 16]),
+            },
+            &[
+                layout(
+                    ALIGNMENT,
+                    PartitionType::Gpt(Guid::BASIC_DATA),
+                    FatFsType::Fat12
+                ),
+                layout(0, PartitionType::Mbr(0x01), FatFsType::Fat12),
+            ],
+            BLOCK_SIZE,
+        ),
+        Err(FatError::InvalidPartition)
+    ));
+
+    // A formatted MBR partition that can't hold a FAT filesystem.
+    assert!(matches!(
+        libfat::create_partition_table(
+            &mut MemoryDevice(&mut image),
+            PartitionTableType::Mbr,
+            &[layout(0, PartitionType::Mbr(0x83), FatFsType::Fat16)],
+            BLOCK_SIZE,
+        ),
+        Err(FatError::InvalidPartition)
+    ));
+
+    // A formatted GPT partition that can't hold a FAT filesystem.
+    assert!(matches!(
+        libfat::create_partition_table(
+            &mut MemoryDevice(&mut image),
+            PartitionTableType::Gpt {
+                disk_guid: Guid([0x24; 16]),
+            },
+            &[layout(
+                0,
+                PartitionType::Gpt(Guid([0x1; 16])),
+                FatFsType::Fat16
+            )],
+            BLOCK_SIZE,
+        ),
+        Err(FatError::InvalidPartition)
+    ));
+
+    assert!(image.iter().all(|byte| *byte == 0));
+}
+
+/// Write an EBR describing a logical partition and the next EBR of the chain.
+fn write_ebr(
+    image: &mut [u8],
+    ebr_lba: u64,
+    partition_offset: u32,
+    partition_block_count: u32,
+    next_ebr: Option<(u32, u32)>,
+) {
+    let mut ebr = MasterBootRecord::new_empty();
+
+    ebr.set_entry(
+        0,
+        &MbrPartitionEntry {
+            status: 0x0,
+            partition_type: 0x06,
+            lba_start: partition_offset,
+            block_count: partition_block_count,
+        },
+    );
+
+    if let Some((lba_start, block_count)) = next_ebr {
+        ebr.set_entry(
+            1,
+            &MbrPartitionEntry {
+                status: 0x0,
+                partition_type: 0x05,
+                lba_start,
+                block_count,
+            },
+        );
+    }
+
+    ebr.write(&mut MemoryDevice(image), ebr_lba * BLOCK_SIZE)
+        .unwrap();
+}
+
+/// Create a MBR with one primary partition and two logical partitions in an extended partition.
+fn create_ebr_image() -> Vec<u8> {
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+    let alignment = (ALIGNMENT / BLOCK_SIZE) as u32;
+    let disk_block_count = (IMAGE_SIZE as u64 / BLOCK_SIZE) as u32;
+
+    let mut mbr = MasterBootRecord::new_empty();
+    mbr.set_entry(
+        0,
+        &MbrPartitionEntry {
+            status: 0x0,
+            partition_type: 0x06,
+            lba_start: alignment,
+            block_count: 5 * alignment,
+        },
+    );
+
+    // The extended partition covers the rest of the disk.
+    let extended_start = 6 * alignment;
+    mbr.set_entry(
+        1,
+        &MbrPartitionEntry {
+            status: 0x0,
+            partition_type: 0x0F,
+            lba_start: extended_start,
+            block_count: disk_block_count - extended_start,
+        },
+    );
+    mbr.write(&mut MemoryDevice(&mut image), 0).unwrap();
+
+    // Logical partitions start one alignment unit after their EBR, the next EBR is relative to the extended partition.
+    write_ebr(
+        &mut image,
+        u64::from(extended_start),
+        alignment,
+        4 * alignment,
+        Some((5 * alignment, 5 * alignment)),
+    );
+    write_ebr(
+        &mut image,
+        u64::from(extended_start + 5 * alignment),
+        alignment,
+        4 * alignment,
+        None,
+    );
+
+    let options = FormatOptions::new().fat_type(FatFsType::Fat16);
+    for start in &[alignment, 7 * alignment, 12 * alignment] {
+        libfat::format_partition_with_options(
+            MemoryDevice(&mut image),
+            &options,
+            u64::from(*start) * BLOCK_SIZE,
+            u64::from(4 * alignment) * BLOCK_SIZE,
+        )
+        .unwrap();
+    }
+
+    image
+}
+
+#[test]
+fn ebr_chain() {
+    let mut image = create_ebr_image();
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    let indexes: Vec<u64> = partitions.iter().map(|partition| partition.index).collect();
+    let starts: Vec<u64> = partitions.iter().map(|partition| partition.start).collect();
+    assert_eq!(indexes, [0, 4, 5]);
+    assert_eq!(starts, [ALIGNMENT, 7 * ALIGNMENT, 12 * ALIGNMENT]);
+    assert!(partitions
+        .iter()
+        .all(|partition| partition.fat_type == Some(FatFsType::Fat16)));
+
+    check_mount(&mut image, 4, FatFsType::Fat16);
+    check_mount(&mut image, 5, FatFsType::Fat16);
+
+    // The logical partitions are independent filesystems.
+    let fs = libfat::get_partition(MemoryDevice(&mut image), 0, BLOCK_SIZE).unwrap();
+    assert!(fs.open_file("/file.txt").is_err());
+    drop(fs);
+
+    assert!(matches!(
+        libfat::get_partition(MemoryDevice(&mut image), 6, BLOCK_SIZE),
+        Err(FatError::PartitionNotFound)
+    ));
+}
+
+#[test]
+fn broken_ebr_chain() {
+    let mut image = create_ebr_image();
+    let alignment = (ALIGNMENT / BLOCK_SIZE) as u32;
+
+    // Make the second EBR point back to the first one.
+    write_ebr(
+        &mut image,
+        u64::from(11 * alignment),
+        alignment,
+        4 * alignment,
+        Some((0, 5 * alignment)),
+    );
+
+    // A next EBR at offset zero ends the chain.
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 3);
+
+    // Make the second EBR point outside of the disk.
+    write_ebr(
+        &mut image,
+        u64::from(11 * alignment),
+        alignment,
+        4 * alignment,
+        Some((100 * alignment, 5 * alignment)),
+    );
+
+    let partitions = libfat::list_partitions(&mut MemoryDevice(&mut image), BLOCK_SIZE).unwrap();
+    assert_eq!(partitions.len(), 3);
+
+    // Make the second EBR point to itself.
+    write_ebr(
+        &mut image,
+        u64::from(11 * alignment),
+        alignment,
+        4 * alignment,
+        Some((5 * alignment, 5 * alignment)),
+    );
+
+    assert!(matches!(
+        libfat::get_partition(MemoryDevice(&mut image), 6 + 1024, BLOCK_SIZE),
+        Err(FatError::InvalidPartition)
+    ));
+}
@@ -0,0 +1,182 @@
+//! Check the contiguous cluster allocation and the preallocation of files.
+
+use libfat::filesystem::VolumeInfo;
+use libfat::FatFsType;
+
+mod common;
+
+use common::{format_image, MemoryDevice};
+
+/// The size of the test image.
+const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+
+/// Read the cluster chain of the file in the root directory with a short name starting with the given prefix.
+fn cluster_chain(image: &[u8], info: &VolumeInfo, prefix: &[u8]) -> Vec<u16> {
+    let block_size = info.bytes_per_block as usize;
+    let fat_start = info.reserved_block_count as usize * block_size;
+    let root_start = fat_start + info.fats_count as usize * info.fat_size as usize * block_size;
+
+    let entry = image[root_start..root_start + info.root_dir_childs_count as usize * 32]
+        .chunks(32)
+        .find(|entry| entry[11] != 0x0F && entry.starts_with(prefix))
+        .unwrap();
+
+    let mut chain = Vec::new();
+    let mut cluster = u16::from_le_bytes([entry[26], entry[27]]);
+
+    while cluster != 0 && cluster < 0xFFF8 {
+        chain.push(cluster);
+        let offset = fat_start + cluster as usize * 2;
+        cluster = u16::from_le_bytes([image[offset], image[offset + 1]]);
+    }
+
+    chain
+}
+
+/// Check that the given clusters follow each other.
+fn is_contiguous(chain: &[u16]) -> bool {
+    chain.windows(2).all(|pair| pair[1] == pair[0] + 1)
+}
+
+#[test]
+fn contiguous_allocation() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+
+    let info = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let cluster_size = fs.volume_info().cluster_size() as usize;
+
+        // Leave holes of one cluster at the start of the data region.
+        for index in 0..20 {
+            let path = format!("/hole_{}.bin", index);
+            fs.create_file(&path).unwrap();
+
+            let mut file = fs.open_file(&path).unwrap();
+            file.write(&fs, 0, &vec![0x42; cluster_size], true).unwrap();
+        }
+
+        for index in (0..20).step_by(2) {
+            fs.delete_file(&format!("/hole_{}.bin", index)).unwrap();
+        }
+
+        fs.create_file("/big.bin").unwrap();
+        let mut file = fs.open_file("/big.bin").unwrap();
+        file.write(&fs, 0, &vec![0xAB; cluster_size * 10], true)
+            .unwrap();
+
+        // Extending the file keeps it contiguous as the following clusters are free.
+        file.write(
+            &fs,
+            (cluster_size * 10) as u64,
+            &vec![0xCD; cluster_size * 5],
+            true,
+        )
+        .unwrap();
+
+        fs.volume_info()
+    };
+
+    let chain = cluster_chain(&image, &info, b"BIG");
+    assert_eq!(chain.len(), 15);
+    assert!(is_contiguous(&chain));
+}
+
+#[test]
+fn preallocate() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+
+    let info = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let cluster_size = fs.volume_info().cluster_size() as usize;
+        let free_clusters = fs.volume_info().free_clusters;
+
+        fs.create_file("/video.bin").unwrap();
+        let mut file = fs.open_file("/video.bin").unwrap();
+        file.preallocate(&fs, (cluster_size * 8) as u64).unwrap();
+
+        assert_eq!(file.file_info.file_size, 0);
+        assert_eq!(fs.search_entry("/video.bin").unwrap().file_size, 0);
+        assert_eq!(fs.volume_info().free_clusters, free_clusters - 8);
+
+        // Writing in the preallocated clusters doesn't allocate anything.
+        let mut file = fs.open_file("/video.bin").unwrap();
+        file.write(&fs, 0, &vec![0x42; cluster_size * 3], true)
+            .unwrap();
+        assert_eq!(file.file_info.file_size as usize, cluster_size * 3);
+        assert_eq!(fs.volume_info().free_clusters, free_clusters - 8);
+
+        // Preallocating less than what is already allocated does nothing.
+        file.preallocate(&fs, 10).unwrap();
+        assert_eq!(fs.volume_info().free_clusters, free_clusters - 8);
+
+        // Writing after the preallocated clusters allocates the missing ones.
+        file.write(
+            &fs,
+            (cluster_size * 3) as u64,
+            &vec![0x43; cluster_size * 6],
+            true,
+        )
+        .unwrap();
+        assert_eq!(fs.volume_info().free_clusters, free_clusters - 9);
+
+        fs.volume_info()
+    };
+
+    let chain = cluster_chain(&image, &info, b"VIDEO");
+    assert_eq!(chain.len(), 9);
+    assert!(is_contiguous(&chain));
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let cluster_size = fs.volume_info().cluster_size() as usize;
+    let free_clusters = fs.volume_info().free_clusters;
+
+    let mut file = fs.open_file("/video.bin").unwrap();
+    let mut content = vec![0x0u8; cluster_size * 9];
+    assert_eq!(
+        file.read(&fs, 0, &mut content).unwrap() as usize,
+        content.len()
+    );
+    assert!(content[..cluster_size * 3].iter().all(|byte| *byte == 0x42));
+    assert!(content[cluster_size * 3..].iter().all(|byte| *byte == 0x43));
+
+    // Truncating frees the preallocated clusters too.
+    file.preallocate(&fs, (cluster_size * 20) as u64).unwrap();
+    assert_eq!(fs.volume_info().free_clusters, free_clusters - 11);
+    file.set_len(&fs, 10).unwrap();
+    assert_eq!(fs.volume_info().free_clusters, free_clusters + 8);
+
+    // Truncating to zero frees every cluster.
+    file.preallocate(&fs, (cluster_size * 4) as u64).unwrap();
+    file.set_len(&fs, 0).unwrap();
+    assert_eq!(fs.volume_info().free_clusters, free_clusters + 9);
+
+    fs.delete_file("/video.bin").unwrap();
+    assert_eq!(fs.volume_info().free_clusters, free_clusters + 9);
+}
+
+#[test]
+fn preallocate_too_large() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+
+    let info = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        fs.create_file("/file.bin").unwrap();
+        let free_clusters = fs.volume_info().free_clusters;
+
+        let mut file = fs.open_file("/file.bin").unwrap();
+        assert!(file.preallocate(&fs, IMAGE_SIZE as u64).is_err());
+        assert!(file.preallocate(&fs, 0x1_0000_0000).is_err());
+        assert_eq!(fs.search_entry("/file.bin").unwrap().file_size, 0);
+
+        // The clusters allocated before running out of space are given back.
+        assert_eq!(fs.volume_info().free_clusters, free_clusters);
+
+        file.write(&fs, 0, b"data", true).unwrap();
+        assert!(file.set_len(&fs, IMAGE_SIZE as u64).is_err());
+        assert_eq!(fs.volume_info().free_clusters, free_clusters - 1);
+
+        fs.volume_info()
+    };
+
+    assert_eq!(cluster_chain(&image, &info, b"FILE").len(), 1);
+}
@@ -0,0 +1,135 @@
+//! Check that read only entries can't be modified unless explicitly allowed.
+
+use libfat::attribute::Attributes;
+use libfat::{FatError, FatFsType};
+
+mod common;
+
+use common::{format_image, MemoryDevice};
+
+/// The size of the test image.
+const IMAGE_SIZE: usize = 8 * 1024 * 1024;
+
+/// Check that the result of an operation is an `AccessDenied` error.
+fn assert_access_denied<T>(result: Result<T, FatError>) {
+    assert!(matches!(result, Err(FatError::AccessDenied)));
+}
+
+#[test]
+fn read_only_entries() {
+    let mut image = format_image(FatFsType::Fat12, IMAGE_SIZE);
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        fs.create_directory("/dir").unwrap();
+        fs.create_file("/file.txt").unwrap();
+
+        let mut file = fs.open_file("/file.txt").unwrap();
+        file.write(&fs, 0, b"read only", true).unwrap();
+
+        let attribute = fs.search_entry("/file.txt").unwrap().attribute;
+        fs.set_attributes("/file.txt", attribute.set(Attributes::READ_ONLY))
+            .unwrap();
+        let attribute = fs.search_entry("/dir").unwrap().attribute;
+        fs.set_attributes("/dir", attribute.set(Attributes::READ_ONLY))
+            .unwrap();
+
+        let mut file = fs.open_file("/file.txt").unwrap();
+        assert_access_denied(file.write(&fs, 0, b"modified", false));
+        assert_access_denied(file.write(&fs, 9, b"appended", true));
+        assert_access_denied(file.set_len(&fs, 0));
+        assert_access_denied(file.set_len(&fs, 4096));
+        assert_access_denied(fs.delete_file("/file.txt"));
+        assert_access_denied(fs.rename_file("/file.txt", "/renamed.txt"));
+        assert_access_denied(fs.rename_file("/file.txt", "/dir/file.txt"));
+        assert_access_denied(fs.delete_directory("/dir"));
+        assert_access_denied(fs.rename_directory("/dir", "/other"));
+
+        // The content of a read only directory can still be changed.
+        fs.create_file("/dir/child.txt").unwrap();
+        fs.delete_file("/dir/child.txt").unwrap();
+
+        // Reading is still allowed.
+        let mut buf = [0x0u8; 9];
+        assert_eq!(file.read(&fs, 0, &mut buf).unwrap(), 9);
+        assert_eq!(&buf, b"read only");
+    }
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let entry = fs.search_entry("/file.txt").unwrap();
+        assert_eq!(entry.file_size, 9);
+        fs.search_entry("/dir").unwrap();
+
+        // Clearing the attribute makes the entry writable again.
+        fs.set_attributes("/file.txt", entry.attribute.clear(Attributes::READ_ONLY))
+            .unwrap();
+        let mut file = fs.open_file("/file.txt").unwrap();
+        file.set_len(&fs, 4).unwrap();
+        fs.rename_file("/file.txt", "/renamed.txt").unwrap();
+        fs.delete_file("/renamed.txt").unwrap();
+    }
+}
+
+#[test]
+fn ignore_read_only() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+
+    let mut fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    fs.create_directory("/dir").unwrap();
+    fs.create_file("/file.txt").unwrap();
+
+    let attribute = fs.search_entry("/file.txt").unwrap().attribute;
+    fs.set_attributes("/file.txt", attribute.set(Attributes::READ_ONLY))
+        .unwrap();
+    let attribute = fs.search_entry("/dir").unwrap().attribute;
+    fs.set_attributes("/dir", attribute.set(Attributes::READ_ONLY))
+        .unwrap();
+
+    fs.set_ignore_read_only(true);
+
+    let mut file = fs.open_file("/file.txt").unwrap();
+    file.write(&fs, 0, b"forced", true).unwrap();
+    file.set_len(&fs, 3).unwrap();
+    fs.rename_file("/file.txt", "/dir/file.txt").unwrap();
+
+    // The attribute is kept when the entry moves.
+    let entry = fs.search_entry("/dir/file.txt").unwrap();
+    assert!(entry.attribute.is_read_only());
+    assert_eq!(entry.file_size, 3);
+
+    fs.delete_file("/dir/file.txt").unwrap();
+    fs.rename_directory("/dir", "/other").unwrap();
+    fs.delete_directory("/other").unwrap();
+
+    fs.set_ignore_read_only(false);
+    fs.create_file("/file.txt").unwrap();
+    let attribute = fs.search_entry("/file.txt").unwrap().attribute;
+    fs.set_attributes("/file.txt", attribute.set(Attributes::READ_ONLY))
+        .unwrap();
+    assert_access_denied(fs.delete_file("/file.txt"));
+}
+
+#[test]
+fn read_only_after_open() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    fs.create_file("/file.txt").unwrap();
+    let mut file = fs.open_file("/file.txt").unwrap();
+    file.write(&fs, 0, b"read only", true).unwrap();
+
+    // The file was opened before the attribute was set.
+    let attribute = fs.search_entry("/file.txt").unwrap().attribute;
+    fs.set_attributes("/file.txt", attribute.set(Attributes::READ_ONLY))
+        .unwrap();
+    assert_access_denied(file.write(&fs, 0, b"modified", false));
+    assert_access_denied(file.set_len(&fs, 0));
+    assert_access_denied(file.preallocate(&fs, 4096));
+
+    fs.set_attributes("/file.txt", attribute.clear(Attributes::READ_ONLY))
+        .unwrap();
+    file.write(&fs, 0, b"modified", false).unwrap();
+    file.preallocate(&fs, 4096).unwrap();
+    file.set_len(&fs, 8).unwrap();
+}
@@ -0,0 +1,118 @@
+//! Check that a filesystem mounted read only never writes to the storage device.
+
+use libfat::attribute::Attributes;
+use libfat::{FatError, FatFsType};
+use storage_device::{StorageDevice, StorageDeviceError, StorageDeviceResult};
+
+mod common;
+
+use common::{format_image, MemoryDevice};
+
+/// The size of the test images.
+const IMAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// A storage device that can only be read.
+struct WriteProtectedDevice<'a>(&'a [u8]);
+
+impl<'a> StorageDevice for WriteProtectedDevice<'a> {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> StorageDeviceResult<()> {
+        let offset = offset as usize;
+        let data = self
+            .0
+            .get(offset..offset + buf.len())
+            .ok_or(StorageDeviceError::ReadError)?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn write(&mut self, _offset: u64, _buf: &[u8]) -> StorageDeviceResult<()> {
+        unimplemented!("write to a write protected device")
+    }
+
+    fn len(&mut self) -> StorageDeviceResult<u64> {
+        Ok(self.0.len() as u64)
+    }
+}
+
+/// Check that the result of an operation is a `ReadOnlyFileSystem` error.
+fn assert_read_only<T>(result: Result<T, FatError>) {
+    assert!(matches!(result, Err(FatError::ReadOnlyFileSystem)));
+}
+
+/// Format an image with a few entries, then mount it read only over a write protected device.
+fn check_read_only_mount(fat_type: FatFsType) {
+    let mut image = format_image(fat_type, IMAGE_SIZE);
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        fs.create_directory("/dir").unwrap();
+        fs.create_file("/dir/file.txt").unwrap();
+
+        let mut file = fs.open_file("/dir/file.txt").unwrap();
+        file.write(&fs, 0, b"untrusted", true).unwrap();
+    }
+
+    let mut fs = libfat::get_raw_partition(WriteProtectedDevice(&image)).unwrap();
+    assert!(!fs.is_read_only());
+    fs.set_read_only(true);
+    assert!(fs.is_read_only());
+
+    // Reading is still possible.
+    let mut file = fs.open_file("/dir/file.txt").unwrap();
+    let mut buf = [0x0u8; 9];
+    assert_eq!(file.read(&fs, 0, &mut buf).unwrap(), 9);
+    assert_eq!(&buf, b"untrusted");
+    fs.open_directory("/dir")
+        .unwrap()
+        .find_entry("file.txt")
+        .unwrap();
+    fs.volume_info();
+
+    // Every modification fails, even the ones that would fail for another reason.
+    assert_read_only(fs.create_file("/new.txt"));
+    assert_read_only(fs.create_file("/dir/file.txt"));
+    assert_read_only(fs.create_directory("/new"));
+    assert_read_only(fs.delete_file("/dir/file.txt"));
+    assert_read_only(fs.delete_file("/missing.txt"));
+    assert_read_only(fs.delete_directory("/dir"));
+    assert_read_only(fs.rename_file("/dir/file.txt", "/moved.txt"));
+    assert_read_only(fs.rename_directory("/dir", "/other"));
+    assert_read_only(file.write(&fs, 0, b"modified", false));
+    assert_read_only(file.write(&fs, 9, b"appended", true));
+    assert_read_only(file.set_len(&fs, 0));
+    assert_read_only(fs.set_times("/dir/file.txt", None, None, Some(1_600_000_000)));
+    assert_read_only(fs.set_attributes("/dir", Attributes::new(Attributes::DIRECTORY)));
+    assert_read_only(fs.set_volume_label("LABEL"));
+
+    // The read only attribute override doesn't bypass a read only mount.
+    fs.set_ignore_read_only(true);
+    assert_read_only(file.set_len(&fs, 0));
+}
+
+#[test]
+fn read_only_mount_fat12() {
+    check_read_only_mount(FatFsType::Fat12);
+}
+
+#[test]
+fn read_only_mount_fat16() {
+    check_read_only_mount(FatFsType::Fat16);
+}
+
+#[test]
+fn read_only_mount_fat32() {
+    check_read_only_mount(FatFsType::Fat32);
+}
+
+#[test]
+fn remount_read_write() {
+    let mut image = format_image(FatFsType::Fat32, IMAGE_SIZE);
+
+    let mut fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    fs.set_read_only(true);
+    assert_read_only(fs.create_file("/file.txt"));
+
+    fs.set_read_only(false);
+    fs.create_file("/file.txt").unwrap();
+    fs.search_entry("/file.txt").unwrap();
+}
@@ -0,0 +1,130 @@
+//! Format, write and read back filesystems using every supported logical block size.
+
+use libfat::{FatFsType, FileSystemIterator, FormatOptions};
+
+mod common;
+
+use common::MemoryDevice;
+
+/// Generate the content of the test file of the given index.
+fn file_content(index: usize) -> Vec<u8> {
+    let len = index * 397 % 9000;
+    (0..len)
+        .map(|i| ((i * 31 + index * 7) % 251) as u8)
+        .collect()
+}
+
+/// Generate the path of the test file of the given index.
+fn file_path(index: usize) -> String {
+    format!("/dir/sub/a_long_file_name_number_{}.txt", index)
+}
+
+/// Format an image, fill it with files and check everything can be read back after a remount.
+fn check_block_size(bytes_per_block: u16, fat_type: FatFsType, image_size: usize) {
+    const FILE_COUNT: usize = 70;
+
+    let mut image = vec![0x0u8; image_size];
+
+    let options = FormatOptions::new()
+        .bytes_per_block(bytes_per_block)
+        .fat_type(fat_type);
+    libfat::format_partition_with_options(MemoryDevice(&mut image), &options, 0, image_size as u64)
+        .unwrap();
+
+    assert_eq!(u16::from_le_bytes([image[11], image[12]]), bytes_per_block);
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        assert_eq!(fs.get_type(), fat_type);
+
+        fs.create_directory("/dir").unwrap();
+        fs.create_directory("/dir/sub").unwrap();
+
+        // Enough entries to span multiple blocks and clusters of the directory.
+        for index in 0..FILE_COUNT {
+            fs.create_file(&file_path(index)).unwrap();
+            let mut file = fs.open_file(&file_path(index)).unwrap();
+            file.write(&fs, 0, &file_content(index), true).unwrap();
+        }
+
+        // Overwrite a file in the middle, crossing block and cluster boundaries.
+        let mut file = fs.open_file(&file_path(50)).unwrap();
+        file.write(&fs, 777, &[0xAA; 5000], true).unwrap();
+
+        for index in 0..40 {
+            fs.create_file(&format!("/root_entry_{}", index)).unwrap();
+        }
+    }
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+    for index in 0..FILE_COUNT {
+        let mut expected = file_content(index);
+        if index == 50 {
+            expected.resize(777 + 5000, 0);
+            expected[777..].copy_from_slice(&[0xAA; 5000]);
+        }
+
+        let mut file = fs.open_file(&file_path(index)).unwrap();
+        assert_eq!(file.file_info.file_size as usize, expected.len());
+
+        let mut buf = vec![0x0u8; expected.len() + 10];
+        let read_size = file.read(&fs, 0, &mut buf).unwrap() as usize;
+        assert_eq!(read_size, expected.len());
+        assert!(buf[..read_size] == expected[..]);
+
+        // Unaligned read in the middle of the file.
+        if expected.len() > 100 {
+            let offset = (index * 1237) % (expected.len() - 50);
+            let mut buf = vec![0x0u8; 3000];
+            let read_size = file.read(&fs, offset as u64, &mut buf).unwrap() as usize;
+            assert_eq!(read_size, core::cmp::min(3000, expected.len() - offset));
+            assert!(buf[..read_size] == expected[offset..offset + read_size]);
+        }
+    }
+
+    let entry_count = fs
+        .open_directory("/dir/sub")
+        .unwrap()
+        .iter()
+        .to_iterator(&fs)
+        .count();
+    assert_eq!(entry_count, FILE_COUNT + 2);
+
+    for index in 0..40 {
+        fs.open_file(&format!("/root_entry_{}", index)).unwrap();
+    }
+
+    for index in 0..FILE_COUNT {
+        fs.delete_file(&file_path(index)).unwrap();
+    }
+    fs.delete_directory("/dir/sub").unwrap();
+}
+
+#[test]
+fn block_size_512() {
+    check_block_size(512, FatFsType::Fat12, 4 * 1024 * 1024);
+    check_block_size(512, FatFsType::Fat16, 32 * 1024 * 1024);
+    check_block_size(512, FatFsType::Fat32, 64 * 1024 * 1024);
+}
+
+#[test]
+fn block_size_1024() {
+    check_block_size(1024, FatFsType::Fat12, 4 * 1024 * 1024);
+    check_block_size(1024, FatFsType::Fat16, 32 * 1024 * 1024);
+    check_block_size(1024, FatFsType::Fat32, 128 * 1024 * 1024);
+}
+
+#[test]
+fn block_size_2048() {
+    check_block_size(2048, FatFsType::Fat12, 4 * 1024 * 1024);
+    check_block_size(2048, FatFsType::Fat16, 64 * 1024 * 1024);
+    check_block_size(2048, FatFsType::Fat32, 256 * 1024 * 1024);
+}
+
+#[test]
+fn block_size_4096() {
+    check_block_size(4096, FatFsType::Fat12, 8 * 1024 * 1024);
+    check_block_size(4096, FatFsType::Fat16, 128 * 1024 * 1024);
+    check_block_size(4096, FatFsType::Fat32, 512 * 1024 * 1024);
+}
@@ -0,0 +1,223 @@
+//! Check that accessing a file far from its start doesn't follow its whole cluster chain.
+
+use libfat::{FatFsType, FileExtent};
+use storage_device::StorageDevice;
+
+mod common;
+
+use common::{format_image, CountingDevice, DeviceOperations, MemoryDevice};
+
+/// The size of the test image.
+const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+
+/// The count of clusters of the test file.
+const CLUSTER_COUNT: usize = 200;
+
+/// The content of the cluster at the given index of the test file.
+fn cluster_content(index: usize, cluster_size: usize) -> Vec<u8> {
+    (0..cluster_size)
+        .map(|value| (value + index * 7) as u8)
+        .collect()
+}
+
+/// Format a FAT16 image containing a fragmented file "/file.bin" with a run every 3 clusters.
+fn fragmented_image() -> Vec<u8> {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let cluster_size = fs.volume_info().cluster_size() as usize;
+
+        fs.create_file("/file.bin").unwrap();
+        fs.create_file("/other.bin").unwrap();
+        let mut file = fs.open_file("/file.bin").unwrap();
+        let mut other = fs.open_file("/other.bin").unwrap();
+
+        for index in 0..CLUSTER_COUNT {
+            let offset = (index * cluster_size) as u64;
+            file.write(&fs, offset, &cluster_content(index, cluster_size), true)
+                .unwrap();
+
+            if index % 3 == 2 {
+                let other_offset = u64::from(other.file_info.file_size);
+                other.write(&fs, other_offset, &[0x42; 10], true).unwrap();
+            }
+        }
+    }
+
+    image
+}
+
+/// Read the cluster at the given index of the file and check its content.
+fn check_cluster<S: StorageDevice>(
+    fs: &libfat::filesystem::FatFileSystem<S>,
+    file: &mut libfat::directory::File,
+    index: usize,
+) {
+    let cluster_size = fs.volume_info().cluster_size() as usize;
+    let mut content = vec![0x0u8; cluster_size];
+
+    file.read(fs, (index * cluster_size) as u64, &mut content)
+        .unwrap();
+    assert!(content == cluster_content(index, cluster_size), "{}", index);
+}
+
+#[test]
+fn sequential_access() {
+    let mut image = fragmented_image();
+    let operations = DeviceOperations::default();
+
+    let fs = libfat::get_raw_partition(CountingDevice {
+        inner: MemoryDevice(&mut image),
+        operations: &operations,
+    })
+    .unwrap();
+    let mut file = fs.open_file("/file.bin").unwrap();
+
+    for index in 0..CLUSTER_COUNT {
+        let read_count = operations.read_count();
+        check_cluster(&fs, &mut file, index);
+
+        // The data and at most one FAT entry.
+        assert!(operations.read_count() - read_count <= 2, "{}", index);
+    }
+
+    // Going back to a previous cluster follows the chain from the start again.
+    check_cluster(&fs, &mut file, 1);
+    let read_count = operations.read_count();
+    check_cluster(&fs, &mut file, 2);
+    assert!(operations.read_count() - read_count <= 2);
+}
+
+#[test]
+fn random_access_with_extent_map() {
+    let mut image = fragmented_image();
+    let operations = DeviceOperations::default();
+    let mut extents = vec![FileExtent::EMPTY; CLUSTER_COUNT];
+    let mut small_extents = [FileExtent::EMPTY; 4];
+
+    let fs = libfat::get_raw_partition(CountingDevice {
+        inner: MemoryDevice(&mut image),
+        operations: &operations,
+    })
+    .unwrap();
+    let mut file = fs.open_file("/file.bin").unwrap();
+    assert!(file.set_extent_map(&mut extents).is_none());
+
+    check_cluster(&fs, &mut file, CLUSTER_COUNT - 1);
+
+    // The whole chain is now known, any cluster is read without reading the FAT.
+    for index in (0..CLUSTER_COUNT).rev().step_by(7) {
+        let read_count = operations.read_count();
+        check_cluster(&fs, &mut file, index);
+        assert_eq!(operations.read_count() - read_count, 1, "{}", index);
+    }
+
+    // Replacing the extent map gives back the previous memory.
+    let previous_map = file.set_extent_map(&mut small_extents);
+    assert_eq!(previous_map.unwrap().len(), CLUSTER_COUNT);
+
+    // A small extent map only covers the start of the file.
+    for index in (0..CLUSTER_COUNT).step_by(5) {
+        check_cluster(&fs, &mut file, index);
+    }
+    for index in (0..CLUSTER_COUNT).rev().step_by(3) {
+        check_cluster(&fs, &mut file, index);
+    }
+}
+
+#[test]
+fn modified_chain() {
+    let mut image = fragmented_image();
+    let mut extents = [FileExtent::EMPTY; 16];
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let cluster_size = fs.volume_info().cluster_size() as usize;
+    let mut file = fs.open_file("/file.bin").unwrap();
+    file.set_extent_map(&mut extents);
+
+    check_cluster(&fs, &mut file, CLUSTER_COUNT - 1);
+
+    // Truncating and extending the file again changes the end of the chain.
+    file.set_len(&fs, (cluster_size * 10) as u64).unwrap();
+    fs.create_file("/filler.bin").unwrap();
+    let mut filler = fs.open_file("/filler.bin").unwrap();
+    filler
+        .write(&fs, 0, &vec![0x0; cluster_size * 3], true)
+        .unwrap();
+
+    for index in 10..20 {
+        let offset = (index * cluster_size) as u64;
+        file.write(&fs, offset, &cluster_content(index, cluster_size), true)
+            .unwrap();
+    }
+
+    for index in (0..20).rev() {
+        check_cluster(&fs, &mut file, index);
+    }
+
+    // Truncating to zero changes the start of the chain.
+    file.set_len(&fs, 0).unwrap();
+    filler
+        .write(&fs, 0, &vec![0x0; cluster_size * 20], true)
+        .unwrap();
+
+    for index in 0..5 {
+        let offset = (index * cluster_size) as u64;
+        file.write(&fs, offset, &cluster_content(index, cluster_size), true)
+            .unwrap();
+    }
+
+    let mut reopened_file = fs.open_file("/file.bin").unwrap();
+    for index in (0..5).rev() {
+        check_cluster(&fs, &mut file, index);
+        check_cluster(&fs, &mut reopened_file, index);
+    }
+}
+
+#[test]
+fn chain_modified_by_another_instance() {
+    let mut image = fragmented_image();
+    let mut extents = vec![FileExtent::EMPTY; CLUSTER_COUNT];
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let cluster_size = fs.volume_info().cluster_size() as usize;
+    let mut file = fs.open_file("/file.bin").unwrap();
+    let mut other = fs.open_file("/file.bin").unwrap();
+    file.set_extent_map(&mut extents);
+
+    check_cluster(&fs, &mut file, CLUSTER_COUNT - 1);
+
+    // The freed clusters are given to another file.
+    other.set_len(&fs, (cluster_size * 2) as u64).unwrap();
+    fs.create_file("/filler.bin").unwrap();
+    let mut filler = fs.open_file("/filler.bin").unwrap();
+    let filler_content = vec![0x55; cluster_size * CLUSTER_COUNT];
+    filler.write(&fs, 0, &filler_content, true).unwrap();
+
+    // The first instance sees the new size and extends the chain again.
+    let mut content = vec![0x0u8; cluster_size];
+    assert_eq!(
+        file.read(&fs, (cluster_size * 5) as u64, &mut content)
+            .unwrap(),
+        0
+    );
+    file.write(
+        &fs,
+        (cluster_size * 5) as u64,
+        &cluster_content(5, cluster_size),
+        true,
+    )
+    .unwrap();
+
+    check_cluster(&fs, &mut file, 1);
+    check_cluster(&fs, &mut file, 5);
+
+    let mut content = vec![0x0u8; filler_content.len()];
+    filler.read(&fs, 0, &mut content).unwrap();
+    assert!(content == filler_content);
+
+    // Truncating to zero changes the start of the chain.
+    other.set_len(&fs, 0).unwrap();
+    assert_eq!(file.read(&fs, 0, &mut content).unwrap(), 0);
+}
@@ -0,0 +1,102 @@
+//! Check the storage device over the streams of the standard library.
+#![cfg(feature = "std")]
+
+use libfat::{FatError, FatFsType, FormatOptions, IoStorageDevice, OpenOptions};
+use std::io::{Cursor, Read, Write};
+
+/// The size of the test images.
+const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+
+#[test]
+fn memory_stream() {
+    let mut image = Cursor::new(vec![0x0u8; IMAGE_SIZE]);
+
+    libfat::format_partition_with_options(
+        IoStorageDevice::new(&mut image),
+        &FormatOptions::new().fat_type(FatFsType::Fat16),
+        0,
+        IMAGE_SIZE as u64,
+    )
+    .unwrap();
+
+    let fs = libfat::get_raw_partition(IoStorageDevice::new(image)).unwrap();
+    let options = OpenOptions::new().write(true).create(true);
+    let mut file = fs.open_file_with_options("/hello.txt", &options).unwrap();
+    file.write_all(b"Hello World").unwrap();
+
+    let mut file = fs
+        .open_file_with_options("/hello.txt", &OpenOptions::new().read(true))
+        .unwrap();
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "Hello World");
+}
+
+#[test]
+fn image_file() {
+    let path = std::env::temp_dir().join(format!("libfat-std-device-{}.img", std::process::id()));
+
+    {
+        let image = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        image.set_len(IMAGE_SIZE as u64).unwrap();
+
+        libfat::format_partition_with_options(
+            IoStorageDevice::new(image),
+            &FormatOptions::new().fat_type(FatFsType::Fat16),
+            0,
+            IMAGE_SIZE as u64,
+        )
+        .unwrap();
+    }
+
+    {
+        let image = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let fs = libfat::get_raw_partition(IoStorageDevice::new(image)).unwrap();
+
+        fs.create_directory("/dir").unwrap();
+        let options = OpenOptions::new().write(true).create(true);
+        let mut file = fs
+            .open_file_with_options("/dir/data.bin", &options)
+            .unwrap();
+        std::io::copy(&mut Cursor::new(vec![0x42u8; 100_000]), &mut file).unwrap();
+    }
+
+    let image = std::fs::File::open(&path).unwrap();
+    let fs = libfat::get_raw_partition(IoStorageDevice::new(image)).unwrap();
+    assert_eq!(fs.search_entry("/dir/data.bin").unwrap().file_size, 100_000);
+
+    // The image file was opened read only.
+    let error = fs.create_file("/file.txt").unwrap_err();
+    assert!(matches!(error, FatError::WriteFailed));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn errors() {
+    assert_eq!(FatError::NotFound.to_string(), "not found");
+    assert_eq!(
+        FatError::Custom {
+            name: "Custom error"
+        }
+        .to_string(),
+        "Custom error"
+    );
+
+    let error = std::io::Error::from(FatError::FileExists);
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(error.to_string(), "file exists");
+
+    let source: Box<dyn std::error::Error> = Box::new(FatError::ReadOnlyFileSystem);
+    assert_eq!(source.to_string(), "read only filesystem");
+}
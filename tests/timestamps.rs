@@ -0,0 +1,144 @@
+//! Check that directory entries are stamped using the clock of the filesystem.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+use libfat::{FatDateTime, FatFsType, TimeProvider};
+
+mod common;
+
+use common::{format_image, MemoryDevice};
+
+/// The size of the test image.
+const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+
+/// A clock where only the minutes can be changed.
+struct TestClock {
+    /// The current minutes.
+    minutes: AtomicU8,
+}
+
+impl TimeProvider for TestClock {
+    fn now(&self) -> FatDateTime {
+        FatDateTime::new(2021, 6, 15, 12, self.minutes.load(Ordering::SeqCst), 31, 0).unwrap()
+    }
+}
+
+/// Convert the test clock time at the given minutes to a UNIX timestamp.
+fn timestamp(minutes: u8) -> u64 {
+    // The seconds of the modification time are rounded down to a multiple of two.
+    FatDateTime::new(2021, 6, 15, 12, minutes, 30, 0)
+        .unwrap()
+        .to_unix_time()
+}
+
+/// UNIX timestamp of the day of the test clock.
+fn date_timestamp() -> u64 {
+    FatDateTime::new(2021, 6, 15, 0, 0, 0, 0)
+        .unwrap()
+        .to_unix_time()
+}
+
+#[test]
+fn stamp_entries() {
+    // The clock only has to outlive the filesystem.
+    let clock = TestClock {
+        minutes: AtomicU8::new(0),
+    };
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+
+    {
+        let mut fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+        // Without a clock, nothing is stamped.
+        fs.create_file("/unstamped.txt").unwrap();
+        assert_eq!(
+            fs.search_entry("/unstamped.txt")
+                .unwrap()
+                .creation_timestamp,
+            0
+        );
+
+        fs.set_time_provider(&clock);
+
+        clock.minutes.store(1, Ordering::SeqCst);
+        fs.create_file("/file.txt").unwrap();
+        fs.create_directory("/dir").unwrap();
+
+        let entry = fs.search_entry("/file.txt").unwrap();
+        assert_eq!(entry.creation_timestamp, timestamp(1) + 1);
+        assert_eq!(entry.last_modification_timestamp, timestamp(1));
+        assert_eq!(entry.last_access_timestamp, date_timestamp());
+        assert_eq!(
+            fs.search_entry("/dir").unwrap().creation_timestamp,
+            timestamp(1) + 1
+        );
+
+        // Write extending the file.
+        clock.minutes.store(2, Ordering::SeqCst);
+        let mut file = fs.open_file("/file.txt").unwrap();
+        file.write(&fs, 0, &[0x42; 100], true).unwrap();
+        assert_eq!(file.file_info.last_modification_timestamp, timestamp(2));
+
+        // Write in place.
+        clock.minutes.store(3, Ordering::SeqCst);
+        file.write(&fs, 10, &[0x43; 10], false).unwrap();
+        assert_eq!(file.file_info.last_modification_timestamp, timestamp(3));
+
+        // Truncate.
+        clock.minutes.store(4, Ordering::SeqCst);
+        file.set_len(&fs, 50).unwrap();
+        assert_eq!(file.file_info.last_modification_timestamp, timestamp(4));
+
+        // Move to another directory keeps the creation date.
+        clock.minutes.store(5, Ordering::SeqCst);
+        fs.rename_file("/file.txt", "/dir/moved.txt").unwrap();
+    }
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let entry = fs.search_entry("/dir/moved.txt").unwrap();
+    assert_eq!(entry.creation_timestamp, timestamp(1) + 1);
+    assert_eq!(entry.last_modification_timestamp, timestamp(5));
+    assert_eq!(entry.file_size, 50);
+}
+
+#[test]
+fn set_times() {
+    const FAT32_IMAGE_SIZE: usize = 64 * 1024 * 1024;
+    let mut image = format_image(FatFsType::Fat32, FAT32_IMAGE_SIZE);
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        fs.create_directory("/dir").unwrap();
+        fs.create_file("/dir/file.txt").unwrap();
+
+        // 2020-09-13 12:26:41, 2021-01-01 10:00:00 and 2022-02-28 23:59:59.
+        fs.set_times(
+            "/dir/file.txt",
+            Some(1_600_000_001),
+            Some(1_609_495_200),
+            Some(1_646_092_799),
+        )
+        .unwrap();
+        fs.set_times("/dir", None, None, Some(1_600_000_000))
+            .unwrap();
+
+        assert!(fs.set_times("/dir", Some(0), None, None).is_err());
+        assert!(fs
+            .set_times("/dir", None, None, Some(4_354_819_200))
+            .is_err());
+        assert!(fs.set_times("/", None, None, Some(1_600_000_000)).is_err());
+        assert!(fs
+            .set_times("/missing", None, None, Some(1_600_000_000))
+            .is_err());
+    }
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+
+    let entry = fs.search_entry("/dir/file.txt").unwrap();
+    assert_eq!(entry.creation_timestamp, 1_600_000_001);
+    assert_eq!(entry.last_access_timestamp, 1_609_459_200);
+    assert_eq!(entry.last_modification_timestamp, 1_646_092_798);
+
+    let entry = fs.search_entry("/dir").unwrap();
+    assert_eq!(entry.creation_timestamp, 0);
+    assert_eq!(entry.last_modification_timestamp, 1_600_000_000);
+}
@@ -0,0 +1,88 @@
+//! Check the volume information reported by a filesystem.
+
+use libfat::{FatFsType, FormatOptions};
+
+mod common;
+
+use common::MemoryDevice;
+
+#[test]
+fn volume_info_fat16() {
+    const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+
+    let options = FormatOptions::new()
+        .fat_type(FatFsType::Fat16)
+        .blocks_per_cluster(4)
+        .volume_id(0xCAFE_BABE)
+        .oem_name(*b"LIBFAT  ");
+    libfat::format_partition_with_options(MemoryDevice(&mut image), &options, 0, IMAGE_SIZE as u64)
+        .unwrap();
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let info = fs.volume_info();
+
+    assert_eq!(info.fat_type, FatFsType::Fat16);
+    assert_eq!(info.volume_id, Some(0xCAFE_BABE));
+    assert_eq!(&info.oem_name, b"LIBFAT  ");
+    assert_eq!(info.media_type, 0xF8);
+    assert_eq!(info.bytes_per_block, 512);
+    assert_eq!(info.blocks_per_cluster, 4);
+    assert_eq!(info.cluster_size(), 2048);
+    assert_eq!(info.total_blocks as usize, IMAGE_SIZE / 512);
+    assert_eq!(info.reserved_block_count, 1);
+    assert_eq!(info.fats_count, 2);
+    assert_eq!(info.root_dir_childs_count, 512);
+    assert_eq!(info.fs_info_block, None);
+    assert_eq!(info.backup_boot_record_block, None);
+
+    let data_blocks = info.total_blocks
+        - u32::from(info.reserved_block_count)
+        - u32::from(info.fats_count) * info.fat_size
+        - 32;
+    assert_eq!(info.total_clusters, data_blocks / 4);
+    assert_eq!(info.free_clusters, info.total_clusters);
+    assert_eq!(info.free_bytes(), info.total_bytes());
+
+    fs.create_file("/file.bin").unwrap();
+    let mut file = fs.open_file("/file.bin").unwrap();
+    file.write(&fs, 0, &[0x42; 5000], true).unwrap();
+
+    let info = fs.volume_info();
+    assert_eq!(info.free_clusters, info.total_clusters - 3);
+}
+
+#[test]
+fn volume_info_fat32() {
+    const IMAGE_SIZE: usize = 64 * 1024 * 1024;
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+
+    let options = FormatOptions::new().fat_type(FatFsType::Fat32);
+    libfat::format_partition_with_options(MemoryDevice(&mut image), &options, 0, IMAGE_SIZE as u64)
+        .unwrap();
+
+    let free_clusters = {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        let info = fs.volume_info();
+
+        assert_eq!(info.fat_type, FatFsType::Fat32);
+        assert_eq!(info.volume_id, Some(0));
+        assert_eq!(info.reserved_block_count, 32);
+        assert_eq!(info.root_dir_childs_count, 0);
+        assert_eq!(info.fs_info_block, Some(1));
+        assert_eq!(info.backup_boot_record_block, Some(6));
+
+        // The root directory uses one cluster.
+        assert_eq!(info.free_clusters, info.total_clusters - 1);
+        assert_eq!(info.next_free_cluster_hint, Some(2));
+
+        fs.create_directory("/dir").unwrap();
+        fs.volume_info().free_clusters
+    };
+
+    // The free cluster count is persisted in the FS Info structure.
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    let info = fs.volume_info();
+    assert_eq!(info.free_clusters, free_clusters);
+    assert_eq!(info.next_free_cluster_hint, Some(3));
+}
@@ -0,0 +1,113 @@
+//! Read and write the volume label through the boot record and the root directory.
+
+use libfat::{FatFsType, FileSystemIterator, FormatOptions};
+
+mod common;
+
+use common::{format_image, MemoryDevice};
+
+/// The size of the test images.
+const IMAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Offset of the volume label in the boot record of the given FAT type.
+fn boot_record_label_offset(fat_type: FatFsType) -> usize {
+    match fat_type {
+        FatFsType::Fat12 | FatFsType::Fat16 => 43,
+        FatFsType::Fat32 => 71,
+    }
+}
+
+/// Format an image, change its label and check both locations stay in sync.
+fn check_volume_label(fat_type: FatFsType) {
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+    let label_offset = boot_record_label_offset(fat_type);
+
+    let options = FormatOptions::new()
+        .fat_type(fat_type)
+        .volume_label(*b"FORMATTED  ");
+    libfat::format_partition_with_options(MemoryDevice(&mut image), &options, 0, IMAGE_SIZE as u64)
+        .unwrap();
+    assert_eq!(&image[label_offset..label_offset + 11], b"FORMATTED  ");
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        assert_eq!(fs.volume_label().unwrap().as_str(), "FORMATTED");
+
+        fs.create_file("/file.txt").unwrap();
+        fs.set_volume_label("my data").unwrap();
+        assert_eq!(fs.volume_label().unwrap().as_str(), "MY DATA");
+
+        // The volume entry isn't visible as a file.
+        let root_entries = fs
+            .open_directory("/")
+            .unwrap()
+            .iter()
+            .to_iterator(&fs)
+            .count();
+        assert_eq!(root_entries, 1);
+
+        assert!(fs.set_volume_label("TOO LONG LABEL").is_err());
+        assert!(fs.set_volume_label("BAD.NAME").is_err());
+        assert!(fs.set_volume_label(" LEADING").is_err());
+        assert!(fs.set_volume_label("ÉTÉ").is_err());
+        assert_eq!(fs.volume_label().unwrap().as_str(), "MY DATA");
+    }
+
+    assert_eq!(&image[label_offset..label_offset + 11], b"MY DATA    ");
+
+    {
+        let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+        assert_eq!(fs.volume_label().unwrap().as_str(), "MY DATA");
+
+        fs.set_volume_label("").unwrap();
+        assert_eq!(fs.volume_label().unwrap().as_str(), "");
+    }
+
+    assert_eq!(&image[label_offset..label_offset + 11], b"NO NAME    ");
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    assert_eq!(fs.volume_label().unwrap().as_str(), "");
+
+    // A deleted volume entry can be reused.
+    fs.set_volume_label("AGAIN").unwrap();
+    assert_eq!(fs.volume_label().unwrap().as_str(), "AGAIN");
+    fs.open_file("/file.txt").unwrap();
+}
+
+#[test]
+fn volume_label_fat12() {
+    check_volume_label(FatFsType::Fat12);
+}
+
+#[test]
+fn volume_label_fat16() {
+    check_volume_label(FatFsType::Fat16);
+}
+
+#[test]
+fn volume_label_fat32() {
+    check_volume_label(FatFsType::Fat32);
+}
+
+#[test]
+fn format_without_label() {
+    let mut image = format_image(FatFsType::Fat16, IMAGE_SIZE);
+    assert_eq!(&image[43..54], b"NO NAME    ");
+
+    let fs = libfat::get_raw_partition(MemoryDevice(&mut image)).unwrap();
+    assert_eq!(fs.volume_label().unwrap().as_str(), "");
+}
+
+#[test]
+fn format_with_invalid_label() {
+    let mut image = vec![0x0u8; IMAGE_SIZE];
+
+    let options = FormatOptions::new().volume_label(*b"lowercase  ");
+    assert!(libfat::format_partition_with_options(
+        MemoryDevice(&mut image),
+        &options,
+        0,
+        IMAGE_SIZE as u64
+    )
+    .is_err());
+}